@@ -1,4 +1,13 @@
-use crate::{commands::*, exit_with_message, structures::Sha};
+use crate::{
+    commands::*,
+    config::{ConfigChange, ConfigScope},
+    diff::{
+        parse_similarity, ConflictStyle, DiffAlgorithm, DiffOptions, DiffOutput, RenameDetection,
+    },
+    exit_with_message,
+    revision::{resolve_revision, CommitFilter, Sorting},
+    structures::{verify_objects_on_read, Sha},
+};
 use clap::{ArgGroup, Parser, Subcommand};
 use flexi_logger::{colored_detailed_format, Duplicate, Logger};
 use std::path::PathBuf;
@@ -12,6 +21,21 @@ fn halp_str() -> &'static str {
 enum Command {
     /// Add file contents to the index
     Add { pathspec: Vec<PathBuf> },
+    /// List, create, or delete branches
+    Branch {
+        /// Delete a branch. The branch must be fully merged into HEAD
+        #[clap(short = 'd', long = "delete", group = "action")]
+        delete: bool,
+        /// Delete a branch irrespective of its merged status
+        #[clap(short = 'D', group = "action")]
+        force_delete: bool,
+        /// Move/rename a branch, renaming the current branch when only the new name is given
+        #[clap(short = 'm', long = "move", group = "action")]
+        rename: bool,
+        /// The branch name, followed by the start point when creating or the new name when renaming
+        #[clap(max_values = 2)]
+        names: Vec<String>,
+    },
     #[clap(group(
         ArgGroup::new("mode")
             .required(true)
@@ -24,21 +48,143 @@ enum Command {
         /// show object's type
         #[clap(short = 't', group = "mode")]
         type_print: bool,
+        #[clap(parse(try_from_str = resolve_revision))]
         sha: Sha,
     },
+    /// Switch branches or restore working tree files
+    Checkout {
+        /// The branch to switch to, or a commit to detach HEAD at
+        target: String,
+    },
     /// Record changes to the repository
     Commit {
         #[clap(short = 'm')]
         messages: Option<Vec<String>>,
+        /// Override the commit author. Specify an explicit author using the standard A U Thor <author@example.com> format
+        #[clap(long)]
+        author: Option<String>,
     },
     /// Create a new commit object
     CommitTree {
+        #[clap(parse(try_from_str = resolve_revision))]
         sha: Sha,
         #[clap(short = 'm')]
         message: Option<Vec<String>>,
-        #[clap(short = 'p')]
+        #[clap(short = 'p', parse(try_from_str = resolve_revision))]
         parent: Option<Vec<Sha>>,
     },
+    #[clap(group(ArgGroup::new("action")), group(ArgGroup::new("location")))]
+    /// Get and set repository or global options
+    Config {
+        /// Get the value for a given key. The default when only a name is given
+        #[clap(long, group = "action")]
+        get: bool,
+        /// Get every value for a key that is set more than once
+        #[clap(long, group = "action")]
+        get_all: bool,
+        /// Print the names and values of all keys matching the regex
+        #[clap(long, group = "action")]
+        get_regexp: bool,
+        /// Add a new value to the key without altering any existing ones
+        #[clap(long, group = "action")]
+        add: bool,
+        /// Replace every value of the key with the new one
+        #[clap(long, group = "action")]
+        replace_all: bool,
+        /// Remove the value of the key
+        #[clap(long, group = "action")]
+        unset: bool,
+        /// Remove every value of the key
+        #[clap(long, group = "action")]
+        unset_all: bool,
+        /// List every variable set in the config files, along with its value
+        #[clap(short = 'l', long, group = "action")]
+        list: bool,
+        /// Use the system-wide config file
+        #[clap(long, group = "location")]
+        system: bool,
+        /// Use the user's config file, ~/.gitconfig
+        #[clap(long, group = "location")]
+        global: bool,
+        /// Use the repository's config file. The default when writing
+        #[clap(long, group = "location")]
+        local: bool,
+        /// Use the worktree's config file, which is the repository's unless extensions.worktreeConfig is set
+        #[clap(long, group = "location")]
+        worktree: bool,
+        /// Use the given config file
+        #[clap(short = 'f', long, group = "location")]
+        file: Option<PathBuf>,
+        /// Read and write values as booleans. Same as --type=bool
+        #[clap(long = "bool", group = "value_type")]
+        as_bool: bool,
+        /// Read and write values as numbers. Same as --type=int
+        #[clap(long = "int", group = "value_type")]
+        as_int: bool,
+        /// Check values are of the type and show them in its canonical form
+        #[clap(long = "type", arg_enum, group = "value_type")]
+        kind: Option<ConfigType>,
+        /// Show the scope each value comes from: system, global, local, worktree or command
+        #[clap(long)]
+        show_scope: bool,
+        /// Show the file each value comes from
+        #[clap(long)]
+        show_origin: bool,
+        /// The key, like user.name, or the regex for --get-regexp
+        name: Option<String>,
+        /// The value to set
+        value: Option<String>,
+    },
+    /// Show changes between the working tree, the index and commits
+    Diff {
+        /// Show the changes staged in the index relative to HEAD, or to the given commit
+        #[clap(long, alias = "staged")]
+        cached: bool,
+        /// Generate diffs with <n> lines of context
+        #[clap(short = 'U', long = "unified", default_value_t = 3)]
+        context: usize,
+        /// Generate a diff using the "patience diff" algorithm
+        #[clap(long, group = "algorithm")]
+        patience: bool,
+        /// Generate a diff using the "histogram diff" algorithm
+        #[clap(long, group = "algorithm")]
+        histogram: bool,
+        /// Spend extra time to make sure the smallest possible diff is produced
+        #[clap(long, group = "algorithm")]
+        minimal: bool,
+        /// Choose a diff algorithm. Defaults to diff.algorithm from the config, or myers
+        #[clap(long, arg_enum, group = "algorithm")]
+        diff_algorithm: Option<DiffAlgorithm>,
+        /// Detect renames, optionally only those at least <n> similar
+        #[clap(
+            short = 'M',
+            long = "find-renames",
+            min_values = 0,
+            require_equals = true,
+            default_missing_value = "50%",
+            parse(try_from_str = parse_similarity)
+        )]
+        find_renames: Option<u64>,
+        /// Detect copies as well as renames, optionally only those at least <n> similar
+        #[clap(
+            short = 'C',
+            long = "find-copies",
+            min_values = 0,
+            require_equals = true,
+            default_missing_value = "50%",
+            parse(try_from_str = parse_similarity)
+        )]
+        find_copies: Option<u64>,
+        /// Show only the names of changed files
+        #[clap(long, group = "output")]
+        name_only: bool,
+        /// Show only the names and status of changed files
+        #[clap(long, group = "output")]
+        name_status: bool,
+        /// Compare against this commit, or compare the first commit to the second, also given as A..B, or as A...B to compare B to the merge base of both
+        #[clap(max_values = 2)]
+        commits: Vec<String>,
+    },
     /// Compute object ID and optionally creates a blob from a file
     HashObject {
         /// The file to hash
@@ -51,16 +197,192 @@ enum Command {
     Init { destination: Option<String> },
     /// Shows the commit logs
     Log {
-        #[clap(arg_enum, long = "pretty")]
+        /// Pretty-print the commits as oneline, short, medium, full, fuller, reference, email, raw, format:<string> or tformat:<string>
+        #[clap(long = "pretty", alias = "format")]
         pretty: Option<LogFormat>,
+        /// Shorthand for --pretty=oneline with abbreviated commit names
+        #[clap(long, conflicts_with = "pretty")]
+        oneline: bool,
+        /// Draw a text-based graphical representation of the commit history on the left hand side of the output. Implies --topo-order unless --date-order is given
+        #[clap(long, conflicts_with = "reverse")]
+        graph: bool,
+        /// Show no parents before all of their children are shown, and avoid showing commits on multiple lines of history intermixed
+        #[clap(long, group = "order")]
+        topo_order: bool,
+        /// Show no parents before all of their children are shown, but otherwise show commits in commit timestamp order
+        #[clap(long, group = "order")]
+        date_order: bool,
+        /// Output the commits chosen to be shown in reverse order
+        #[clap(long)]
+        reverse: bool,
+        /// Limit the number of commits to output
+        #[clap(short = 'n', long)]
+        max_count: Option<usize>,
+        /// Skip number commits before starting to show the commit output
+        #[clap(long, default_value_t = 0)]
+        skip: usize,
+        /// Follow only the first parent commit upon seeing a merge commit
+        #[clap(long)]
+        first_parent: bool,
+        /// Limit the commits output to ones with author header lines that match the regular expression. With more than one, commits matching any of them are shown
+        #[clap(long, number_of_values = 1)]
+        author: Vec<String>,
+        /// Limit the commits output to ones with committer header lines that match the regular expression. With more than one, commits matching any of them are shown
+        #[clap(long, number_of_values = 1)]
+        committer: Vec<String>,
+        /// Limit the commits output to ones with a log message that matches the regular expression. With more than one, commits matching any of them are shown
+        #[clap(long, number_of_values = 1)]
+        grep: Vec<String>,
+        /// Match the regular expression limiting patterns without regard to letter case
+        #[clap(short = 'i', long)]
+        regexp_ignore_case: bool,
+        /// Limit the commits output to ones that match all given --grep, instead of ones that match at least one
+        #[clap(long)]
+        all_match: bool,
+        /// Limit the commits output to ones with a log message that do not match the pattern specified with --grep
+        #[clap(long)]
+        invert_grep: bool,
+        /// Show commits more recent than a specific date
+        #[clap(long, alias = "after")]
+        since: Option<String>,
+        /// Show commits older than a specific date
+        #[clap(long, alias = "before")]
+        until: Option<String>,
+        /// Look for differences that change the number of occurrences of the string in a file
+        #[clap(short = 'S', conflicts_with = "pickaxe-lines")]
+        pickaxe_string: Option<String>,
+        /// Look for differences whose added or removed lines match the regular expression
+        #[clap(short = 'G')]
+        pickaxe_lines: Option<String>,
+        /// When -S or -G finds a change, show all the changes in that changeset, not just the files that contain the change
+        #[clap(long)]
+        pickaxe_all: bool,
+        /// Show the changes each commit made as a patch
+        #[clap(short = 'p', short_alias = 'u', long, group = "output")]
+        patch: bool,
+        /// Show only the names of changed files
+        #[clap(long, group = "output")]
+        name_only: bool,
+        /// Show only the names and status of changed files
+        #[clap(long, group = "output")]
+        name_status: bool,
+        /// Show commits reachable from these, but not from ones given as ^<rev>, or as A..B and A...B ranges. Defaults to HEAD
+        revisions: Vec<String>,
+        /// Show only commits that change something at one of the paths
+        #[clap(last = true)]
+        paths: Vec<String>,
     },
     /// Show information about files in the index and the working tree
     LsFiles {
         #[clap(long)]
         stage: bool,
     },
+    /// Join two development histories together
+    Merge {
+        /// Use the given message for the merge commit. Multiple -m options are joined as separate paragraphs
+        #[clap(short = 'm')]
+        message: Option<Vec<String>>,
+        /// How conflicts are written out. Defaults to merge.conflictStyle from the config, or merge
+        #[clap(long, arg_enum)]
+        conflict: Option<ConflictStyle>,
+        /// The branch or commit to merge into the current branch
+        commit: String,
+    },
+    /// Find as good common ancestors as possible for a merge
+    MergeBase {
+        /// Output all merge bases for the commits, instead of just one
+        #[clap(short = 'a', long)]
+        all: bool,
+        /// Compute the best common ancestors of all supplied commits, in preparation for an n-way merge
+        #[clap(long, conflicts_with = "is-ancestor")]
+        octopus: bool,
+        /// Check if the first commit is an ancestor of the second, and exit with status 0 if true, or with status 1 if not
+        #[clap(long, conflicts_with = "all")]
+        is_ancestor: bool,
+        #[clap(required = true)]
+        commits: Vec<String>,
+    },
     /// Reads tree information into the index
-    ReadTree { sha: Sha },
+    ReadTree {
+        /// Perform a merge, not just a read. With three trees the paths that cannot be trivially resolved are left in stages 1, 2 and 3
+        #[clap(short = 'm')]
+        merge: bool,
+        /// Keep the current index contents, and read the contents of the named tree-ish under the directory at <prefix>
+        #[clap(long, conflicts_with = "merge")]
+        prefix: Option<String>,
+        #[clap(required = true, max_values = 3, parse(try_from_str = resolve_revision))]
+        shas: Vec<Sha>,
+    },
+    /// Pack unpacked objects in a repository
+    Repack {
+        /// Pack everything reachable into a single pack, instead of only the loose objects
+        #[clap(short = 'a')]
+        all: bool,
+        /// After packing, remove the loose objects and packs that are now redundant
+        #[clap(short = 'd')]
+        delete: bool,
+        /// How many of the most recent objects to try as delta bases for each object
+        #[clap(long, default_value_t = 10)]
+        window: usize,
+        /// The maximum length of a delta chain
+        #[clap(long, default_value_t = 50)]
+        depth: usize,
+    },
+    /// Pick out and massage parameters
+    RevParse {
+        /// Verify that exactly one parameter is given and that it names an object, then print its sha
+        #[clap(long)]
+        verify: bool,
+        /// Only meaningful with --verify. Exit with status 1 instead of printing an error when the first argument is not a valid object name
+        #[clap(short = 'q', long)]
+        quiet: bool,
+        /// Like --verify, but shorten the sha to a unique abbreviation at least <n> characters long, 7 by default
+        #[clap(
+            long,
+            value_name = "n",
+            min_values = 0,
+            require_equals = true,
+            default_missing_value = "7"
+        )]
+        short: Option<usize>,
+        /// Show every object whose name begins with the given prefix, which must be at least 4 characters long
+        #[clap(long, require_equals = true, value_name = "prefix")]
+        disambiguate: Option<String>,
+        /// The revisions to parse
+        revisions: Vec<String>,
+    },
+    /// Show the working tree status
+    Status {
+        /// Give the output in an easy-to-parse format for scripts
+        #[clap(
+            long,
+            arg_enum,
+            min_values = 0,
+            require_equals = true,
+            default_missing_value = "v1"
+        )]
+        porcelain: Option<PorcelainVersion>,
+    },
+    /// Create, list or delete a tag object
+    Tag {
+        /// Make an unsigned, annotated tag object
+        #[clap(short = 'a')]
+        annotate: bool,
+        /// Use the given tag message, implies -a. Multiple -m options are joined as separate paragraphs
+        #[clap(short = 'm')]
+        message: Option<Vec<String>>,
+        /// Replace an existing tag with the given name instead of failing
+        #[clap(short = 'f', long)]
+        force: bool,
+        /// Delete existing tags with the given names
+        #[clap(short = 'd', long, conflicts_with_all = &["annotate", "message", "list"])]
+        delete: bool,
+        /// List tags, only those matching the pattern if one is given
+        #[clap(short = 'l', long, conflicts_with_all = &["annotate", "message"])]
+        list: bool,
+        /// The tag name, followed by the object to tag when creating
+        names: Vec<String>,
+    },
     /// Register file contents in the working tree to the index
     UpdateIndex {
         /// If a specified file isn’t in the index already then it’s added. Default behaviour is to ignore new files.
@@ -75,6 +397,7 @@ enum Command {
     UpdateRef {
         #[clap(help = halp_str())]
         r#ref: String,
+        #[clap(parse(try_from_str = resolve_revision))]
         new_value: Sha,
     },
     /// Create a tree object from the current index
@@ -94,10 +417,49 @@ pub struct Args {
     command: Command,
     #[clap(short = 'd')]
     debug: bool,
+    /// Rehash every object read from the database and fail on one whose contents don't match its name
+    #[clap(long)]
+    verify_objects: bool,
+}
+
+/// Git takes the similarity for -M and -C stuck to the flag, as in -M90%, which clap would read
+/// as a cluster of short flags. Those are rewritten to the long form before parsing, for the
+/// commands that have them and only up to a `--`, after which they are paths
+fn expand_similarity_flags(mut args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut expanded: Vec<String> = args.next().into_iter().collect();
+    let mut subcommand = None;
+    for arg in args.by_ref() {
+        if arg == "--" {
+            expanded.push(arg);
+            break;
+        }
+        // the options before the command don't take values
+        if subcommand.is_none() && !arg.starts_with('-') {
+            subcommand = Some(arg.clone());
+        }
+        match subcommand.as_deref() {
+            Some("diff" | "log") => expanded.push(expand_similarity_flag(arg)),
+            _ => expanded.push(arg),
+        }
+    }
+    expanded.extend(args);
+    expanded
+}
+
+fn expand_similarity_flag(arg: String) -> String {
+    let long = match arg.get(..2) {
+        Some("-M") => "--find-renames",
+        Some("-C") => "--find-copies",
+        _ => return arg,
+    };
+    match arg[2..].starts_with(|c: char| c.is_ascii_digit()) {
+        true => format!("{}={}", long, &arg[2..]),
+        false => arg,
+    }
 }
 
 pub fn parse_args() -> Result<(), std::io::Error> {
-    let args = Args::parse();
+    let args = Args::parse_from(expand_similarity_flags(std::env::args()));
     let d_level = match args.debug {
         true => "debug",
         false => "error",
@@ -108,10 +470,30 @@ pub fn parse_args() -> Result<(), std::io::Error> {
         .format(colored_detailed_format)
         .start()
         .expect("Failed to start logger");
+    verify_objects_on_read(args.verify_objects);
     match &args.command {
         Command::Add { pathspec: files } => {
             update_index_add(files)?;
         }
+        Command::Branch {
+            delete,
+            force_delete,
+            rename,
+            names,
+        } => {
+            if *delete || *force_delete {
+                if names.is_empty() {
+                    exit_with_message("fatal: branch name required");
+                }
+                branch_delete(names, *force_delete)?;
+            } else if *rename {
+                branch_rename(names)?;
+            } else if let Some(name) = names.first() {
+                branch_create(name, &names.get(1).cloned())?;
+            } else {
+                branch_list()?;
+            }
+        }
         Command::CatFile {
             pretty,
             type_print,
@@ -124,15 +506,129 @@ pub fn parse_args() -> Result<(), std::io::Error> {
                 cat_file_print_type(sha)?;
             }
         }
-        Command::Commit { messages } => {
-            commit(messages)?;
+        Command::Checkout { target } => {
+            checkout(target)?;
+        }
+        Command::Commit { messages, author } => {
+            commit(messages, author)?;
         }
         Command::CommitTree {
             sha,
             message,
             parent,
         } => {
-            commit_tree(sha, message, parent)?;
+            commit_tree(sha, message, parent, None)?;
+        }
+        Command::Config {
+            get,
+            get_all,
+            get_regexp,
+            add,
+            replace_all,
+            unset,
+            unset_all,
+            list,
+            system,
+            global,
+            local,
+            worktree,
+            file,
+            as_bool,
+            as_int,
+            kind,
+            show_scope,
+            show_origin,
+            name,
+            value,
+        } => {
+            let files = match (file, system, global, local, worktree) {
+                (Some(path), ..) => ConfigFiles::File(path.clone()),
+                (_, true, ..) => ConfigFiles::Scope(ConfigScope::System),
+                (_, _, true, ..) => ConfigFiles::Scope(ConfigScope::Global),
+                (_, _, _, true, _) => ConfigFiles::Scope(ConfigScope::Local),
+                (_, _, _, _, true) => ConfigFiles::Scope(ConfigScope::Worktree),
+                _ => ConfigFiles::All,
+            };
+            let kind = match (as_bool, as_int) {
+                (true, _) => Some(ConfigType::Bool),
+                (_, true) => Some(ConfigType::Int),
+                _ => *kind,
+            };
+            let display = ConfigDisplay {
+                show_scope: *show_scope,
+                show_origin: *show_origin,
+                kind,
+            };
+            // only setting takes a value along with the name, and listing takes neither
+            let name_only = *get || *get_all || *get_regexp || *unset || *unset_all;
+            let expected = match (*list, name_only) {
+                (true, _) => 0,
+                (false, false) if value.is_some() || *add || *replace_all => 2,
+                _ => 1,
+            };
+            let given = name.iter().count() + value.iter().count();
+            if given != expected {
+                let msg = format!("error: wrong number of arguments, should be {}", expected);
+                exit_with_message(&msg);
+            }
+            let name = name.as_deref().unwrap_or_default();
+            if *list {
+                config_list(&files, display)?;
+            } else if *get_regexp {
+                config_get_regexp(name, &files, display)?;
+            } else if *unset || *unset_all {
+                let change = match unset_all {
+                    true => ConfigChange::UnsetAll,
+                    false => ConfigChange::Unset,
+                };
+                config_change(name, &files, change, kind)?;
+            } else if let Some(value) = value {
+                let change = match (add, replace_all) {
+                    (true, _) => ConfigChange::Add(value),
+                    (_, true) => ConfigChange::ReplaceAll(value),
+                    _ => ConfigChange::Set(value),
+                };
+                config_change(name, &files, change, kind)?;
+            } else {
+                config_get(name, &files, display, *get_all)?;
+            }
+        }
+        Command::Diff {
+            cached,
+            context,
+            patience,
+            histogram,
+            minimal,
+            diff_algorithm,
+            find_renames,
+            find_copies,
+            name_only,
+            name_status,
+            commits,
+        } => {
+            let algorithm = match (patience, histogram, minimal) {
+                (true, _, _) => Some(DiffAlgorithm::Patience),
+                (_, true, _) => Some(DiffAlgorithm::Histogram),
+                (_, _, true) => Some(DiffAlgorithm::Minimal),
+                _ => *diff_algorithm,
+            };
+            let renames = match (find_copies, find_renames) {
+                (Some(score), _) => RenameDetection::Copies(*score),
+                (None, Some(score)) => RenameDetection::Renames(*score),
+                (None, None) => RenameDetection::Off,
+            };
+            let output = match (name_only, name_status) {
+                (true, _) => DiffOutput::NameOnly,
+                (_, true) => DiffOutput::NameStatus,
+                _ => DiffOutput::Patch,
+            };
+            let options = DiffOptions {
+                context: *context,
+                algorithm: DiffAlgorithm::choose(algorithm),
+                renames,
+                output,
+            };
+            diff(*cached, &options, commits)?;
         }
         Command::HashObject { file, write } => {
             if *write {
@@ -147,16 +643,153 @@ pub fn parse_args() -> Result<(), std::io::Error> {
             };
             init(root_dir)?;
         }
-        Command::Log { pretty } => {
-            log(pretty)?;
+        Command::Log {
+            pretty,
+            oneline,
+            graph,
+            topo_order,
+            date_order,
+            reverse,
+            max_count,
+            skip,
+            first_parent,
+            author,
+            committer,
+            grep,
+            regexp_ignore_case,
+            all_match,
+            invert_grep,
+            since,
+            until,
+            pickaxe_string,
+            pickaxe_lines,
+            pickaxe_all,
+            patch,
+            name_only,
+            name_status,
+            revisions,
+            paths,
+        } => {
+            let sorting = match (topo_order, date_order, graph) {
+                (true, _, _) => Sorting::Topo,
+                (_, true, _) => Sorting::Date,
+                (_, _, true) => Sorting::Topo,
+                _ => Sorting::Walk,
+            };
+            let options = LogOptions {
+                sorting,
+                first_parent: *first_parent,
+                reverse: *reverse,
+                max_count: *max_count,
+                skip: *skip,
+                graph: *graph,
+                revisions: revisions.clone(),
+                filter: CommitFilter {
+                    authors: compile_patterns(author, *regexp_ignore_case),
+                    committers: compile_patterns(committer, *regexp_ignore_case),
+                    messages: compile_patterns(grep, *regexp_ignore_case),
+                    all_match: *all_match,
+                    invert_grep: *invert_grep,
+                    since: since.as_deref().map(parse_date_limit),
+                    until: until.as_deref().map(parse_date_limit),
+                    pickaxe: compile_pickaxe(pickaxe_string, pickaxe_lines, *regexp_ignore_case),
+                },
+                paths: paths.clone(),
+                diff: match (patch, name_only, name_status) {
+                    (true, _, _) => Some(DiffOutput::Patch),
+                    (_, true, _) => Some(DiffOutput::NameOnly),
+                    (_, _, true) => Some(DiffOutput::NameStatus),
+                    _ => None,
+                }
+                .map(|output| DiffOptions {
+                    context: 3,
+                    algorithm: DiffAlgorithm::choose(None),
+                    renames: RenameDetection::Off,
+                    output,
+                }),
+                pickaxe_all: *pickaxe_all,
+            };
+            log(pretty, *oneline, &options)?;
         }
         Command::LsFiles { stage } => {
             if *stage {
                 ls_files_staging()?;
             }
         }
-        Command::ReadTree { sha } => {
-            read_tree(sha)?;
+        Command::Merge {
+            message,
+            conflict,
+            commit,
+        } => {
+            merge(commit, message, ConflictStyle::choose(*conflict))?;
+        }
+        Command::MergeBase {
+            all,
+            octopus,
+            is_ancestor,
+            commits,
+        } => {
+            if *is_ancestor {
+                merge_base_is_ancestor(commits)?;
+            } else {
+                merge_base(commits, *all, *octopus)?;
+            }
+        }
+        Command::ReadTree {
+            merge,
+            prefix,
+            shas,
+        } => {
+            read_tree(shas, *merge, prefix)?;
+        }
+        Command::Repack {
+            all,
+            delete,
+            window,
+            depth,
+        } => {
+            repack(*all, *delete, *window, *depth)?;
+        }
+        Command::RevParse {
+            verify,
+            quiet,
+            short,
+            disambiguate,
+            revisions,
+        } => {
+            if let Some(prefix) = disambiguate {
+                rev_parse_disambiguate(prefix)?;
+            }
+            if !revisions.is_empty() || disambiguate.is_none() {
+                rev_parse(revisions, *verify, *quiet, *short)?;
+            }
+        }
+        Command::Status { porcelain } => {
+            status(porcelain)?;
+        }
+        Command::Tag {
+            annotate,
+            message,
+            force,
+            delete,
+            list,
+            names,
+        } => {
+            if *delete {
+                tag_delete(names)?;
+            } else if *list || names.is_empty() {
+                tag_list(&names.first().cloned())?;
+            } else if names.len() > 2 {
+                exit_with_message("fatal: too many arguments");
+            } else {
+                tag_create(
+                    &names[0],
+                    &names.get(1).cloned(),
+                    *annotate,
+                    message,
+                    *force,
+                )?;
+            }
         }
         Command::UpdateIndex { add, remove, files } => {
             if *add {
@@ -179,3 +812,38 @@ pub fn parse_args() -> Result<(), std::io::Error> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::expand_similarity_flags;
+
+    fn expand(args: &[&str]) -> Vec<String> {
+        expand_similarity_flags(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn similarity_flags_expand_only_for_diff_and_log_options() {
+        assert_eq!(
+            expand(&["flog", "-d", "log", "-M90%", "-C5", "-M", "--", "-M50"]),
+            [
+                "flog",
+                "-d",
+                "log",
+                "--find-renames=90%",
+                "--find-copies=5",
+                "-M",
+                "--",
+                "-M50"
+            ]
+        );
+        assert_eq!(
+            expand(&["flog", "diff", "HEAD", "-M1"]),
+            ["flog", "diff", "HEAD", "--find-renames=1"]
+        );
+        assert_eq!(
+            expand(&["flog", "commit", "-M1", "-C2"]),
+            ["flog", "commit", "-M1", "-C2"]
+        );
+        assert_eq!(expand(&["flog", "-M1"]), ["flog", "-M1"]);
+    }
+}
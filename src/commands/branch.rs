@@ -0,0 +1,303 @@
+use std::{fs, io, path::Path};
+
+use log::info;
+
+use crate::{
+    exit_with_message,
+    revision::{is_ancestor, try_resolve_revision},
+    structures::{peel_to_commit, RefFile},
+    utils::{get_current_branch, get_head_sha, is_valid_ref_name, set_head_to_branch},
+};
+
+/// Lists branches, marking the one HEAD is on
+pub fn branch_list() -> Result<(), io::Error> {
+    let current = get_current_branch()?;
+    if current == "HEAD" {
+        if let Some(sha) = get_head_sha()? {
+            println!("* (HEAD detached at {})", &sha.to_string()[..7]);
+        }
+    }
+    for reff in RefFile::list("refs/heads")? {
+        let marker = match reff.name() == &current {
+            true => '*',
+            false => ' ',
+        };
+        println!("{} {}", marker, reff.short_name());
+    }
+    Ok(())
+}
+
+/// Creates a branch at the start point, or at HEAD when there isn't one
+pub fn branch_create(name: &str, start: &Option<String>) -> Result<(), io::Error> {
+    check_branch_name(name);
+    let reff = format!("refs/heads/{}", name);
+    if branch_exists(&reff) {
+        let msg = format!("fatal: A branch named '{}' already exists.", name);
+        exit_with_message(&msg);
+    }
+    let sha = match start {
+        Some(start) => match try_resolve_revision(start)? {
+            Some(sha) => peel_to_commit(&sha)?,
+            None => {
+                let msg = format!("fatal: Not a valid object name: '{}'.", start);
+                exit_with_message(&msg);
+            }
+        },
+        None => match get_head_sha()? {
+            Some(sha) => sha,
+            None => {
+                let current = get_current_branch()?;
+                let msg = format!(
+                    "fatal: Not a valid object name: '{}'.",
+                    current.trim_start_matches("refs/heads/")
+                );
+                exit_with_message(&msg);
+            }
+        },
+    };
+    info!("Creating branch {} at {}", name, sha);
+    RefFile::new(&reff, &sha).write()?;
+    Ok(())
+}
+
+/// Deletes branches. Without force a branch has to be merged into HEAD first
+pub fn branch_delete(names: &[String], force: bool) -> Result<(), io::Error> {
+    let current = get_current_branch()?;
+    let head = get_head_sha()?;
+    for name in names.iter() {
+        let reff = format!("refs/heads/{}", name);
+        if reff == current {
+            let msg = format!(
+                "error: Cannot delete branch '{}' checked out at '{}'",
+                name,
+                fs::canonicalize(".")?.display()
+            );
+            exit_with_message(&msg);
+        }
+        if !branch_exists(&reff) {
+            let msg = format!("error: branch '{}' not found.", name);
+            exit_with_message(&msg);
+        }
+        let ref_file = RefFile::new_from_branch(&reff)?;
+        let merged = match &head {
+            Some(head) => is_ancestor(ref_file.sha(), head)?,
+            None => false,
+        };
+        if !force && !merged {
+            let msg = format!(
+                "error: The branch '{}' is not fully merged.\nIf you are sure you want to delete it, run 'flog branch -D {}'.",
+                name, name
+            );
+            exit_with_message(&msg);
+        }
+        ref_file.delete()?;
+        println!(
+            "Deleted branch {} (was {}).",
+            name,
+            &ref_file.sha().to_string()[..7]
+        );
+    }
+    Ok(())
+}
+
+/// Renames a branch, following it with HEAD if it is the current branch
+pub fn branch_rename(names: &[String]) -> Result<(), io::Error> {
+    let current = get_current_branch()?;
+    let (old_ref, new_name) = match names {
+        [new_name] => {
+            if current == "HEAD" {
+                exit_with_message("fatal: cannot rename the current branch while not on any.");
+            }
+            (current.clone(), new_name)
+        }
+        [old_name, new_name] => (format!("refs/heads/{}", old_name), new_name),
+        _ => exit_with_message("fatal: too many arguments for a rename operation"),
+    };
+    check_branch_name(new_name);
+    let new_ref = format!("refs/heads/{}", new_name);
+    if branch_exists(&new_ref) {
+        let msg = format!("fatal: A branch named '{}' already exists.", new_name);
+        exit_with_message(&msg);
+    }
+    let is_current = old_ref == current;
+    if branch_exists(&old_ref) {
+        let old = RefFile::new_from_branch(&old_ref)?;
+        RefFile::new(&new_ref, old.sha()).write()?;
+        old.delete()?;
+    } else if !is_current {
+        // an unborn current branch has no ref file yet, only HEAD needs to follow it
+        let msg = format!(
+            "error: refname {} not found\nfatal: Branch rename failed",
+            old_ref
+        );
+        exit_with_message(&msg);
+    }
+    if is_current {
+        set_head_to_branch(&new_ref)?;
+    }
+    Ok(())
+}
+
+fn branch_exists(reff: &str) -> bool {
+    Path::new(".re_flogged").join(reff).is_file()
+}
+
+fn check_branch_name(name: &str) {
+    if !is_valid_ref_name(name) {
+        let msg = format!("fatal: '{}' is not a valid branch name.", name);
+        exit_with_message(&msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{structures::Sha, test_repo::TestRepo};
+
+    /// Two commits on main, and one on `side` that main doesn't have
+    fn history(repo: &TestRepo) -> [Sha; 3] {
+        let first = repo.commit("first", &[], &[]);
+        let second = repo.commit("second", &[&first], &[]);
+        let side = repo.commit("side", &[&first], &[]);
+        repo.set_head(&second);
+        fs::write(".re_flogged/refs/heads/side", format!("{}\n", side)).unwrap();
+        [first, second, side]
+    }
+
+    fn branch_sha(name: &str) -> Option<String> {
+        let path = format!(".re_flogged/refs/heads/{}", name);
+        fs::read_to_string(path)
+            .ok()
+            .map(|sha| sha.trim().to_string())
+    }
+
+    #[test]
+    fn create_and_list() {
+        let repo = TestRepo::new("branch_create");
+        let [first, second, _] = history(&repo);
+        assert_eq!(repo.flog(&["branch", "topic"]), (0, String::new()));
+        assert_eq!(branch_sha("topic"), Some(second.to_string()));
+        assert_eq!(repo.flog(&["branch", "nested/old", "main~1"]).0, 0);
+        assert_eq!(branch_sha("nested/old"), Some(first.to_string()));
+        assert_eq!(
+            repo.flog(&["branch"]),
+            (0, String::from("* main\n  nested/old\n  side\n  topic\n"))
+        );
+
+        let (code, printed) = repo.flog(&["branch", "topic"]);
+        assert_eq!(
+            (code, printed.as_str()),
+            (1, "fatal: A branch named 'topic' already exists.\n")
+        );
+        let (code, printed) = repo.flog(&["branch", "a..b"]);
+        assert_eq!(
+            (code, printed.as_str()),
+            (1, "fatal: 'a..b' is not a valid branch name.\n")
+        );
+        let (code, printed) = repo.flog(&["branch", "other", "nope"]);
+        assert_eq!(
+            (code, printed.as_str()),
+            (1, "fatal: Not a valid object name: 'nope'.\n")
+        );
+        assert_eq!(branch_sha("other"), None);
+
+        fs::write(".re_flogged/HEAD", format!("{}\n", first)).unwrap();
+        let short = &first.to_string()[..7];
+        assert_eq!(
+            repo.flog(&["branch"]),
+            (
+                0,
+                format!(
+                    "* (HEAD detached at {})\n  main\n  nested/old\n  side\n  topic\n",
+                    short
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn delete_only_merged_unless_forced() {
+        let repo = TestRepo::new("branch_delete");
+        let [first, _, side] = history(&repo);
+        let (code, printed) = repo.flog(&["branch", "-d", "side"]);
+        assert_eq!(
+            (code, printed.as_str()),
+            (
+                1,
+                "error: The branch 'side' is not fully merged.\nIf you are sure you want to delete it, run 'flog branch -D side'.\n"
+            )
+        );
+        assert_eq!(branch_sha("side"), Some(side.to_string()));
+        assert_eq!(
+            repo.flog(&["branch", "-D", "side"]),
+            (
+                0,
+                format!("Deleted branch side (was {}).\n", &side.to_string()[..7])
+            )
+        );
+        assert_eq!(branch_sha("side"), None);
+
+        repo.flog(&["branch", "merged", &first.to_string()]);
+        assert_eq!(
+            repo.flog(&["branch", "-d", "merged"]),
+            (
+                0,
+                format!("Deleted branch merged (was {}).\n", &first.to_string()[..7])
+            )
+        );
+        let (code, printed) = repo.flog(&["branch", "-d", "missing"]);
+        assert_eq!(
+            (code, printed.as_str()),
+            (1, "error: branch 'missing' not found.\n")
+        );
+        let (code, printed) = repo.flog(&["branch", "-D", "main"]);
+        let here = fs::canonicalize(".").unwrap();
+        assert_eq!(
+            (code, printed),
+            (
+                1,
+                format!(
+                    "error: Cannot delete branch 'main' checked out at '{}'\n",
+                    here.display()
+                )
+            )
+        );
+        assert!(branch_sha("main").is_some());
+    }
+
+    #[test]
+    fn rename_moves_the_ref_and_head() {
+        let repo = TestRepo::new("branch_rename");
+        let [_, second, side] = history(&repo);
+        assert_eq!(repo.flog(&["branch", "-m", "side", "renamed"]).0, 0);
+        assert_eq!(branch_sha("side"), None);
+        assert_eq!(branch_sha("renamed"), Some(side.to_string()));
+
+        assert_eq!(repo.flog(&["branch", "-m", "trunk"]).0, 0);
+        assert_eq!(branch_sha("main"), None);
+        assert_eq!(branch_sha("trunk"), Some(second.to_string()));
+        assert_eq!(
+            fs::read_to_string(".re_flogged/HEAD").unwrap(),
+            "ref: refs/heads/trunk\n"
+        );
+        assert_eq!(
+            repo.flog(&["branch"]),
+            (0, String::from("  renamed\n* trunk\n"))
+        );
+
+        let (code, printed) = repo.flog(&["branch", "-m", "renamed", "trunk"]);
+        assert_eq!(
+            (code, printed.as_str()),
+            (1, "fatal: A branch named 'trunk' already exists.\n")
+        );
+        let (code, printed) = repo.flog(&["branch", "-m", "missing", "other"]);
+        assert_eq!(
+            (code, printed.as_str()),
+            (
+                1,
+                "error: refname refs/heads/missing not found\nfatal: Branch rename failed\n"
+            )
+        );
+    }
+}
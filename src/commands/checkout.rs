@@ -0,0 +1,327 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsStr,
+    fs::{self, File, Permissions},
+    io::{self, Write},
+    os::unix::{ffi::OsStrExt, fs::symlink, fs::PermissionsExt},
+    path::Path,
+};
+
+use log::{debug, info};
+
+use crate::{
+    exit_with_message,
+    revision::try_resolve_revision,
+    structures::{
+        load_blob_from_sha, load_commit_from_sha, peel_to_commit, IndexEntry, IndexFile, Sha,
+        TreeEntry,
+    },
+    utils::{
+        append_head_log, get_commit_tree_entries, get_current_branch, get_head_sha,
+        get_head_tree_entries, previous_checkout, set_head_detached, set_head_to_branch,
+    },
+};
+
+/// Switches the working tree, index and HEAD over to a branch or a commit. `-` and `@{-n}`
+/// go back to the branch or commit checked out before
+pub fn checkout(target: &str) -> Result<(), io::Error> {
+    let previous = match previous_checkout_number(target) {
+        Some(n) => previous_checkout(n)?,
+        None => None,
+    };
+    let target = previous.unwrap_or_else(|| target.to_string());
+    let target = target.as_str();
+    let branch_ref = format!("refs/heads/{}", target);
+    let is_branch = Path::new(".re_flogged").join(&branch_ref).is_file();
+    let current_branch = get_current_branch()?;
+    if is_branch && current_branch == branch_ref {
+        println!("Already on '{}'", target);
+        return Ok(());
+    }
+    let sha = match try_resolve_revision(target)? {
+        Some(sha) => peel_to_commit(&sha)?,
+        None => {
+            let msg = format!(
+                "error: pathspec '{}' did not match any file(s) known to flog",
+                target
+            );
+            exit_with_message(&msg);
+        }
+    };
+    info!("Checking out {}", sha);
+    let head = get_head_sha()?;
+    let commit = load_commit_from_sha(&sha)?;
+    let target_entries = get_commit_tree_entries(&sha)?;
+    let current_entries = get_head_tree_entries()?;
+    switch_worktree(
+        &current_entries,
+        &target_entries,
+        "checkout",
+        "switch branches",
+    )?;
+
+    let from = match (current_branch.strip_prefix("refs/heads/"), &head) {
+        (Some(branch), _) => branch.to_string(),
+        (None, Some(head)) => head.to_string(),
+        (None, None) => current_branch.clone(),
+    };
+    let message = format!("checkout: moving from {} to {}", from, target);
+    append_head_log(&head.unwrap_or_else(Sha::empty), &sha, &message)?;
+    if is_branch {
+        set_head_to_branch(&branch_ref)?;
+        println!("Switched to branch '{}'", target);
+    } else {
+        set_head_detached(&sha)?;
+        let subject = commit.first_paragraph();
+        let subject = subject.lines().next().unwrap_or_default().to_string();
+        println!("HEAD is now at {} {}", &sha.to_string()[..7], subject);
+    }
+    Ok(())
+}
+
+/// Which earlier checkout `-` or `@{-n}` asks for
+fn previous_checkout_number(target: &str) -> Option<usize> {
+    match target {
+        "-" => Some(1),
+        _ => target.strip_prefix("@{-")?.strip_suffix('}')?.parse().ok(),
+    }
+}
+
+/// Moves the working tree and index from one set of tracked files to another, keeping local
+/// changes to files the two sets agree on. The operation names it in any error messages
+pub fn switch_worktree(
+    current_entries: &BTreeMap<String, TreeEntry>,
+    target_entries: &BTreeMap<String, TreeEntry>,
+    operation: &str,
+    action: &str,
+) -> Result<(), io::Error> {
+    let index_file = IndexFile::new(0);
+    let dirty = find_local_changes(&index_file, current_entries);
+    let updated: BTreeSet<String> = current_entries
+        .keys()
+        .chain(target_entries.keys())
+        .filter(|path| current_entries.get(*path) != target_entries.get(*path))
+        .cloned()
+        .collect();
+    check_nothing_is_clobbered(&dirty, current_entries, &updated, operation, action);
+
+    for path in current_entries.keys() {
+        if !target_entries.contains_key(path) && !dirty.contains(path) {
+            debug!("Removing {}", path);
+            remove_file_and_empty_parents(Path::new(path))?;
+        }
+    }
+    let mut new_index = IndexFile::empty();
+    for (path, entry) in target_entries.iter() {
+        if dirty.contains(path) {
+            // local changes are carried over untouched
+            continue;
+        }
+        if updated.contains(path) || fs::symlink_metadata(path).is_err() {
+            write_entry_to_worktree(entry)?;
+        }
+        let mut index_entry = IndexEntry::new_from_tree_entry(entry, 0);
+        index_entry.refresh_stat(&fs::symlink_metadata(path)?);
+        new_index.add_entry(index_entry);
+    }
+    for entry in index_file.index_entries().iter() {
+        if dirty.contains(&entry.get_readable_file_name()) {
+            new_index.add_entry(entry.clone());
+        }
+    }
+    new_index.write()
+}
+
+/// Paths whose index or working tree content differs from the HEAD commit
+pub fn find_local_changes(
+    index_file: &IndexFile,
+    current_entries: &BTreeMap<String, TreeEntry>,
+) -> BTreeSet<String> {
+    let mut dirty = BTreeSet::new();
+    let mut staged_paths = BTreeSet::new();
+    for entry in index_file.index_entries().iter() {
+        let name = entry.get_readable_file_name();
+        let matches_head = match current_entries.get(&name) {
+            Some(head_entry) => {
+                head_entry.sha() == entry.file_sha() && head_entry.mode() == entry.mode()
+            }
+            None => false,
+        };
+        if entry.stage() != 0 || !matches_head || !entry.matches_worktree() {
+            dirty.insert(name.clone());
+        }
+        staged_paths.insert(name);
+    }
+    for path in current_entries.keys() {
+        if !staged_paths.contains(path) {
+            dirty.insert(path.clone()); // deletion staged
+        }
+    }
+    dirty
+}
+
+/// Aborts if updating the paths would overwrite local changes or untracked files
+pub fn check_nothing_is_clobbered(
+    dirty: &BTreeSet<String>,
+    current_entries: &BTreeMap<String, TreeEntry>,
+    updated: &BTreeSet<String>,
+    operation: &str,
+    action: &str,
+) {
+    let clobbered: Vec<&String> = dirty.intersection(updated).collect();
+    if !clobbered.is_empty() {
+        let files = clobbered.iter().fold(String::new(), |mut acc, path| {
+            acc.push_str(&format!("\t{}\n", path));
+            acc
+        });
+        let msg = format!(
+            "error: Your local changes to the following files would be overwritten by {}:\n{}Please commit your changes or stash them before you {}.\nAborting",
+            operation, files, action
+        );
+        exit_with_message(&msg);
+    }
+    let untracked: Vec<&String> = updated
+        .iter()
+        .filter(|path| {
+            !current_entries.contains_key(*path)
+                && !dirty.contains(*path)
+                && fs::symlink_metadata(path).is_ok()
+        })
+        .collect();
+    if !untracked.is_empty() {
+        let files = untracked.iter().fold(String::new(), |mut acc, path| {
+            acc.push_str(&format!("\t{}\n", path));
+            acc
+        });
+        let msg = format!(
+            "error: The following untracked working tree files would be overwritten by {}:\n{}Please move or remove them before you {}.\nAborting",
+            operation, files, action
+        );
+        exit_with_message(&msg);
+    }
+}
+
+/// Writes a blob out to its path with the mode recorded in the tree
+pub fn write_entry_to_worktree(entry: &TreeEntry) -> Result<(), io::Error> {
+    let path = Path::new(entry.path());
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    if fs::symlink_metadata(path).is_ok() {
+        fs::remove_file(path)?;
+    }
+    let blob = load_blob_from_sha(entry.sha())?;
+    if *entry.mode() == 0o120000 {
+        return symlink(OsStr::from_bytes(blob.data()), path);
+    }
+    let mut file = File::create(path)?;
+    file.write_all(blob.data())?;
+    let permissions = match *entry.mode() == 0o100755 {
+        true => 0o755,
+        false => 0o644,
+    };
+    fs::set_permissions(path, Permissions::from_mode(permissions))?;
+    Ok(())
+}
+
+/// Removes a tracked file along with any directories it leaves empty
+pub fn remove_file_and_empty_parents(path: &Path) -> Result<(), io::Error> {
+    if fs::symlink_metadata(path).is_ok() {
+        fs::remove_file(path)?;
+    }
+    for ancestor in path.ancestors().skip(1) {
+        if ancestor.as_os_str().is_empty() {
+            break;
+        }
+        // stops at the first directory that still has something in it
+        if fs::remove_dir(ancestor).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::test_repo::TestRepo;
+
+    /// A repository with `a` on main, and a branch `side` that changes `a` and adds `b/c`
+    fn two_branches(name: &str) -> TestRepo {
+        let repo = TestRepo::new(name);
+        repo.write_file("a", "one\n");
+        assert_eq!(repo.flog(&["add", "a"]).0, 0);
+        assert_eq!(repo.flog(&["commit", "-m", "first"]).0, 0);
+        assert_eq!(repo.flog(&["branch", "side"]).0, 0);
+        assert_eq!(repo.flog(&["checkout", "side"]).0, 0);
+        repo.write_file("a", "two\n");
+        repo.write_file("b/c", "three\n");
+        assert_eq!(repo.flog(&["add", "a", "b/c"]).0, 0);
+        assert_eq!(repo.flog(&["commit", "-m", "second"]).0, 0);
+        repo
+    }
+
+    #[test]
+    fn switching_branches_updates_the_worktree() {
+        let repo = two_branches("checkout_switch");
+        let (code, printed) = repo.flog(&["checkout", "main"]);
+        assert_eq!((code, printed.as_str()), (0, "Switched to branch 'main'\n"));
+        assert_eq!(fs::read_to_string("a").unwrap(), "one\n");
+        assert!(!std::path::Path::new("b").exists());
+        assert_eq!(
+            fs::read_to_string(".re_flogged/HEAD").unwrap(),
+            "ref: refs/heads/main\n"
+        );
+        assert_eq!(repo.flog(&["status", "--porcelain"]), (0, String::new()));
+        let (code, printed) = repo.flog(&["checkout", "-"]);
+        assert_eq!((code, printed.as_str()), (0, "Switched to branch 'side'\n"));
+        assert_eq!(fs::read_to_string("b/c").unwrap(), "three\n");
+        let (_, printed) = repo.flog(&["checkout", "side"]);
+        assert_eq!(printed, "Already on 'side'\n");
+    }
+
+    #[test]
+    fn checking_out_a_commit_detaches_head() {
+        let repo = two_branches("checkout_detach");
+        let (_, first) = repo.flog(&["rev-parse", "main"]);
+        let (code, printed) = repo.flog(&["checkout", "main~0"]);
+        assert_eq!(code, 0);
+        assert_eq!(printed, format!("HEAD is now at {} first\n", &first[..7]));
+        assert_eq!(fs::read_to_string(".re_flogged/HEAD").unwrap(), first);
+        assert_eq!(fs::read_to_string("a").unwrap(), "one\n");
+    }
+
+    #[test]
+    fn local_changes_are_not_clobbered() {
+        let repo = two_branches("checkout_clobber");
+        repo.write_file("a", "local\n");
+        let (code, printed) = repo.flog(&["checkout", "main"]);
+        assert_eq!(code, 1);
+        assert_eq!(
+            printed,
+            "error: Your local changes to the following files would be overwritten by checkout:\n\
+             \ta\n\
+             Please commit your changes or stash them before you switch branches.\n\
+             Aborting\n"
+        );
+        assert_eq!(fs::read_to_string("a").unwrap(), "local\n");
+        assert_eq!(
+            fs::read_to_string(".re_flogged/HEAD").unwrap(),
+            "ref: refs/heads/side\n"
+        );
+
+        // once the change is undone the checkout goes ahead, but not over an untracked file
+        repo.write_file("a", "two\n");
+        assert_eq!(repo.flog(&["checkout", "main"]).0, 0);
+        repo.write_file("b/c", "untracked\n");
+        let (code, printed) = repo.flog(&["checkout", "side"]);
+        assert_eq!(code, 1);
+        assert!(printed.starts_with(
+            "error: The following untracked working tree files would be overwritten by checkout:\n\tb/c\n"
+        ));
+        assert_eq!(fs::read_to_string("b/c").unwrap(), "untracked\n");
+    }
+}
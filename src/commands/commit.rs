@@ -2,28 +2,44 @@ use std::io;
 
 use crate::{
     commands::{commit_tree, write_tree},
-    structures::{load_commit_from_sha, IndexFile, RefFile},
-    utils::get_current_branch,
+    exit_with_message,
+    structures::{Author, IndexFile},
+    utils::{
+        clear_merge_state, get_current_branch, get_head_sha, get_merge_head, get_merge_message,
+    },
 };
 
 use super::update_ref_basic;
 
-pub fn commit(messages: &Option<Vec<String>>) -> Result<(), io::Error> {
+/// Commits the index on top of HEAD. While a merge is being concluded the merged commit
+/// becomes the second parent, and its prepared message is used when none is given. An author
+/// given as `Name <email>` replaces the configured one
+pub fn commit(messages: &Option<Vec<String>>, author: &Option<String>) -> Result<(), io::Error> {
+    let author = author.as_deref().map(Author::from_author_option);
     let current_branch = get_current_branch().expect("Failed to get current branch");
     let mut index_file = IndexFile::from_disk().expect("failed to create index file");
+    if index_file.index_entries().iter().any(|e| e.stage() != 0) {
+        exit_with_message(
+            "error: Committing is not possible because you have unmerged files.\nhint: Fix them up in the work tree, and then use 'flog add <file>'\nhint: as appropriate to mark resolution and make a commit.\nfatal: Exiting because of an unresolved conflict.",
+        );
+    }
 
     let sha = write_tree(false).expect("Failed to write tree");
-    let new_commit = match RefFile::new_from_branch(&current_branch) {
-        Ok(ref_file) => {
-            if let Ok(head) = load_commit_from_sha(ref_file.sha()) {
-                commit_tree(&sha, messages, head.parent()).expect("Failed to commit tree")
-            } else {
-                panic!("idk");
-            }
-        }
-        Err(_) => commit_tree(&sha, messages, &None).expect("failed to commit tree"),
+    let mut parents = vec![];
+    parents.extend(get_head_sha()?);
+    let merge_head = get_merge_head()?;
+    parents.extend(merge_head.clone());
+    let messages = match (messages, &merge_head) {
+        (None, Some(_)) => get_merge_message().map(|message| vec![message]),
+        _ => messages.clone(),
     };
+    let parents = match parents.is_empty() {
+        true => None,
+        false => Some(parents),
+    };
+    let new_commit = commit_tree(&sha, &messages, &parents, author).expect("Failed to commit tree");
     update_ref_basic(&current_branch, new_commit.get_sha()).expect("Failed to update ref");
+    clear_merge_state()?;
     index_file.clear_all_entries();
     Ok(())
 }
@@ -1,13 +1,14 @@
 use std::io;
 
-use crate::structures::{Commit, GitObject, Sha};
+use crate::structures::{Author, Commit, GitObject, Sha};
 
 pub fn commit_tree(
     sha: &Sha,
     message: &Option<Vec<String>>,
     parent: &Option<Vec<Sha>>,
+    author: Option<Author>,
 ) -> Result<Commit, io::Error> {
-    let mut commit = Commit::new_from_tree_sha(sha, message, parent)?;
+    let mut commit = Commit::new_from_tree_sha(sha, message, parent, author)?;
     commit.write_to_disk()?;
     Ok(commit)
 }
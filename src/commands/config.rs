@@ -0,0 +1,240 @@
+use std::{io, path::PathBuf, process, rc::Rc};
+
+use clap::ArgEnum;
+use regex::Regex;
+
+use crate::{
+    config::{
+        change_config_file, parse_bool, parse_int, split_name, Config, ConfigChange, ConfigScope,
+        ConfigValue,
+    },
+    exit_with_message,
+};
+
+/// How `config` reads and writes values
+#[derive(Clone, Copy, Debug, ArgEnum)]
+pub enum ConfigType {
+    /// `true` or `false`, from any of the spellings git takes
+    Bool,
+    /// A number, with any `k`, `m` or `g` suffix multiplied out
+    Int,
+}
+
+/// Which files `config` reads and changes
+#[derive(Clone, Debug)]
+pub enum ConfigFiles {
+    /// Every scope's when reading, the repository's when writing
+    All,
+    Scope(ConfigScope),
+    File(PathBuf),
+}
+
+/// How the setting shows with `--show-scope` and `--show-origin`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConfigDisplay {
+    pub show_scope: bool,
+    pub show_origin: bool,
+    pub kind: Option<ConfigType>,
+}
+
+/// Prints the value that counts for the name, or with `all` every value set for it. Exits
+/// with status 1 when it isn't set
+pub fn config_get(
+    name: &str,
+    files: &ConfigFiles,
+    display: ConfigDisplay,
+    all: bool,
+) -> Result<(), io::Error> {
+    if let Err(msg) = split_name(name) {
+        exit_with_message(&format!("error: {}", msg));
+    }
+    let config = read_config(files)?;
+    let mut values = config.get_all(name);
+    if !all && values.len() > 1 {
+        values.drain(..values.len() - 1);
+    }
+    if values.is_empty() {
+        process::exit(1);
+    }
+    for value in values {
+        println!(
+            "{}{}",
+            prefix(value, display),
+            typed_value(value, display.kind)
+        );
+    }
+    Ok(())
+}
+
+/// Prints the name and value of every setting whose name matches the regex anywhere
+pub fn config_get_regexp(
+    pattern: &str,
+    files: &ConfigFiles,
+    display: ConfigDisplay,
+) -> Result<(), io::Error> {
+    let regex = match Regex::new(pattern) {
+        Ok(regex) => regex,
+        Err(_) => exit_with_message(&format!("error: invalid key pattern: {}", pattern)),
+    };
+    let config = read_config(files)?;
+    let mut found = false;
+    for value in config.values().iter() {
+        if !regex.is_match(value.name()) {
+            continue;
+        }
+        found = true;
+        match (value.value(), display.kind) {
+            (None, None) => println!("{}{}", prefix(value, display), value.name()),
+            _ => println!(
+                "{}{} {}",
+                prefix(value, display),
+                value.name(),
+                typed_value(value, display.kind)
+            ),
+        }
+    }
+    if !found {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Prints every setting as `name=value`, or just the name for a key on its own
+pub fn config_list(files: &ConfigFiles, display: ConfigDisplay) -> Result<(), io::Error> {
+    let config = read_config(files)?;
+    for value in config.values().iter() {
+        match (value.value(), display.kind) {
+            (None, None) => println!("{}{}", prefix(value, display), value.name()),
+            _ => println!(
+                "{}{}={}",
+                prefix(value, display),
+                value.name(),
+                typed_value(value, display.kind)
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Sets, adds, replaces or removes values in the scope's file, the repository's by default.
+/// Values given with a type are written the way git normalises them, so `yes` becomes `true`
+pub fn config_change(
+    name: &str,
+    files: &ConfigFiles,
+    change: ConfigChange,
+    kind: Option<ConfigType>,
+) -> Result<(), io::Error> {
+    let path = match files {
+        ConfigFiles::All => ConfigScope::Local.write_path(),
+        ConfigFiles::Scope(scope) => scope.write_path(),
+        ConfigFiles::File(path) => Some(path.clone()),
+    };
+    let path = match path {
+        Some(path) => path,
+        None => exit_with_message("fatal: $HOME not set"),
+    };
+    let normalised = |value: &str| match kind {
+        Some(kind) => normalise_value(name, value, kind),
+        None => value.to_string(),
+    };
+    let written;
+    let change = match change {
+        ConfigChange::Set(value) => {
+            written = normalised(value);
+            ConfigChange::Set(&written)
+        }
+        ConfigChange::Add(value) => {
+            written = normalised(value);
+            ConfigChange::Add(&written)
+        }
+        ConfigChange::ReplaceAll(value) => {
+            written = normalised(value);
+            ConfigChange::ReplaceAll(&written)
+        }
+        change => change,
+    };
+    change_config_file(&path, name, change).map_err(fatal)?;
+    Config::reload();
+    Ok(())
+}
+
+fn read_config(files: &ConfigFiles) -> Result<Rc<Config>, io::Error> {
+    let config = match files {
+        ConfigFiles::All => Config::all(),
+        // as with git, includes are only followed when reading every scope
+        ConfigFiles::Scope(scope) => Config::from_scopes(&[*scope], false).map(Rc::new),
+        ConfigFiles::File(path) => Config::from_file(path, ConfigScope::File, false).map(Rc::new),
+    };
+    config.map_err(fatal)
+}
+
+/// Config files that can't be parsed end the command, as they do for git
+fn fatal(e: io::Error) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::InvalidData => exit_with_message(&format!("fatal: {}", e)),
+        _ => e,
+    }
+}
+
+fn prefix(value: &ConfigValue, display: ConfigDisplay) -> String {
+    let mut prefix = String::new();
+    if display.show_scope {
+        prefix.push_str(&format!("{}\t", value.scope().name()));
+    }
+    if display.show_origin {
+        prefix.push_str(&format!("file:{}\t", value.origin().display()));
+    }
+    prefix
+}
+
+/// The value as the type asks for it, exiting when it can't be read as one
+fn typed_value(value: &ConfigValue, kind: Option<ConfigType>) -> String {
+    let text = value.value().as_deref();
+    match kind {
+        None => text.unwrap_or_default().to_string(),
+        Some(ConfigType::Bool) => match parse_bool(text) {
+            Some(flag) => flag.to_string(),
+            None => {
+                let msg = format!(
+                    "fatal: bad boolean config value '{}' for '{}'",
+                    text.unwrap_or_default(),
+                    value.name()
+                );
+                exit_with_message(&msg);
+            }
+        },
+        Some(ConfigType::Int) => match text.and_then(parse_int) {
+            Some(number) => number.to_string(),
+            None => {
+                let msg = format!(
+                    "fatal: bad numeric config value '{}' for '{}' in file {}: invalid unit",
+                    text.unwrap_or_default(),
+                    value.name(),
+                    value.origin().display()
+                );
+                exit_with_message(&msg);
+            }
+        },
+    }
+}
+
+fn normalise_value(name: &str, value: &str, kind: ConfigType) -> String {
+    let normalised = match kind {
+        ConfigType::Bool => parse_bool(Some(value)).map(|flag| flag.to_string()),
+        ConfigType::Int => parse_int(value).map(|number| number.to_string()),
+    };
+    match (normalised, kind) {
+        (Some(normalised), _) => normalised,
+        (None, ConfigType::Bool) => {
+            let msg = format!("fatal: bad boolean config value '{}' for '{}'", value, name);
+            exit_with_message(&msg);
+        }
+        (None, ConfigType::Int) => {
+            let msg = format!(
+                "fatal: bad numeric config value '{}' for '{}': invalid unit",
+                value, name
+            );
+            exit_with_message(&msg);
+        }
+    }
+}
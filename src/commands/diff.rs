@@ -0,0 +1,162 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+};
+
+use crate::{
+    diff::{
+        detect_renames, diff_entry_maps, diff_trees, DiffFile, DiffOptions, EntryPair, FileChange,
+    },
+    exit_with_message,
+    revision::{merge_bases, resolve_revision},
+    structures::{load_tree_from_sha, peel_to_commit, peel_to_tree, IndexFile, Sha, TreeEntry},
+    utils::get_head_sha,
+};
+
+/// Shows changes between the working tree and the index, between the index and a commit
+/// (HEAD by default) with `cached`, between a commit and the working tree, or between two commits.
+/// The two commits can be given as `A..B`, or as `A...B` to compare B with the merge base of both
+pub fn diff(cached: bool, options: &DiffOptions, commits: &[String]) -> Result<(), io::Error> {
+    let (changes, unmerged) = collect_changes(cached, commits)?;
+    let changes = detect_renames(changes, options.renames);
+    for path in unmerged.iter() {
+        println!("* Unmerged path {}", path);
+    }
+    for change in changes.iter() {
+        print!("{}", options.format(change));
+    }
+    Ok(())
+}
+
+/// The changes the diff command shows for its arguments, along with any unmerged paths
+/// that were left out of a comparison against the index
+pub fn collect_changes(
+    cached: bool,
+    commits: &[String],
+) -> Result<(Vec<FileChange>, BTreeSet<String>), io::Error> {
+    let commits = match commits {
+        [range] if range.contains("..") => split_range(range)?,
+        _ => commits.to_vec(),
+    };
+    // comparing two commits is the only case that leaves the index alone
+    let (index, unmerged) = match commits.len() {
+        2 => (BTreeMap::new(), BTreeSet::new()),
+        _ => index_entries(),
+    };
+    let (pairs, from_worktree): (Vec<EntryPair>, bool) = match (cached, &commits[..]) {
+        (false, []) => (diff_entry_maps(&index, &worktree_entries(&index)?), true),
+        (true, []) => {
+            let head = match get_head_sha()? {
+                Some(sha) => tree_entries(&peel_to_tree(&sha)?)?,
+                None => BTreeMap::new(),
+            };
+            (diff_entry_maps(&head, &index), false)
+        }
+        (true, [commit]) => {
+            let tree = tree_entries(&commit_tree(commit)?)?;
+            (diff_entry_maps(&tree, &index), false)
+        }
+        (false, [commit]) => {
+            let tree = tree_entries(&commit_tree(commit)?)?;
+            (diff_entry_maps(&tree, &worktree_entries(&index)?), true)
+        }
+        (false, [old, new]) => {
+            let pairs = diff_trees(Some(&commit_tree(old)?), Some(&commit_tree(new)?))?;
+            (pairs, false)
+        }
+        _ => exit_with_message("usage: flog diff [--cached] [<commit> [<commit>]]"),
+    };
+    let changes = pairs
+        .iter()
+        .filter(|(old, new)| {
+            !old.iter()
+                .chain(new.iter())
+                .any(|e| unmerged.contains(e.path()))
+        })
+        .map(|pair| FileChange::load(pair, from_worktree))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((changes, unmerged))
+}
+
+fn commit_tree(name: &str) -> Result<Sha, io::Error> {
+    peel_to_tree(&resolve_revision(name)?)
+}
+
+/// The two commits a range compares, with HEAD for a missing side. `A...B` starts from a
+/// merge base of A and B instead of A
+fn split_range(range: &str) -> Result<Vec<String>, io::Error> {
+    let (old, new, symmetric) = match range.split_once("...") {
+        Some((old, new)) => (old, new, true),
+        None => {
+            let (old, new) = range.split_once("..").unwrap();
+            (old, new, false)
+        }
+    };
+    let or_head = |side: &str| match side {
+        "" => "HEAD".to_string(),
+        side => side.to_string(),
+    };
+    let (old, new) = (or_head(old), or_head(new));
+    if !symmetric {
+        return Ok(vec![old, new]);
+    }
+    let old_commit = peel_to_commit(&resolve_revision(&old)?)?;
+    let new_commit = peel_to_commit(&resolve_revision(&new)?)?;
+    match merge_bases(&old_commit, &[new_commit])?.first() {
+        Some(base) => Ok(vec![base.to_string(), new]),
+        None => exit_with_message(&format!("fatal: {}: no merge base", range)),
+    }
+}
+
+fn tree_entries(tree: &Sha) -> Result<BTreeMap<String, TreeEntry>, io::Error> {
+    let entries = load_tree_from_sha(tree)?.flatten()?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| (entry.path().clone(), entry))
+        .collect())
+}
+
+/// The resolved index entries keyed by path, and the paths that still have conflict stages
+fn index_entries() -> (BTreeMap<String, TreeEntry>, BTreeSet<String>) {
+    let mut entries = BTreeMap::new();
+    let mut unmerged = BTreeSet::new();
+    for entry in IndexFile::new(0).index_entries().iter() {
+        let path = entry.get_readable_file_name();
+        match entry.stage() {
+            0 => {
+                let tree_entry =
+                    TreeEntry::new(path.clone(), *entry.mode(), entry.file_sha().clone());
+                entries.insert(path, tree_entry);
+            }
+            _ => {
+                unmerged.insert(path);
+            }
+        }
+    }
+    (entries, unmerged)
+}
+
+/// The tracked files as they are in the working tree. Files that still match the index keep
+/// its entry so they aren't read and hashed again
+fn worktree_entries(
+    index: &BTreeMap<String, TreeEntry>,
+) -> Result<BTreeMap<String, TreeEntry>, io::Error> {
+    let mut entries = BTreeMap::new();
+    let index_file = IndexFile::new(0);
+    for entry in index_file.index_entries().iter() {
+        let path = entry.get_readable_file_name();
+        let indexed = match index.get(&path) {
+            Some(indexed) => indexed,
+            None => continue,
+        };
+        if fs::symlink_metadata(&path).is_err() {
+            continue;
+        }
+        let worktree_entry = match entry.matches_worktree() {
+            true => indexed.clone(),
+            false => DiffFile::from_worktree(&path)?.entry(),
+        };
+        entries.insert(path, worktree_entry);
+    }
+    Ok(entries)
+}
@@ -1,14 +1,25 @@
-use std::io::{self};
+use std::{
+    io::{self},
+    str::FromStr,
+};
 
-use clap::ArgEnum;
-use log::debug;
+use regex::{bytes, Regex, RegexBuilder};
 
 use crate::{
-    structures::{MediumPrinter, OneLinePrinter, Printer, RefFile, ShortPrinter},
-    utils::get_current_branch,
+    diff::{commit_changes, detect_renames, pickaxe_changes, DiffOptions, FileChange, Pickaxe},
+    exit_with_message,
+    revision::{
+        is_within, parse_revision_arg, unknown_revision, CommitFilter, RevWalk, Revision, Sorting,
+    },
+    structures::{
+        load_commit_from_sha, peel_to_commit, Commit, CommitGraph, EmailPrinter, FormatPrinter,
+        FullPrinter, FullerPrinter, MediumPrinter, OneLinePrinter, Printer, RawPrinter,
+        ReferencePrinter, ShortPrinter,
+    },
+    utils::{get_current_branch, get_head_sha, parse_date},
 };
 
-#[derive(Clone, Debug, ArgEnum)]
+#[derive(Clone, Debug)]
 pub enum LogFormat {
     Oneline,
     Short,
@@ -18,22 +29,262 @@ pub enum LogFormat {
     Reference,
     Email,
     Raw,
-    Format,  // takes a string
-    TFormat, // takes a string
+    /// A template of `%` placeholders, with newlines between commits
+    Format(String),
+    /// A template of `%` placeholders, with a newline after every commit
+    TFormat(String),
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(template) = s.strip_prefix("format:") {
+            return Ok(LogFormat::Format(template.to_string()));
+        }
+        if let Some(template) = s.strip_prefix("tformat:") {
+            return Ok(LogFormat::TFormat(template.to_string()));
+        }
+        let format = match s {
+            "oneline" => LogFormat::Oneline,
+            "short" => LogFormat::Short,
+            "medium" => LogFormat::Medium,
+            "full" => LogFormat::Full,
+            "fuller" => LogFormat::Fuller,
+            "reference" => LogFormat::Reference,
+            "email" => LogFormat::Email,
+            "raw" => LogFormat::Raw,
+            // a template on its own is taken as tformat
+            _ if s.contains('%') => LogFormat::TFormat(s.to_string()),
+            _ => return Err(format!("invalid --pretty format: {}", s)),
+        };
+        Ok(format)
+    }
+}
+
+/// Which commits log shows, and in what order
+#[derive(Clone, Debug)]
+pub struct LogOptions {
+    pub sorting: Sorting,
+    pub first_parent: bool,
+    pub reverse: bool,
+    pub max_count: Option<usize>,
+    pub skip: usize,
+    pub graph: bool,
+    /// Where the walk starts and which history it leaves out, HEAD when there are none
+    pub revisions: Vec<String>,
+    pub filter: CommitFilter,
+    /// Only commits changing something at one of these are shown, when there are any
+    pub paths: Vec<String>,
+    /// How each commit's changes are shown after it, if they are
+    pub diff: Option<DiffOptions>,
+    /// Shows all of a commit's changes when the pickaxe finds something in any of them
+    pub pickaxe_all: bool,
+}
+
+/// What log walks for its revision arguments, or for HEAD without any
+fn log_revisions(args: &[String]) -> Result<Vec<Revision>, io::Error> {
+    if args.is_empty() {
+        return match get_head_sha()? {
+            Some(head) => Ok(vec![Revision::Include(head)]),
+            None => {
+                let branch = get_current_branch()?;
+                let msg = format!(
+                    "fatal: your current branch '{}' does not have any commits yet",
+                    branch.trim_start_matches("refs/heads/")
+                );
+                exit_with_message(&msg);
+            }
+        };
+    }
+    let mut revisions = vec![];
+    for arg in args.iter() {
+        match parse_revision_arg(arg)? {
+            Some(parsed) => revisions.extend(parsed),
+            None => exit_with_message(&unknown_revision(arg)),
+        }
+    }
+    Ok(revisions)
+}
+
+/// Compiles the patterns given to one of log's filters, exiting on one that isn't valid
+pub fn compile_patterns(patterns: &[String], ignore_case: bool) -> Vec<Regex> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .build()
+                .unwrap_or_else(|_| {
+                    exit_with_message(&format!("fatal: invalid regular expression: {}", pattern))
+                })
+        })
+        .collect()
 }
 
-pub fn log(print_options: &Option<LogFormat>) -> Result<(), io::Error> {
-    let current_branch = get_current_branch()?;
-    let ref_file = RefFile::new_from_branch(&current_branch)?;
-    debug!("{:?}", ref_file);
-    let printer: &dyn Printer = match print_options {
+/// The pickaxe for `-S`, which looks for the string itself, or `-G`, which takes a regular
+/// expression. Exits if the expression isn't valid
+pub fn compile_pickaxe(
+    string: &Option<String>,
+    lines: &Option<String>,
+    ignore_case: bool,
+) -> Option<Pickaxe> {
+    let compile = |pattern: &str| {
+        bytes::RegexBuilder::new(pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .unwrap_or_else(|_| {
+                exit_with_message(&format!("fatal: invalid regular expression: {}", pattern))
+            })
+    };
+    match (string, lines) {
+        (Some(string), _) => Some(Pickaxe::Occurrences(compile(&regex::escape(string)))),
+        (None, Some(pattern)) => Some(Pickaxe::Lines(compile(pattern))),
+        (None, None) => None,
+    }
+}
+
+/// Reads a `--since` or `--until` date as a unix timestamp, exiting on one that can't be read
+pub fn parse_date_limit(date: &str) -> i64 {
+    match parse_date(date) {
+        Some(date) => date.timestamp(),
+        None => exit_with_message(&format!("fatal: invalid date: {}", date)),
+    }
+}
+
+/// Shows the history of the current branch. `oneline` is the oneline format with the commit
+/// names abbreviated
+pub fn log(
+    print_options: &Option<LogFormat>,
+    oneline: bool,
+    options: &LogOptions,
+) -> Result<(), io::Error> {
+    let printer: Box<dyn Printer> = match print_options {
+        _ if oneline => Box::new(OneLinePrinter { abbrev: true }),
         Some(format) => match format {
-            &LogFormat::Oneline => &OneLinePrinter {},
-            &LogFormat::Short => &ShortPrinter {},
-            _ => &MediumPrinter {},
+            LogFormat::Oneline => Box::new(OneLinePrinter { abbrev: false }),
+            LogFormat::Short => Box::new(ShortPrinter {}),
+            LogFormat::Medium => Box::new(MediumPrinter {}),
+            LogFormat::Full => Box::new(FullPrinter {}),
+            LogFormat::Fuller => Box::new(FullerPrinter {}),
+            LogFormat::Reference => Box::new(ReferencePrinter {}),
+            LogFormat::Email => Box::new(EmailPrinter {}),
+            LogFormat::Raw => Box::new(RawPrinter {}),
+            LogFormat::Format(template) => Box::new(FormatPrinter::new(template, false)?),
+            LogFormat::TFormat(template) => Box::new(FormatPrinter::new(template, true)?),
         },
-        None => &MediumPrinter {},
+        None => Box::new(MediumPrinter {}),
     };
-    ref_file.pretty_print(printer);
+    let mut walk = RevWalk::new();
+    walk.sort(options.sorting);
+    walk.first_parent(options.first_parent);
+    walk.filter_commits(options.filter.clone());
+    walk.limit_to_paths(options.paths.clone());
+    // the graph joins each commit to the nearest ones it shows
+    walk.rewrite_parents(options.graph);
+    for revision in log_revisions(&options.revisions)? {
+        match revision {
+            Revision::Include(sha) => walk.push(&peel_to_commit(&sha)?)?,
+            Revision::Exclude(sha) => walk.hide(&peel_to_commit(&sha)?)?,
+        }
+    }
+    // the count and skip pick commits in walk order, before they are reversed
+    let mut commits: Box<dyn Iterator<Item = Result<Commit, io::Error>>> = Box::new(
+        walk.skip(options.skip)
+            .take(options.max_count.unwrap_or(usize::MAX)),
+    );
+    if options.reverse {
+        let mut reversed = commits.collect::<Result<Vec<_>, _>>()?;
+        reversed.reverse();
+        commits = Box::new(reversed.into_iter().map(Ok));
+    }
+    let mut graph = match options.graph {
+        true => Some(CommitGraph::new(options.first_parent)),
+        false => None,
+    };
+    // the graph has to be carried on through any empty line between two commits, unless
+    // the last commit's text stopped partway through a line
+    let mut missing_newline = false;
+    for (shown, commit) in commits.enumerate() {
+        let commit = commit?;
+        if let Some(graph) = graph.as_mut() {
+            graph.update(&commit);
+        }
+        if shown > 0 && !printer.terminated() {
+            if let (Some(graph), false) = (graph.as_mut(), missing_newline) {
+                print!("{}", graph.padding_line());
+            }
+            println!();
+        }
+        let text = printer.format_commit(&commit);
+        missing_newline = !text.ends_with('\n');
+        match graph.as_mut() {
+            Some(graph) => {
+                print!("{}", graph.show_commit());
+                print!("{}", graph.show_commit_text(&text));
+            }
+            None => print!("{}", text),
+        }
+        if printer.terminated() {
+            if let (Some(graph), false) = (graph.as_mut(), missing_newline) {
+                print!("{}", graph.padding_line());
+            }
+            println!();
+        }
+        let diff = match &options.diff {
+            Some(diff) => diff,
+            None => continue,
+        };
+        let changes = shown_changes(&commit, options)?;
+        if changes.is_empty() {
+            continue;
+        }
+        // the changes are kept apart from the commit by an empty line, unless its text
+        // stopped partway through a line and only needs ending
+        let mut lines = String::new();
+        if missing_newline && !printer.terminated() {
+            println!();
+        } else if printer.separates_changes() {
+            lines.push('\n');
+        }
+        for change in changes.iter() {
+            lines.push_str(&diff.format(change));
+        }
+        for line in lines.lines() {
+            if let Some(graph) = graph.as_mut() {
+                print!("{}", graph.padding_line());
+            }
+            println!("{}", line);
+        }
+    }
     Ok(())
 }
+
+/// The changes log shows for the commit: those at the paths it was limited to, and of those
+/// only the ones the pickaxe found something in unless all were asked for
+fn shown_changes(commit: &Commit, options: &LogOptions) -> Result<Vec<FileChange>, io::Error> {
+    // the graph's parents for a history limited to paths are the nearest commits that changed
+    // them, so are what to compare against. Otherwise they only lack those left out
+    let changes = match options.graph && !options.paths.is_empty() {
+        true => commit_changes(commit, options.first_parent)?,
+        false => commit_changes(&load_commit_from_sha(commit.sha())?, options.first_parent)?,
+    };
+    let changes = match options.diff {
+        Some(diff) => detect_renames(changes, diff.renames),
+        None => changes,
+    };
+    let changes: Vec<FileChange> = changes
+        .into_iter()
+        .filter(|change| {
+            options.paths.is_empty()
+                || options
+                    .paths
+                    .iter()
+                    .any(|path| is_within(change.path(), path))
+        })
+        .collect();
+    match &options.filter.pickaxe {
+        Some(pickaxe) => Ok(pickaxe_changes(changes, pickaxe, options.pickaxe_all)),
+        None => Ok(changes),
+    }
+}
@@ -25,9 +25,11 @@ pub fn hash_and_write_to_db<P: AsRef<OsStr>>(file_path: P) -> Result<(), io::Err
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_repo::lock_cwd;
 
     #[test]
     fn hash_obj_and_write() {
+        let _cwd = lock_cwd();
         hash_and_write_to_db("/home/samuwen/Documents/repos/entirely_fake_repo/boop/README.md")
             .unwrap();
         assert!(
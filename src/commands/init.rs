@@ -38,7 +38,8 @@ pub fn init<P: AsRef<OsStr>>(root_path: P) -> Result<(), io::Error> {
 	bare = false
 	repositoryformatversion = 0
 	filemode = true
-	logallrefupdates = true",
+	logallrefupdates = true
+",
     )?;
     let print_path = Path::canonicalize(&path)?;
     println!(
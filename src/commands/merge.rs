@@ -0,0 +1,373 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File, Permissions},
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    slice,
+};
+
+use log::{debug, info};
+
+use crate::{
+    commands::{
+        add_merged_path, check_nothing_is_clobbered, commit_tree, find_local_changes, merge_trees,
+        remove_file_and_empty_parents, switch_worktree, update_ref_basic, write_entry_to_worktree,
+        write_index_tree, MergedPath,
+    },
+    diff::{is_binary, merge_contents, ConflictStyle, DiffAlgorithm, MergeLabels},
+    exit_with_message,
+    revision::{merge_bases, try_resolve_revision},
+    structures::{load_blob_from_sha, peel_to_commit, Blob, GitObject, IndexFile, Sha, TreeEntry},
+    utils::{
+        get_commit_tree_entries, get_current_branch, get_head_sha, get_merge_head, set_merge_state,
+    },
+};
+
+/// What a path ends up as in the working tree after the merge
+enum MergedFile {
+    Entry(TreeEntry),
+    /// Contents with conflict markers, which only exist in the working tree
+    Conflicted(Vec<u8>, u32),
+}
+
+/// Merges the branch or commit into HEAD. HEAD is fast-forwarded when it is an ancestor of the
+/// other commit, otherwise the two are merged from their merge base and committed with both as
+/// parents, unless some paths conflict and are left in the index for the user to resolve
+pub fn merge(
+    name: &str,
+    message: &Option<Vec<String>>,
+    style: ConflictStyle,
+) -> Result<(), io::Error> {
+    if get_merge_head()?.is_some() {
+        exit_with_message(
+            "fatal: You have not concluded your merge (MERGE_HEAD exists).\nPlease, commit your changes before you merge.",
+        );
+    }
+    let index_file = IndexFile::new(0);
+    if index_file.index_entries().iter().any(|e| e.stage() != 0) {
+        exit_with_message(
+            "error: Merging is not possible because you have unmerged files.\nhint: Fix them up in the work tree, and then use 'flog add <file>'\nhint: as appropriate to mark resolution and make a commit.\nfatal: Exiting because of an unresolved conflict.",
+        );
+    }
+    let theirs = match try_resolve_revision(name)? {
+        Some(sha) => peel_to_commit(&sha)?,
+        None => {
+            let msg = format!("merge: {} - not something we can merge", name);
+            exit_with_message(&msg);
+        }
+    };
+    let current_branch = get_current_branch()?;
+    let head = match get_head_sha()? {
+        Some(head) => head,
+        None => {
+            // nothing to merge into, so the branch just starts at their commit
+            switch_worktree(
+                &BTreeMap::new(),
+                &get_commit_tree_entries(&theirs)?,
+                "merge",
+                "merge",
+            )?;
+            update_ref_basic(&current_branch, &theirs)?;
+            return Ok(());
+        }
+    };
+    // with several equally good bases this takes the newest rather than merging them first
+    let base = match merge_bases(&head, slice::from_ref(&theirs))?
+        .into_iter()
+        .next()
+    {
+        Some(base) => base,
+        None => exit_with_message("fatal: refusing to merge unrelated histories"),
+    };
+    info!("Merging {} into {} from base {}", theirs, head, base);
+    if base == theirs {
+        println!("Already up to date.");
+        return Ok(());
+    }
+    if base == head {
+        println!(
+            "Updating {}..{}\nFast-forward",
+            &head.to_string()[..7],
+            &theirs.to_string()[..7]
+        );
+        switch_worktree(
+            &get_commit_tree_entries(&head)?,
+            &get_commit_tree_entries(&theirs)?,
+            "merge",
+            "merge",
+        )?;
+        update_ref_basic(&current_branch, &theirs)?;
+        return Ok(());
+    }
+
+    let labels = MergeLabels {
+        base: base.to_string()[..7].to_string(),
+        ours: String::from("HEAD"),
+        theirs: name.to_string(),
+    };
+    let ours_entries = get_commit_tree_entries(&head)?;
+    let flatten = |entries: BTreeMap<String, TreeEntry>| entries.into_values().collect::<Vec<_>>();
+    let merged = merge_trees(
+        &flatten(get_commit_tree_entries(&base)?),
+        &flatten(ours_entries.clone()),
+        &flatten(get_commit_tree_entries(&theirs)?),
+    );
+
+    let mut merged_paths = vec![];
+    let mut files: BTreeMap<String, MergedFile> = BTreeMap::new();
+    let mut conflicts: Vec<String> = vec![];
+    for merged_path in merged.into_iter() {
+        let (merged_path, file) = match merged_path {
+            MergedPath::Resolved(entry) => {
+                let file = MergedFile::Entry(entry.clone());
+                (MergedPath::Resolved(entry), file)
+            }
+            MergedPath::Conflicted {
+                path,
+                base,
+                ours: Some(ours),
+                theirs: Some(theirs),
+            } => merge_file(&path, &base, &ours, &theirs, &labels, style)?,
+            MergedPath::Conflicted {
+                path,
+                base,
+                ours,
+                theirs,
+            } => {
+                let (kept, deleted_in, modified_in) = match (&ours, &theirs) {
+                    (Some(kept), _) => (kept.clone(), &labels.theirs, &labels.ours),
+                    (_, Some(kept)) => (kept.clone(), &labels.ours, &labels.theirs),
+                    (None, None) => continue,
+                };
+                println!(
+                    "CONFLICT (modify/delete): {} deleted in {} and modified in {}.  Version {} of {} left in tree.",
+                    path, deleted_in, modified_in, modified_in, path
+                );
+                let merged_path = MergedPath::Conflicted {
+                    path,
+                    base,
+                    ours,
+                    theirs,
+                };
+                (merged_path, MergedFile::Entry(kept))
+            }
+        };
+        let path = match &merged_path {
+            MergedPath::Resolved(entry) => entry.path().clone(),
+            MergedPath::Conflicted { path, .. } => {
+                conflicts.push(path.clone());
+                path.clone()
+            }
+        };
+        files.insert(path, file);
+        merged_paths.push(merged_path);
+    }
+    let new_index = update_worktree(&index_file, &ours_entries, &files, &merged_paths)?;
+    new_index.write()?;
+
+    let mut message = match message {
+        Some(messages) => messages.join("\n\n"),
+        None => default_message(name, &theirs, &current_branch),
+    };
+    if !conflicts.is_empty() {
+        message.push_str("\n\n# Conflicts:\n");
+        for path in conflicts.iter() {
+            message.push_str(&format!("#\t{}\n", path));
+        }
+        set_merge_state(&theirs, &message)?;
+        exit_with_message("Automatic merge failed; fix conflicts and then commit the result.");
+    }
+    let tree = write_index_tree(&new_index, false)?;
+    let commit = commit_tree(&tree, &Some(vec![message]), &Some(vec![head, theirs]), None)?;
+    update_ref_basic(&current_branch, commit.get_sha())?;
+    println!("Merge made by the 'resolve' strategy.");
+    Ok(())
+}
+
+/// Merges the contents of a path both sides changed. A clean merge is written to the database
+/// and resolves the path, otherwise the path stays conflicted and the working tree gets the
+/// contents with conflict markers, or our version if the contents can't be merged
+fn merge_file(
+    path: &str,
+    base: &Option<TreeEntry>,
+    ours: &TreeEntry,
+    theirs: &TreeEntry,
+    labels: &MergeLabels,
+    style: ConflictStyle,
+) -> Result<(MergedPath, MergedFile), io::Error> {
+    println!("Auto-merging {}", path);
+    let conflicted = MergedPath::Conflicted {
+        path: path.to_string(),
+        base: base.clone(),
+        ours: Some(ours.clone()),
+        theirs: Some(theirs.clone()),
+    };
+    let kind = match base {
+        Some(_) => "content",
+        None => "add/add",
+    };
+    let is_file = |entry: &TreeEntry| entry.mode() & 0o170000 == 0o100000;
+    if !is_file(ours) || !is_file(theirs) {
+        println!("CONFLICT ({}): Merge conflict in {}", kind, path);
+        return Ok((conflicted, MergedFile::Entry(ours.clone())));
+    }
+    let base_data = match base {
+        Some(base) => load_blob_from_sha(base.sha())?.data().clone(),
+        None => vec![],
+    };
+    let our_data = load_blob_from_sha(ours.sha())?.data().clone();
+    let their_data = load_blob_from_sha(theirs.sha())?.data().clone();
+    if [&base_data, &our_data, &their_data]
+        .iter()
+        .any(|data| is_binary(data))
+    {
+        println!(
+            "warning: Cannot merge binary files: {} ({} vs. {})",
+            path, labels.ours, labels.theirs
+        );
+        println!("CONFLICT ({}): Merge conflict in {}", kind, path);
+        return Ok((conflicted, MergedFile::Entry(ours.clone())));
+    }
+    let result = merge_contents(
+        &base_data,
+        &our_data,
+        &their_data,
+        labels,
+        style,
+        DiffAlgorithm::Myers,
+    );
+    let mode = merged_mode(base, ours, theirs);
+    if result.conflicts > 0 {
+        println!("CONFLICT ({}): Merge conflict in {}", kind, path);
+        return Ok((conflicted, MergedFile::Conflicted(result.data, mode)));
+    }
+    let mut blob = Blob::new_from_bytes(result.data, mode);
+    blob.write_to_disk()?;
+    let entry = TreeEntry::new(path.to_string(), mode, blob.sha().clone());
+    Ok((
+        MergedPath::Resolved(entry.clone()),
+        MergedFile::Entry(entry),
+    ))
+}
+
+/// Keeps the executable bit of whichever side changed it
+fn merged_mode(base: &Option<TreeEntry>, ours: &TreeEntry, theirs: &TreeEntry) -> u32 {
+    match base {
+        Some(base) if base.mode() == ours.mode() => *theirs.mode(),
+        _ => *ours.mode(),
+    }
+}
+
+/// Writes the merge result over the HEAD version of the working tree and returns the index
+/// to go with it. Local changes to paths the merge doesn't touch are kept, and the merge is
+/// refused when it would overwrite any others
+fn update_worktree(
+    index_file: &IndexFile,
+    ours_entries: &BTreeMap<String, TreeEntry>,
+    files: &BTreeMap<String, MergedFile>,
+    merged_paths: &[MergedPath],
+) -> Result<IndexFile, io::Error> {
+    let dirty = find_local_changes(index_file, ours_entries);
+    let updated: BTreeSet<String> = ours_entries
+        .keys()
+        .chain(files.keys())
+        .filter(|path| match files.get(*path) {
+            Some(MergedFile::Entry(entry)) => ours_entries.get(*path) != Some(entry),
+            _ => true,
+        })
+        .cloned()
+        .collect();
+    check_nothing_is_clobbered(&dirty, ours_entries, &updated, "merge", "merge");
+
+    for path in updated.iter() {
+        match files.get(path) {
+            Some(MergedFile::Entry(entry)) => write_entry_to_worktree(entry)?,
+            Some(MergedFile::Conflicted(data, mode)) => write_conflicted_file(path, data, *mode)?,
+            None => {
+                debug!("Removing {}", path);
+                remove_file_and_empty_parents(Path::new(path))?;
+            }
+        }
+    }
+    let mut new_index = IndexFile::empty();
+    for merged_path in merged_paths.iter() {
+        let path = match merged_path {
+            MergedPath::Resolved(entry) => entry.path(),
+            MergedPath::Conflicted { path, .. } => path,
+        };
+        if !dirty.contains(path) {
+            add_merged_path(&mut new_index, merged_path);
+        }
+    }
+    for entry in index_file.index_entries().iter() {
+        if dirty.contains(&entry.get_readable_file_name()) {
+            new_index.add_entry(entry.clone());
+        }
+    }
+    let resolved: BTreeSet<String> = new_index
+        .index_entries()
+        .iter()
+        .map(|entry| entry.get_readable_file_name())
+        .filter(|path| !dirty.contains(path) && fs::symlink_metadata(path).is_ok())
+        .collect();
+    new_index.refresh_entries(&resolved)?;
+    Ok(new_index)
+}
+
+fn write_conflicted_file(path: &str, data: &[u8], mode: u32) -> Result<(), io::Error> {
+    let path = Path::new(path);
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    if fs::symlink_metadata(path).is_ok() {
+        fs::remove_file(path)?;
+    }
+    File::create(path)?.write_all(data)?;
+    let permissions = match mode == 0o100755 {
+        true => 0o755,
+        false => 0o644,
+    };
+    fs::set_permissions(path, Permissions::from_mode(permissions))
+}
+
+/// Git's message for a merge commit, which names the branch being merged into unless it is
+/// the main branch
+fn default_message(name: &str, theirs: &Sha, current_branch: &str) -> String {
+    let is_branch = Path::new(".re_flogged/refs/heads").join(name).is_file();
+    let mut message = match is_branch {
+        true => format!("Merge branch '{}'", name),
+        false => format!("Merge commit '{}'", theirs),
+    };
+    if let Some(branch) = current_branch.strip_prefix("refs/heads/") {
+        if branch != "main" && branch != "master" {
+            message.push_str(&format!(" into {}", branch));
+        }
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_follows_the_side_that_changed_it() {
+        let entry = |mode| TreeEntry::new(String::from("f"), mode, Sha::new_from_bytes([1; 20]));
+        let base = Some(entry(0o100644));
+        assert_eq!(
+            merged_mode(&base, &entry(0o100644), &entry(0o100755)),
+            0o100755
+        );
+        assert_eq!(
+            merged_mode(&base, &entry(0o100755), &entry(0o100644)),
+            0o100755
+        );
+        assert_eq!(
+            merged_mode(&None, &entry(0o100644), &entry(0o100755)),
+            0o100644
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use std::{io, process};
+
+use crate::{
+    exit_with_message,
+    revision::{is_ancestor, merge_bases, merge_bases_octopus, try_resolve_revision},
+    structures::{peel_to_commit, Sha},
+};
+
+/// Prints the best common ancestor of the first commit and the others, or every one of them
+/// with `all`. With `octopus` the bases are common to all the commits at once rather than to
+/// the first and any of the rest. Exits with
+/// status 1 and no output when there is no common ancestor
+pub fn merge_base(commits: &[String], all: bool, octopus: bool) -> Result<(), io::Error> {
+    let shas = resolve_commits(commits)?;
+    let bases = match (octopus, shas.split_first()) {
+        (true, _) => merge_bases_octopus(&shas)?,
+        (false, Some((first, others))) if !others.is_empty() => merge_bases(first, others)?,
+        _ => exit_with_message("usage: flog merge-base [-a | --all] <commit> <commit>..."),
+    };
+    if bases.is_empty() {
+        process::exit(1);
+    }
+    let shown = match all {
+        true => bases.len(),
+        false => 1,
+    };
+    for base in bases.iter().take(shown) {
+        println!("{}", base);
+    }
+    Ok(())
+}
+
+/// Exits with status 0 when the first commit is an ancestor of the second and 1 when it isn't
+pub fn merge_base_is_ancestor(commits: &[String]) -> Result<(), io::Error> {
+    if commits.len() != 2 {
+        exit_with_message("fatal: --is-ancestor takes exactly two commits");
+    }
+    let shas = resolve_commits(commits)?;
+    match is_ancestor(&shas[0], &shas[1])? {
+        true => Ok(()),
+        false => process::exit(1),
+    }
+}
+
+fn resolve_commits(names: &[String]) -> Result<Vec<Sha>, io::Error> {
+    names
+        .iter()
+        .map(|name| match try_resolve_revision(name)? {
+            Some(sha) => peel_to_commit(&sha),
+            None => {
+                let msg = format!("fatal: Not a valid object name {}", name);
+                exit_with_message(&msg);
+            }
+        })
+        .collect()
+}
@@ -1,23 +1,43 @@
+pub mod branch;
 pub mod cat_file;
+pub mod checkout;
 pub mod commit;
 pub mod commit_tree;
+pub mod config;
+pub mod diff;
 pub mod gitlog;
 pub mod hash_object;
 pub mod init;
 pub mod ls_files;
+pub mod merge;
+pub mod merge_base;
 pub mod read_tree;
+pub mod repack;
+pub mod rev_parse;
+pub mod status;
+pub mod tag;
 pub mod update_index;
 pub mod update_ref;
 pub mod write_tree;
 
+pub use branch::*;
 pub use cat_file::*;
+pub use checkout::*;
 pub use commit::*;
 pub use commit_tree::*;
+pub use config::*;
+pub use diff::*;
 pub use gitlog::*;
 pub use hash_object::*;
 pub use init::*;
 pub use ls_files::*;
+pub use merge::*;
+pub use merge_base::*;
 pub use read_tree::*;
+pub use repack::*;
+pub use rev_parse::*;
+pub use status::*;
+pub use tag::*;
 pub use update_index::*;
 pub use update_ref::*;
 pub use write_tree::*;
@@ -1,7 +1,216 @@
-use std::io;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+};
 
-use crate::structures::Sha;
+use log::info;
 
-pub fn read_tree(_sha: &Sha) -> Result<(), io::Error> {
-    todo!();
+use crate::{
+    exit_with_message,
+    structures::{load_tree_from_sha, peel_to_tree, IndexEntry, IndexFile, Sha, TreeEntry},
+};
+
+/// How a single path came out of merging three trees
+#[derive(Clone, Debug, PartialEq)]
+pub enum MergedPath {
+    /// Both sides agree, or only one of them changed the path since the base
+    Resolved(TreeEntry),
+    /// Both sides changed the path differently. Any side can be missing if it deleted the path
+    Conflicted {
+        path: String,
+        base: Option<TreeEntry>,
+        ours: Option<TreeEntry>,
+        theirs: Option<TreeEntry>,
+    },
+}
+
+/// Reads the tree into the index, replacing what was staged. With a prefix the tree is
+/// grafted under that directory of the existing index instead
+pub fn read_tree(shas: &[Sha], merge: bool, prefix: &Option<String>) -> Result<(), io::Error> {
+    if merge {
+        return read_tree_merge(shas);
+    }
+    if shas.len() != 1 {
+        exit_with_message("fatal: reading more than one tree requires -m");
+    }
+    let sha = &shas[0];
+    info!("Reading tree {} into the index", sha);
+    let tree = load_tree_from_sha(&peel_to_tree(sha)?)?;
+    let entries = tree.flatten()?;
+    let index_file = match prefix {
+        Some(prefix) => read_tree_with_prefix(entries, prefix)?,
+        None => {
+            let mut index_file = IndexFile::empty();
+            for entry in entries.iter() {
+                index_file.add_entry(IndexEntry::new_from_tree_entry(entry, 0));
+            }
+            index_file
+        }
+    };
+    index_file.write()?;
+    Ok(())
+}
+
+/// Merges the trees into the index. One tree is read as is, three trees are merged as
+/// base, ours and theirs with conflicting paths left in stages 1, 2 and 3
+fn read_tree_merge(shas: &[Sha]) -> Result<(), io::Error> {
+    let mut trees = vec![];
+    for sha in shas.iter() {
+        let tree = load_tree_from_sha(&peel_to_tree(sha)?)?;
+        trees.push(tree.flatten()?);
+    }
+    let mut index_file = IndexFile::empty();
+    match trees.as_slice() {
+        [only] => {
+            for entry in only.iter() {
+                index_file.add_entry(IndexEntry::new_from_tree_entry(entry, 0));
+            }
+        }
+        [base, ours, theirs] => {
+            for merged in merge_trees(base, ours, theirs) {
+                add_merged_path(&mut index_file, &merged);
+            }
+        }
+        _ => exit_with_message("fatal: read-tree -m only supports merging one or three trees"),
+    }
+    index_file.write()?;
+    Ok(())
+}
+
+/// Stages a merged path, conflicts go in as one entry per side that still has the path
+pub fn add_merged_path(index_file: &mut IndexFile, merged: &MergedPath) {
+    match merged {
+        MergedPath::Resolved(entry) => {
+            index_file.add_entry(IndexEntry::new_from_tree_entry(entry, 0));
+        }
+        MergedPath::Conflicted {
+            base, ours, theirs, ..
+        } => {
+            let sides = [(1, base), (2, ours), (3, theirs)];
+            for (stage, side) in sides.iter() {
+                if let Some(entry) = side {
+                    index_file.add_entry(IndexEntry::new_from_tree_entry(entry, *stage));
+                }
+            }
+        }
+    }
+}
+
+/// Merges three flattened trees path by path. Paths deleted by the merge are left out
+pub fn merge_trees(
+    base: &[TreeEntry],
+    ours: &[TreeEntry],
+    theirs: &[TreeEntry],
+) -> Vec<MergedPath> {
+    let by_path = |entries: &[TreeEntry]| -> BTreeMap<String, TreeEntry> {
+        entries
+            .iter()
+            .map(|entry| (entry.path().clone(), entry.clone()))
+            .collect()
+    };
+    let (base, ours, theirs) = (by_path(base), by_path(ours), by_path(theirs));
+    let all_paths: BTreeSet<&String> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+    let same = |a: Option<&TreeEntry>, b: Option<&TreeEntry>| match (a, b) {
+        (Some(a), Some(b)) => a.sha() == b.sha() && a.mode() == b.mode(),
+        (None, None) => true,
+        _ => false,
+    };
+    let mut merged = vec![];
+    for path in all_paths {
+        let (b, o, t) = (base.get(path), ours.get(path), theirs.get(path));
+        let resolved = if same(o, t) || same(b, t) {
+            Some(o)
+        } else if same(b, o) {
+            Some(t)
+        } else {
+            None
+        };
+        match resolved {
+            Some(Some(entry)) => merged.push(MergedPath::Resolved(entry.clone())),
+            Some(None) => (), // deleted on the side that changed it
+            None => merged.push(MergedPath::Conflicted {
+                path: path.clone(),
+                base: b.cloned(),
+                ours: o.cloned(),
+                theirs: t.cloned(),
+            }),
+        }
+    }
+    merged
+}
+
+fn read_tree_with_prefix(entries: Vec<TreeEntry>, prefix: &str) -> Result<IndexFile, io::Error> {
+    let prefix = prefix.trim_end_matches('/');
+    let mut index_file = IndexFile::new(0);
+    let overlapping = index_file.index_entries().iter().find(|existing| {
+        let name = existing.get_readable_file_name();
+        name == prefix || name.starts_with(&format!("{}/", prefix))
+    });
+    if let Some(existing) = overlapping {
+        let msg = format!(
+            "error: Entry '{}' overlaps with '{}'.  Cannot bind.",
+            existing.get_readable_file_name(),
+            prefix
+        );
+        exit_with_message(&msg);
+    }
+    for entry in entries.iter() {
+        let path = match prefix.is_empty() {
+            true => entry.path().clone(),
+            false => format!("{}/{}", prefix, entry.path()),
+        };
+        let grafted = TreeEntry::new(path, *entry.mode(), entry.sha().clone());
+        index_file.add_entry(IndexEntry::new_from_tree_entry(&grafted, 0));
+    }
+    Ok(index_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, byte: u8) -> TreeEntry {
+        TreeEntry::new(path.to_string(), 0o100644, Sha::new_from_bytes([byte; 20]))
+    }
+
+    #[test]
+    fn three_way_tree_merge() {
+        let base = vec![
+            entry("both", 1),
+            entry("gone", 2),
+            entry("ours", 3),
+            entry("theirs", 4),
+        ];
+        let ours = vec![
+            entry("both", 5),
+            entry("ours", 6),
+            entry("theirs", 4),
+            entry("new", 7),
+        ];
+        let theirs = vec![
+            entry("both", 8),
+            entry("gone", 2),
+            entry("ours", 3),
+            entry("theirs", 9),
+        ];
+        let merged = merge_trees(&base, &ours, &theirs);
+        assert_eq!(
+            merged,
+            vec![
+                MergedPath::Conflicted {
+                    path: String::from("both"),
+                    base: Some(entry("both", 1)),
+                    ours: Some(entry("both", 5)),
+                    theirs: Some(entry("both", 8)),
+                },
+                MergedPath::Resolved(entry("new", 7)),
+                MergedPath::Resolved(entry("ours", 6)),
+                MergedPath::Resolved(entry("theirs", 9)),
+            ]
+        );
+    }
 }
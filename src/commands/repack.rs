@@ -0,0 +1,225 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashSet, VecDeque},
+    fs, io,
+    path::Path,
+};
+
+use log::{debug, info};
+
+use crate::{
+    structures::{
+        check_file_is_of_kind, create_delta, kind_number, load_commit_from_sha, load_tag_from_sha,
+        load_tree_from_sha, read_object_content, IndexFile, PackIndex, PackWriter, RefFile, Sha,
+        OBJ_BLOB, OBJ_COMMIT, OBJ_TAG, OBJ_TREE, PACK_DIRECTORY,
+    },
+    utils::get_head_sha,
+};
+
+/// An object headed for the new pack, with what is needed to sort it near likely delta bases
+struct PackCandidate {
+    sha: Sha,
+    kind: u8,
+    size: usize,
+    name_hash: u32,
+}
+
+/// A recently written object that later objects can be deltas against
+struct WindowEntry {
+    kind: u8,
+    data: Vec<u8>,
+    offset: u64,
+    depth: usize,
+}
+
+/// Packs reachable objects into a new pack. With `all` objects that are already packed go in
+/// too, and with `delete` the loose objects and packs the new pack makes redundant are removed
+pub fn repack(all: bool, delete: bool, window: usize, depth: usize) -> Result<(), io::Error> {
+    let mut candidates = vec![];
+    for (sha, kind, name) in find_reachable_objects()? {
+        if !all && !sha.to_path().is_file() {
+            continue;
+        }
+        let (_, data) = read_object_content(&sha)?;
+        candidates.push(PackCandidate {
+            sha,
+            kind,
+            size: data.len(),
+            name_hash: name_hash(&name),
+        });
+    }
+    if candidates.is_empty() {
+        println!("Nothing new to pack.");
+        return Ok(());
+    }
+    // objects of the same type with the same file name end up next to each other, biggest
+    // first so that deltas mostly remove data rather than add it
+    candidates.sort_by_key(|c| (c.kind, c.name_hash, Reverse(c.size)));
+
+    let mut writer = PackWriter::new(Path::new(PACK_DIRECTORY), candidates.len() as u32)?;
+    let mut recent: VecDeque<WindowEntry> = VecDeque::with_capacity(window + 1);
+    let mut delta_count = 0;
+    for candidate in candidates.iter() {
+        let (_, data) = read_object_content(&candidate.sha)?;
+        let mut best: Option<(&WindowEntry, Vec<u8>)> = None;
+        for base in recent.iter().rev() {
+            if base.kind != candidate.kind
+                || base.depth >= depth
+                || base.data.len() < data.len() / 32
+            {
+                continue;
+            }
+            let delta = create_delta(&base.data, &data);
+            let limit = match &best {
+                Some((_, best_delta)) => best_delta.len(),
+                None => data.len() / 2,
+            };
+            if delta.len() < limit {
+                best = Some((base, delta));
+            }
+        }
+        let (offset, object_depth) = match best {
+            Some((base, delta)) => {
+                debug!("Storing {} as a delta", candidate.sha);
+                delta_count += 1;
+                let (base_offset, base_depth) = (base.offset, base.depth);
+                (
+                    writer.write_delta(&candidate.sha, base_offset, &delta)?,
+                    base_depth + 1,
+                )
+            }
+            None => (
+                writer.write_object(&candidate.sha, candidate.kind, &data)?,
+                0,
+            ),
+        };
+        recent.push_back(WindowEntry {
+            kind: candidate.kind,
+            data,
+            offset,
+            depth: object_depth,
+        });
+        if recent.len() > window {
+            recent.pop_front();
+        }
+    }
+    let idx_path = writer.finish()?;
+    PackIndex::reload();
+    println!("Total {} (delta {})", candidates.len(), delta_count);
+    if delete {
+        let packed: HashSet<String> = candidates.iter().map(|c| c.sha.to_string()).collect();
+        remove_packed_loose_objects(&packed)?;
+        if all {
+            remove_other_packs(&idx_path)?;
+        }
+        PackIndex::reload();
+    }
+    Ok(())
+}
+
+/// Every object reachable from HEAD, the refs and the index, with the type of each and the
+/// name it was found under
+fn find_reachable_objects() -> Result<Vec<(Sha, u8, String)>, io::Error> {
+    let mut to_visit: Vec<(Sha, u8, String)> = vec![];
+    let mut tips: Vec<Sha> = RefFile::list("refs")?
+        .iter()
+        .map(|reff| reff.sha().clone())
+        .collect();
+    tips.extend(get_head_sha()?);
+    for sha in tips {
+        let kind = ["commit", "tag", "tree", "blob"]
+            .iter()
+            .find(|kind| check_file_is_of_kind(&sha, kind))
+            .copied()
+            .unwrap_or("commit");
+        to_visit.push((sha, kind_number(kind)?, String::new()));
+    }
+    for entry in IndexFile::new(0).index_entries().iter() {
+        let name = entry.get_readable_file_name();
+        to_visit.push((entry.file_sha().clone(), OBJ_BLOB, name));
+    }
+
+    let mut seen = HashSet::new();
+    let mut objects = vec![];
+    while let Some((sha, kind, name)) = to_visit.pop() {
+        if !seen.insert(sha.to_string()) {
+            continue;
+        }
+        match kind {
+            OBJ_COMMIT => {
+                let commit = load_commit_from_sha(&sha)?;
+                to_visit.push((commit.tree_sha().clone(), OBJ_TREE, String::new()));
+                if let Some(parents) = commit.parent() {
+                    for parent in parents.iter() {
+                        to_visit.push((parent.clone(), OBJ_COMMIT, String::new()));
+                    }
+                }
+            }
+            OBJ_TREE => {
+                let tree = load_tree_from_sha(&sha)?;
+                for node in tree.root().nodes().iter() {
+                    let node_kind = match node.mode_bits() {
+                        // submodule commits live in another repository
+                        0o160000 => continue,
+                        0o40000 => OBJ_TREE,
+                        _ => OBJ_BLOB,
+                    };
+                    to_visit.push((node.sha().clone(), node_kind, node.clean_name()));
+                }
+            }
+            OBJ_TAG => {
+                let tag = load_tag_from_sha(&sha)?;
+                let object_kind = kind_number(tag.object_type())?;
+                to_visit.push((tag.object().clone(), object_kind, name.clone()));
+            }
+            _ => {}
+        }
+        objects.push((sha, kind, name));
+    }
+    info!("Found {} reachable objects", objects.len());
+    Ok(objects)
+}
+
+/// Git's hash for grouping objects by file name, weighted towards the last characters so
+/// files with the same extension sort close together
+fn name_hash(name: &str) -> u32 {
+    name.bytes()
+        .filter(|b| !b.is_ascii_whitespace())
+        .fold(0u32, |hash, b| (hash >> 2).wrapping_add((b as u32) << 24))
+}
+
+fn remove_packed_loose_objects(packed: &HashSet<String>) -> Result<(), io::Error> {
+    let objects_dir = Path::new(".re_flogged/objects");
+    for dir in fs::read_dir(objects_dir)? {
+        let dir = dir?;
+        let prefix = dir.file_name().to_string_lossy().to_string();
+        if prefix.len() != 2 || !dir.file_type()?.is_dir() {
+            continue;
+        }
+        for file in fs::read_dir(dir.path())? {
+            let file = file?;
+            let name = format!("{}{}", prefix, file.file_name().to_string_lossy());
+            if packed.contains(&name) {
+                fs::remove_file(file.path())?;
+            }
+        }
+        if fs::read_dir(dir.path())?.next().is_none() {
+            fs::remove_dir(dir.path())?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_other_packs(keep: &Path) -> Result<(), io::Error> {
+    for entry in fs::read_dir(PACK_DIRECTORY)? {
+        let path = entry?.path();
+        let is_pack_file = path
+            .extension()
+            .is_some_and(|ext| ext == "pack" || ext == "idx");
+        if is_pack_file && path.with_extension("idx") != keep {
+            debug!("Removing redundant pack file {}", path.display());
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
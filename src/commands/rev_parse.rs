@@ -0,0 +1,61 @@
+use std::{io, process};
+
+use crate::{
+    exit_with_message,
+    revision::{parse_revision_arg, try_resolve_revision, unknown_revision, Revision},
+    structures::{find_objects_with_prefix, shortest_unique_prefix},
+};
+
+/// Prints the object each revision names, with ranges spelled out as the commits they
+/// include and `^`-prefixed ones they leave out. With `verify` there has to be exactly one
+/// revision naming an object, and `quiet` exits with status 1 instead of complaining when
+/// there isn't. `short` verifies too, and abbreviates the sha to the shortest unique start
+/// at least that long
+pub fn rev_parse(
+    revisions: &[String],
+    verify: bool,
+    quiet: bool,
+    short: Option<usize>,
+) -> Result<(), io::Error> {
+    if verify || short.is_some() {
+        let sha = match revisions {
+            [revision] => try_resolve_revision(revision)?,
+            _ => None,
+        };
+        match (sha, quiet) {
+            (Some(sha), _) => match short {
+                Some(len) => println!("{}", shortest_unique_prefix(&sha, len)?),
+                None => println!("{}", sha),
+            },
+            (None, true) => process::exit(1),
+            (None, false) => exit_with_message("fatal: Needed a single revision"),
+        }
+        return Ok(());
+    }
+    let mut lines = vec![];
+    for revision in revisions.iter() {
+        let parsed = match parse_revision_arg(revision)? {
+            Some(parsed) => parsed,
+            None => exit_with_message(&unknown_revision(revision)),
+        };
+        lines.extend(parsed.into_iter().map(|parsed| match parsed {
+            Revision::Include(sha) => sha.to_string(),
+            Revision::Exclude(sha) => format!("^{}", sha),
+        }));
+    }
+    for line in lines.iter() {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Prints every object whose sha starts with the prefix
+pub fn rev_parse_disambiguate(prefix: &str) -> Result<(), io::Error> {
+    if prefix.len() < 4 || !prefix.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        return Ok(());
+    }
+    for sha in find_objects_with_prefix(prefix)? {
+        println!("{}", sha);
+    }
+    Ok(())
+}
@@ -0,0 +1,539 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    os::unix::fs::MetadataExt,
+    path::Path,
+};
+
+use clap::ArgEnum;
+use log::debug;
+
+use crate::{
+    structures::{IndexEntry, IndexFile, Sha, TreeEntry},
+    utils::{get_current_branch, get_head_sha, get_head_tree_entries, get_merge_head},
+};
+
+#[derive(Clone, Debug, ArgEnum)]
+pub enum PorcelainVersion {
+    V1,
+    V2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Change {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl Change {
+    fn code(change: Option<Change>) -> char {
+        match change {
+            Some(Change::Added) => 'A',
+            Some(Change::Modified) => 'M',
+            Some(Change::Deleted) => 'D',
+            None => '.',
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Change::Added => "new file:   ",
+            Change::Modified => "modified:   ",
+            Change::Deleted => "deleted:    ",
+        }
+    }
+}
+
+/// A tracked path that differs somewhere between HEAD, the index and the working tree
+struct TrackedStatus {
+    path: String,
+    staged: Option<Change>,
+    unstaged: Option<Change>,
+    head: Option<TreeEntry>,
+    index: Option<IndexEntry>,
+    worktree_mode: u32,
+}
+
+/// A path with conflict stages left in the index by a merge
+struct UnmergedStatus {
+    path: String,
+    stages: [Option<IndexEntry>; 3],
+    worktree_mode: u32,
+}
+
+impl UnmergedStatus {
+    /// The two letter code git uses for which sides of the merge still have the path
+    fn code(&self) -> &'static str {
+        match (
+            self.stages[0].is_some(),
+            self.stages[1].is_some(),
+            self.stages[2].is_some(),
+        ) {
+            (true, true, true) => "UU",
+            (false, true, true) => "AA",
+            (true, true, false) => "UD",
+            (true, false, true) => "DU",
+            (false, true, false) => "AU",
+            (false, false, true) => "UA",
+            _ => "DD",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self.code() {
+            "UU" => "both modified:   ",
+            "AA" => "both added:      ",
+            "UD" => "deleted by them: ",
+            "DU" => "deleted by us:   ",
+            "AU" => "added by us:     ",
+            "UA" => "added by them:   ",
+            _ => "both deleted:    ",
+        }
+    }
+}
+
+struct StatusReport {
+    tracked: Vec<TrackedStatus>,
+    unmerged: Vec<UnmergedStatus>,
+    untracked: Vec<String>,
+}
+
+/// Shows what is staged, what has changed in the working tree, and what isn't tracked
+pub fn status(porcelain: &Option<PorcelainVersion>) -> Result<(), io::Error> {
+    let report = build_report()?;
+    match porcelain {
+        Some(PorcelainVersion::V1) => print_porcelain_v1(&report),
+        Some(PorcelainVersion::V2) => print_porcelain_v2(&report),
+        None => print_long(&report)?,
+    }
+    Ok(())
+}
+
+fn build_report() -> Result<StatusReport, io::Error> {
+    let head_entries = get_head_tree_entries()?;
+    let mut index_file = IndexFile::new(0);
+    let mut to_refresh = BTreeSet::new();
+    let mut staged_entries: BTreeMap<String, IndexEntry> = BTreeMap::new();
+    let mut unmerged: BTreeMap<String, UnmergedStatus> = BTreeMap::new();
+    let mut tracked_paths = BTreeSet::new();
+    for entry in index_file.index_entries().iter() {
+        let name = entry.get_readable_file_name();
+        tracked_paths.insert(name.clone());
+        if entry.stage() == 0 {
+            staged_entries.insert(name, entry.clone());
+            continue;
+        }
+        let status = unmerged
+            .entry(name.clone())
+            .or_insert_with(|| UnmergedStatus {
+                worktree_mode: worktree_mode(&name),
+                path: name,
+                stages: [None, None, None],
+            });
+        status.stages[entry.stage() as usize - 1] = Some(entry.clone());
+    }
+
+    let mut tracked = vec![];
+    let all_paths: BTreeSet<&String> = head_entries.keys().chain(staged_entries.keys()).collect();
+    for path in all_paths {
+        if unmerged.contains_key(path) {
+            continue;
+        }
+        let head = head_entries.get(path);
+        let index = staged_entries.get(path);
+        let staged = match (head, index) {
+            (None, Some(_)) => Some(Change::Added),
+            (Some(_), None) => Some(Change::Deleted),
+            (Some(h), Some(i)) if h.sha() != i.file_sha() || h.mode() != i.mode() => {
+                Some(Change::Modified)
+            }
+            _ => None,
+        };
+        let unstaged = match index {
+            Some(entry) => {
+                let changed = worktree_change(entry);
+                if changed.is_none() && needs_stat_refresh(entry) {
+                    to_refresh.insert(path.clone());
+                }
+                changed
+            }
+            None => None,
+        };
+        if staged.is_some() || unstaged.is_some() {
+            tracked.push(TrackedStatus {
+                path: path.clone(),
+                staged,
+                unstaged,
+                head: head.cloned(),
+                index: index.cloned(),
+                worktree_mode: worktree_mode(path),
+            });
+        }
+    }
+    if !to_refresh.is_empty() {
+        // save the stat data of files that only looked changed, so they aren't rehashed next time
+        index_file.refresh_entries(&to_refresh)?;
+        index_file.write()?;
+    }
+
+    let mut untracked = vec![];
+    find_untracked(Path::new(""), &tracked_paths, &mut untracked)?;
+    Ok(StatusReport {
+        tracked,
+        unmerged: unmerged.into_values().collect(),
+        untracked,
+    })
+}
+
+fn worktree_change(entry: &IndexEntry) -> Option<Change> {
+    let name = entry.get_readable_file_name();
+    if fs::symlink_metadata(&name).is_err() {
+        return Some(Change::Deleted);
+    }
+    match entry.matches_worktree() {
+        true => None,
+        false => Some(Change::Modified),
+    }
+}
+
+fn needs_stat_refresh(entry: &IndexEntry) -> bool {
+    match fs::symlink_metadata(entry.get_readable_file_name()) {
+        Ok(metadata) => !entry.is_stat_clean(&metadata),
+        Err(_) => false,
+    }
+}
+
+fn worktree_mode(path: &str) -> u32 {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => 0o120000,
+        Ok(metadata) if metadata.mode() & 0o111 != 0 => 0o100755,
+        Ok(_) => 0o100644,
+        Err(_) => 0,
+    }
+}
+
+/// Lists files the index doesn't know about. Directories without any tracked files are
+/// listed once with a trailing slash instead of file by file
+fn find_untracked(
+    dir: &Path,
+    tracked_paths: &BTreeSet<String>,
+    untracked: &mut Vec<String>,
+) -> Result<(), io::Error> {
+    let read_from = match dir.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => dir,
+    };
+    let mut names: Vec<_> = fs::read_dir(read_from)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name())
+        .collect();
+    names.sort();
+    for name in names {
+        if name == ".re_flogged" {
+            continue;
+        }
+        let path = dir.join(&name);
+        let path_string = path.display().to_string();
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            let dir_prefix = format!("{}/", path_string);
+            let has_tracked = tracked_paths
+                .range(dir_prefix.clone()..)
+                .next()
+                .is_some_and(|tracked| tracked.starts_with(&dir_prefix));
+            match has_tracked {
+                true => find_untracked(&path, tracked_paths, untracked)?,
+                false => untracked.push(dir_prefix),
+            }
+        } else if !tracked_paths.contains(&path_string) {
+            debug!("Untracked: {}", path_string);
+            untracked.push(path_string);
+        }
+    }
+    Ok(())
+}
+
+fn print_long(report: &StatusReport) -> Result<(), io::Error> {
+    let branch = get_current_branch()?;
+    let head = get_head_sha()?;
+    match (branch.strip_prefix("refs/heads/"), &head) {
+        (Some(name), _) => println!("On branch {}", name),
+        (None, Some(sha)) => println!("HEAD detached at {}", &sha.to_string()[..7]),
+        (None, None) => println!("Not currently on any branch."),
+    }
+    // like git with its hints turned off, each part ends in a blank line
+    if head.is_none() {
+        println!("\nNo commits yet\n");
+    }
+    if get_merge_head()?.is_some() {
+        match report.unmerged.is_empty() {
+            true => println!("All conflicts fixed but you are still merging.\n"),
+            false => println!("You have unmerged paths.\n"),
+        }
+    }
+    let staged: Vec<&TrackedStatus> = report
+        .tracked
+        .iter()
+        .filter(|t| t.staged.is_some())
+        .collect();
+    if !staged.is_empty() {
+        println!("Changes to be committed:");
+        for status in staged.iter() {
+            println!("\t{}{}", status.staged.unwrap().label(), status.path);
+        }
+        println!();
+    }
+    if !report.unmerged.is_empty() {
+        println!("Unmerged paths:");
+        for status in report.unmerged.iter() {
+            println!("\t{}{}", status.label(), status.path);
+        }
+        println!();
+    }
+    let unstaged: Vec<&TrackedStatus> = report
+        .tracked
+        .iter()
+        .filter(|t| t.unstaged.is_some())
+        .collect();
+    if !unstaged.is_empty() {
+        println!("Changes not staged for commit:");
+        for status in unstaged.iter() {
+            println!("\t{}{}", status.unstaged.unwrap().label(), status.path);
+        }
+        println!();
+    }
+    if !report.untracked.is_empty() {
+        println!("Untracked files:");
+        for path in report.untracked.iter() {
+            println!("\t{}", path);
+        }
+        println!();
+    }
+    if !staged.is_empty() {
+        return Ok(());
+    }
+    if !unstaged.is_empty() || !report.unmerged.is_empty() {
+        println!("no changes added to commit");
+    } else if !report.untracked.is_empty() {
+        println!("nothing added to commit but untracked files present");
+    } else if head.is_none() {
+        println!("nothing to commit");
+    } else {
+        println!("nothing to commit, working tree clean");
+    }
+    Ok(())
+}
+
+fn print_porcelain_v1(report: &StatusReport) {
+    let mut lines: Vec<(&String, String)> = vec![];
+    for status in report.tracked.iter() {
+        let code = format!(
+            "{}{}",
+            Change::code(status.staged),
+            Change::code(status.unstaged)
+        );
+        lines.push((&status.path, code.replace('.', " ")));
+    }
+    for status in report.unmerged.iter() {
+        lines.push((&status.path, status.code().to_string()));
+    }
+    lines.sort();
+    for (path, code) in lines {
+        println!("{} {}", code, path);
+    }
+    for path in report.untracked.iter() {
+        println!("?? {}", path);
+    }
+}
+
+fn print_porcelain_v2(report: &StatusReport) {
+    let mut lines: Vec<(&String, String)> = vec![];
+    let sha_or_zero = |sha: Option<&Sha>| match sha {
+        Some(sha) => sha.to_string(),
+        None => Sha::empty().to_string(),
+    };
+    for status in report.tracked.iter() {
+        let head_mode = status.head.as_ref().map_or(0, |h| *h.mode());
+        let index_mode = status.index.as_ref().map_or(0, |i| *i.mode());
+        let line = format!(
+            "1 {}{} N... {:06o} {:06o} {:06o} {} {} {}",
+            Change::code(status.staged),
+            Change::code(status.unstaged),
+            head_mode,
+            index_mode,
+            status.worktree_mode,
+            sha_or_zero(status.head.as_ref().map(|h| h.sha())),
+            sha_or_zero(status.index.as_ref().map(|i| i.file_sha())),
+            status.path
+        );
+        lines.push((&status.path, line));
+    }
+    for status in report.unmerged.iter() {
+        let modes: Vec<String> = status
+            .stages
+            .iter()
+            .map(|stage| format!("{:06o}", stage.as_ref().map_or(0, |s| *s.mode())))
+            .collect();
+        let shas: Vec<String> = status
+            .stages
+            .iter()
+            .map(|stage| sha_or_zero(stage.as_ref().map(|s| s.file_sha())))
+            .collect();
+        let line = format!(
+            "u {} N... {} {:06o} {} {}",
+            status.code(),
+            modes.join(" "),
+            status.worktree_mode,
+            shas.join(" "),
+            status.path
+        );
+        lines.push((&status.path, line));
+    }
+    lines.sort();
+    for (_, line) in lines {
+        println!("{}", line);
+    }
+    for path in report.untracked.iter() {
+        println!("? {}", path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::test_repo::TestRepo;
+
+    fn status(repo: &TestRepo, args: &[&str]) -> String {
+        let (code, printed) = repo.flog(&[&["status"], args].concat());
+        assert_eq!(code, 0);
+        printed
+    }
+
+    #[test]
+    fn staged_unstaged_and_untracked() {
+        let repo = TestRepo::new("status_changes");
+        assert_eq!(
+            status(&repo, &[]),
+            "On branch main\n\nNo commits yet\n\nnothing to commit\n"
+        );
+        for (path, contents) in [("a", "1\n"), ("d", "x\n"), ("e", "e\n")] {
+            repo.write_file(path, contents);
+        }
+        repo.flog(&["add", "a", "d", "e"]);
+        repo.flog(&["commit", "-m", "first"]);
+        assert_eq!(
+            status(&repo, &[]),
+            "On branch main\nnothing to commit, working tree clean\n"
+        );
+        assert_eq!(status(&repo, &["--porcelain"]), "");
+
+        repo.write_file("a", "2\n");
+        repo.write_file("n", "n\n");
+        repo.flog(&["add", "n"]);
+        fs::remove_file("d").unwrap();
+        repo.write_file("e", "e2\n");
+        repo.flog(&["add", "e"]);
+        repo.write_file("e", "e3\n");
+        repo.write_file("u", "u\n");
+        // a directory holding nothing tracked shows as just the directory
+        repo.write_file("dir/x", "1");
+        repo.write_file("dir/sub/y", "2");
+        assert_eq!(
+            status(&repo, &[]),
+            "On branch main
+Changes to be committed:
+\tmodified:   e
+\tnew file:   n
+
+Changes not staged for commit:
+\tmodified:   a
+\tdeleted:    d
+\tmodified:   e
+
+Untracked files:
+\tdir/
+\tu
+
+"
+        );
+        assert_eq!(
+            status(&repo, &["--porcelain"]),
+            " M a\n D d\nMM e\nA  n\n?? dir/\n?? u\n"
+        );
+        assert_eq!(
+            status(&repo, &["--porcelain=v2"]),
+            "1 .M N... 100644 100644 100644 d00491fd7e5bb6fa28c517a0bb32b8b506539d4d d00491fd7e5bb6fa28c517a0bb32b8b506539d4d a
+1 .D N... 100644 100644 000000 587be6b4c3f93f93c489c0111bba5596147a26cb 587be6b4c3f93f93c489c0111bba5596147a26cb d
+1 MM N... 100644 100644 100644 d905d9da82c97264ab6f4920e20242e088850ce9 3811af3ca744c2fb44077a8025c23b4d4166a449 e
+1 A. N... 000000 100644 100644 0000000000000000000000000000000000000000 8ba3a16384aacc37d01564b28401755ce8053f51 n
+? dir/
+? u
+"
+        );
+    }
+
+    #[test]
+    fn unmerged_paths() {
+        let repo = TestRepo::new("status_unmerged");
+        for (path, contents) in [("m", "base\n"), ("g", "gone\n")] {
+            repo.write_file(path, contents);
+        }
+        repo.flog(&["add", "m", "g"]);
+        repo.flog(&["commit", "-m", "base"]);
+        repo.flog(&["branch", "other"]);
+        repo.write_file("m", "ours\n");
+        fs::remove_file("g").unwrap();
+        repo.flog(&["update-index", "--remove", "g"]);
+        repo.flog(&["add", "m"]);
+        repo.flog(&["commit", "-m", "ours"]);
+        repo.flog(&["checkout", "other"]);
+        for (path, contents) in [("m", "theirs\n"), ("g", "changed\n"), ("both", "new\n")] {
+            repo.write_file(path, contents);
+        }
+        repo.flog(&["add", "m", "g", "both"]);
+        repo.flog(&["commit", "-m", "theirs"]);
+        repo.flog(&["checkout", "main"]);
+        repo.write_file("both", "mine\n");
+        repo.flog(&["add", "both"]);
+        repo.flog(&["commit", "-m", "both"]);
+        assert_eq!(repo.flog(&["merge", "other"]).0, 1);
+
+        assert_eq!(
+            status(&repo, &[]),
+            "On branch main
+You have unmerged paths.
+
+Unmerged paths:
+\tboth added:      both
+\tdeleted by us:   g
+\tboth modified:   m
+
+no changes added to commit
+"
+        );
+        assert_eq!(status(&repo, &["--porcelain"]), "AA both\nDU g\nUU m\n");
+        assert_eq!(
+            status(&repo, &["--porcelain=v2"]),
+            "u AA N... 000000 100644 100644 100644 0000000000000000000000000000000000000000 351be5bf6e17c59ea560546d69654115ecb2fd8d 3e757656cf36eca53338e520d134963a44f793f8 both
+u DU N... 100644 000000 100644 100644 286c5f5776916d7d7d5849988ca9d83e722cf9c2 0000000000000000000000000000000000000000 5ea2ed416fbd4a4cbe227b75fe255dd7fa6bd4d6 g
+u UU N... 100644 100644 100644 100644 df967b96a579e45a18b8251732d16804b2e56a55 b19a1e93bec1317dc6097229e12afaffbfa74dc2 950b81b7eee953d050aa05a641f8e056c85dd1bd m
+"
+        );
+
+        repo.flog(&["add", "m", "g", "both"]);
+        assert_eq!(
+            status(&repo, &[]),
+            "On branch main
+All conflicts fixed but you are still merging.
+
+Changes to be committed:
+\tmodified:   both
+\tnew file:   g
+\tmodified:   m
+
+"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    io::{self, stdin, Read},
+    path::Path,
+};
+
+use log::info;
+
+use crate::{
+    exit_with_message,
+    revision::try_resolve_revision,
+    structures::{check_file_is_of_kind, GitObject, RefFile, Sha, Tag},
+    utils::{get_head_sha, is_valid_ref_name, wildcard_match},
+};
+
+/// Lists tags, only those matching the pattern if there is one
+pub fn tag_list(pattern: &Option<String>) -> Result<(), io::Error> {
+    for reff in RefFile::list("refs/tags")? {
+        let name = reff.short_name();
+        let matches = match pattern {
+            Some(pattern) => wildcard_match(pattern, name),
+            None => true,
+        };
+        if matches {
+            println!("{}", name);
+        }
+    }
+    Ok(())
+}
+
+/// Creates a tag at the object, or at HEAD when there isn't one. With a message the tag is
+/// annotated and gets its own tag object, otherwise the ref points straight at the object
+pub fn tag_create(
+    name: &str,
+    object: &Option<String>,
+    annotate: bool,
+    message: &Option<Vec<String>>,
+    force: bool,
+) -> Result<(), io::Error> {
+    if !is_valid_ref_name(name) {
+        let msg = format!("fatal: '{}' is not a valid tag name.", name);
+        exit_with_message(&msg);
+    }
+    let reff = format!("refs/tags/{}", name);
+    if !force && Path::new(".re_flogged").join(&reff).is_file() {
+        let msg = format!("fatal: tag '{}' already exists", name);
+        exit_with_message(&msg);
+    }
+    let target = match object {
+        Some(object) => match try_resolve_revision(object)? {
+            Some(sha) => sha,
+            None => {
+                let msg = format!("fatal: Failed to resolve '{}' as a valid ref.", object);
+                exit_with_message(&msg);
+            }
+        },
+        None => match get_head_sha()? {
+            Some(sha) => sha,
+            None => exit_with_message("fatal: Failed to resolve 'HEAD' as a valid ref."),
+        },
+    };
+    let sha = match annotate || message.is_some() {
+        true => {
+            let message = match message {
+                Some(messages) => messages.join("\n\n"),
+                None => {
+                    let mut message = String::new();
+                    stdin().read_to_string(&mut message)?;
+                    message
+                }
+            };
+            let mut tag = Tag::new(&target, object_type(&target), name, &message);
+            tag.write_to_disk()?;
+            tag.get_sha().clone()
+        }
+        false => target,
+    };
+    info!("Tagging {} as {}", sha, name);
+    RefFile::new(&reff, &sha).write()?;
+    Ok(())
+}
+
+/// Deletes tags
+pub fn tag_delete(names: &[String]) -> Result<(), io::Error> {
+    for name in names.iter() {
+        let reff = format!("refs/tags/{}", name);
+        if !Path::new(".re_flogged").join(&reff).is_file() {
+            let msg = format!("error: tag '{}' not found.", name);
+            exit_with_message(&msg);
+        }
+        let ref_file = RefFile::new_from_branch(&reff)?;
+        ref_file.delete()?;
+        println!(
+            "Deleted tag '{}' (was {})",
+            name,
+            &ref_file.sha().to_string()[..7]
+        );
+    }
+    Ok(())
+}
+
+fn object_type(sha: &Sha) -> &'static str {
+    ["commit", "tree", "blob", "tag"]
+        .iter()
+        .find(|kind| check_file_is_of_kind(sha, kind))
+        .copied()
+        .unwrap_or("commit")
+}
@@ -1,32 +1,57 @@
 use std::io;
 
-use crate::structures::{GitObject, IndexFile, Sha, Tree};
+use crate::{
+    exit_with_message,
+    structures::{object_exists, IndexFile, Sha, Tree, TreeEntry},
+};
 
 pub fn write_tree(missing_ok: bool) -> Result<Sha, io::Error> {
     let idx_file = IndexFile::from_disk()?;
-    let mut root_tree = Tree::empty();
-    for entry in idx_file.index_entries() {
-        let slash_count = entry
-            .file_name()
-            .iter()
-            .filter(|&&b| b as char == '/')
-            .count();
-        if slash_count < 1 {
-            root_tree.add_blob_from_index(entry);
-        } else {
-            root_tree.add_tree_from_index(entry, missing_ok);
+    let sha = write_index_tree(&idx_file, missing_ok)?;
+    println!("{}", sha);
+    Ok(sha)
+}
+
+/// Writes the trees for what is staged in the index and returns the root tree's sha. The
+/// blobs come from the index rather than the working tree, so unstaged changes are left out
+pub fn write_index_tree(idx_file: &IndexFile, missing_ok: bool) -> Result<Sha, io::Error> {
+    let mut entries = vec![];
+    for entry in idx_file.index_entries().iter() {
+        let name = entry.get_readable_file_name();
+        if entry.stage() != 0 {
+            let msg = format!(
+                "error: {}: unmerged ({})\nfatal: flog-write-tree: error building trees",
+                name,
+                entry.file_sha()
+            );
+            exit_with_message(&msg);
+        }
+        if !missing_ok && !object_exists(entry.file_sha()) {
+            let msg = format!(
+                "error: invalid object {:o} {} for '{}'\nfatal: flog-write-tree: error building trees",
+                entry.mode(),
+                entry.file_sha(),
+                name
+            );
+            exit_with_message(&msg);
         }
+        entries.push(TreeEntry::new(
+            name,
+            *entry.mode(),
+            entry.file_sha().clone(),
+        ));
     }
-    root_tree.write_to_disk()?;
-    Ok(root_tree.root().sha().clone())
+    Tree::write_from_entries(&entries)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_repo::lock_cwd;
 
     #[test]
     fn w() {
+        let _cwd = lock_cwd();
         write_tree(false);
         assert_eq!(true, false);
     }
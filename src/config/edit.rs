@@ -0,0 +1,169 @@
+use std::{fs, io, path::Path, process};
+
+use crate::exit_with_message;
+
+use super::{
+    format_header, format_value, parse_config, parse_config_file, split_name, ParsedConfig,
+};
+
+/// What `change_config_file` does to the values set for a name
+#[derive(Clone, Copy, Debug)]
+pub enum ConfigChange<'a> {
+    /// Replaces the value, or adds it when there is none. Refuses when there are several
+    Set(&'a str),
+    /// Adds another value after those already set
+    Add(&'a str),
+    /// Replaces every value with the one given
+    ReplaceAll(&'a str),
+    /// Removes the value. Refuses when there are several
+    Unset,
+    /// Removes every value
+    UnsetAll,
+}
+
+/// Changes the values for a name in a config file, leaving the rest of the file as it was.
+/// New entries go after the last entry of the name's section, or in a new section at the end.
+/// Exits with status 5, as git does, when there is nothing to unset or a single value is
+/// asked for where there are several
+pub fn change_config_file(path: &Path, name: &str, change: ConfigChange) -> io::Result<()> {
+    let split =
+        split_name(name).unwrap_or_else(|msg| exit_with_message(&format!("error: {}", msg)));
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let parsed = parse_config_file(&text, path)?;
+    let matching: Vec<_> = parsed
+        .entries()
+        .iter()
+        .filter(|entry| entry.is_named(&split))
+        .collect();
+    let single = matches!(change, ConfigChange::Set(_) | ConfigChange::Unset);
+    if single && matching.len() > 1 {
+        println!("warning: {} has multiple values", name);
+        if let ConfigChange::Set(_) = change {
+            println!("error: cannot overwrite multiple values with a single value");
+            println!(
+                "       Use a regexp, --add or --replace-all to change {}.",
+                name
+            );
+        }
+        process::exit(5);
+    }
+    let unsetting = matches!(change, ConfigChange::Unset | ConfigChange::UnsetAll);
+    if unsetting && matching.is_empty() {
+        process::exit(5);
+    }
+
+    let mut lines: Vec<Option<String>> = text.lines().map(|line| Some(line.to_string())).collect();
+    let header_lines: Vec<usize> = parsed.sections().iter().map(|s| *s.line()).collect();
+    let removed = match change {
+        ConfigChange::Add(_) => &[][..],
+        ConfigChange::Set(_) if matching.is_empty() => &[][..],
+        _ => &matching[..],
+    };
+    for entry in removed.iter() {
+        lines[*entry.line()..entry.line() + entry.line_count()].fill(None);
+        // an entry sharing a line with its header leaves the header behind
+        if header_lines.contains(entry.line()) {
+            lines[*entry.line()] = Some(format_header(entry.section(), entry.subsection()));
+        }
+    }
+
+    let key = &name[name.rfind('.').unwrap() + 1..];
+    let new_entry = |value: &str| format!("\t{} = {}", key, format_value(value));
+    match change {
+        ConfigChange::Set(value) | ConfigChange::ReplaceAll(value) if !matching.is_empty() => {
+            // the new value takes the place of the last old one
+            let at = *matching.last().unwrap().line();
+            lines[at] = match lines[at].take() {
+                Some(header) => Some(format!("{}\n{}", header, new_entry(value))),
+                None => Some(new_entry(value)),
+            };
+        }
+        ConfigChange::Set(value) | ConfigChange::ReplaceAll(value) | ConfigChange::Add(value) => {
+            match section_end(&parsed, &split.0, &split.1) {
+                Some(end) => {
+                    let line = lines[end].take().unwrap_or_default();
+                    lines[end] = Some(format!("{}\n{}", line, new_entry(value)));
+                }
+                None => {
+                    let section = &name[..name.find('.').unwrap()];
+                    lines.push(Some(format_header(section, &split.1)));
+                    lines.push(Some(new_entry(value)));
+                }
+            }
+        }
+        ConfigChange::Unset | ConfigChange::UnsetAll => {
+            remove_empty_sections(&mut lines, &header_lines, &split.0, &split.1)
+        }
+    }
+
+    let mut contents = String::new();
+    for line in lines.iter().flatten() {
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    fs::write(path, contents)
+}
+
+/// The line new entries for the section go after: the last line of its last entry, or the
+/// header of the last such section when it has no entries
+fn section_end(parsed: &ParsedConfig, section: &str, subsection: &Option<String>) -> Option<usize> {
+    let header = parsed
+        .sections()
+        .iter()
+        .enumerate()
+        .rfind(|(_, s)| s.section() == section && s.subsection() == subsection)?;
+    let start = *header.1.line();
+    let next = parsed.sections().get(header.0 + 1).map(|s| *s.line());
+    let last_entry = parsed
+        .entries()
+        .iter()
+        .rfind(|entry| *entry.line() >= start && next.is_none_or(|next| *entry.line() < next));
+    Some(last_entry.map_or(start, |entry| entry.line() + entry.line_count() - 1))
+}
+
+/// Drops the headers of the section that nothing but blank lines are left under. Like git,
+/// a section with a comment just above it is kept, as the comment may be about the section
+fn remove_empty_sections(
+    lines: &mut [Option<String>],
+    header_lines: &[usize],
+    section: &str,
+    subsection: &Option<String>,
+) {
+    for (index, &start) in header_lines.iter().enumerate() {
+        let header = match lines[start].as_deref().map(parse_config) {
+            Some(Ok(header)) => header,
+            _ => continue,
+        };
+        let matches = header
+            .sections()
+            .first()
+            .is_some_and(|s| s.section() == section && s.subsection() == subsection);
+        let end = header_lines.get(index + 1).copied().unwrap_or(lines.len());
+        let commented = lines[..start]
+            .iter()
+            .flatten()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .is_some_and(|line| line.trim_start().starts_with(['#', ';']));
+        let empty = header.entries().is_empty()
+            && !commented
+            && lines[start]
+                .as_deref()
+                .unwrap_or_default()
+                .trim_end()
+                .ends_with(']')
+            && lines[start + 1..end]
+                .iter()
+                .flatten()
+                .all(|line| line.trim().is_empty());
+        if matches && empty {
+            for line in lines[start..end].iter_mut() {
+                *line = None;
+            }
+        }
+    }
+}
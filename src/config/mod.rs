@@ -0,0 +1,7 @@
+mod edit;
+mod parse;
+mod scopes;
+
+pub use edit::*;
+pub use parse::*;
+pub use scopes::*;
@@ -0,0 +1,348 @@
+use std::{io, path::Path};
+
+use derive_getters::Getters;
+
+/// A `[section]` or `[section "subsection"]` header and the line it is on
+#[derive(Clone, Debug, Getters)]
+pub struct ConfigSection {
+    /// Lowercased, as section names are case insensitive
+    section: String,
+    subsection: Option<String>,
+    line: usize,
+}
+
+/// One `key = value` line of a config file, with where it sits so it can be edited in place
+#[derive(Clone, Debug, Getters)]
+pub struct ConfigEntry {
+    section: String,
+    subsection: Option<String>,
+    /// Lowercased, as keys are case insensitive
+    key: String,
+    /// None for a key on its own, which is a boolean set to true
+    value: Option<String>,
+    /// The first line of the entry, counting from 0
+    line: usize,
+    /// How many lines the entry spans, more than one when it ends in a backslash
+    line_count: usize,
+}
+
+impl ConfigEntry {
+    /// The name the entry is looked up by, like `remote.origin.url`
+    pub fn name(&self) -> String {
+        join_name(&self.section, &self.subsection, &self.key)
+    }
+
+    /// Whether the entry sets the name, given as `split_name` returns it
+    pub fn is_named(&self, name: &(String, Option<String>, String)) -> bool {
+        self.section == name.0 && self.subsection == name.1 && self.key == name.2
+    }
+}
+
+/// The sections and entries of a config file, in the order they appear
+#[derive(Clone, Debug, Default, Getters)]
+pub struct ParsedConfig {
+    sections: Vec<ConfigSection>,
+    entries: Vec<ConfigEntry>,
+}
+
+/// Parses a config file in git's syntax. Fails with the number of the first bad line,
+/// counting from 1 as git reports it
+pub fn parse_config(text: &str) -> Result<ParsedConfig, usize> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut parsed = ParsedConfig::default();
+    let mut current: Option<(String, Option<String>)> = None;
+    let mut number = 0;
+    while number < lines.len() {
+        let mut rest = lines[number].trim_start();
+        if rest.starts_with('[') {
+            let (section, subsection, after) = parse_header(rest).ok_or(number + 1)?;
+            parsed.sections.push(ConfigSection {
+                section: section.clone(),
+                subsection: subsection.clone(),
+                line: number,
+            });
+            current = Some((section, subsection));
+            // an entry may follow the header on the same line
+            rest = after.trim_start();
+        }
+        if rest.is_empty() || rest.starts_with('#') || rest.starts_with(';') {
+            number += 1;
+            continue;
+        }
+        let (section, subsection) = current.clone().ok_or(number + 1)?;
+        let key_len = rest
+            .find(|ch: char| !ch.is_ascii_alphanumeric() && ch != '-')
+            .unwrap_or(rest.len());
+        let key = &rest[..key_len];
+        if !key.starts_with(|ch: char| ch.is_ascii_alphabetic()) {
+            return Err(number + 1);
+        }
+        let after = rest[key_len..].trim_start();
+        let start = number;
+        let value = match after.chars().next() {
+            None | Some('#') | Some(';') => None,
+            Some('=') => Some(parse_value(&after[1..], &lines, &mut number)?),
+            Some(_) => return Err(number + 1),
+        };
+        parsed.entries.push(ConfigEntry {
+            section,
+            subsection,
+            key: key.to_lowercase(),
+            value,
+            line: start,
+            line_count: number - start + 1,
+        });
+        number += 1;
+    }
+    Ok(parsed)
+}
+
+/// Parses the text of a config file, failing with the bad line and file as git reports them
+pub fn parse_config_file(text: &str, path: &Path) -> Result<ParsedConfig, io::Error> {
+    parse_config(text).map_err(|line| {
+        let msg = format!("bad config line {} in file {}", line, path.display());
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    })
+}
+
+/// Splits a name like `remote.origin.url` into its lowercased section, its subsection and
+/// its lowercased key, failing with the message git gives for names it won't take
+pub fn split_name(name: &str) -> Result<(String, Option<String>, String), String> {
+    let (first, key) = match (name.find('.'), name.rfind('.')) {
+        (Some(_), Some(last)) if last + 1 < name.len() => (&name[..last], &name[last + 1..]),
+        _ => return Err(format!("key does not contain a section: {}", name)),
+    };
+    let (section, subsection) = match first.split_once('.') {
+        Some((section, subsection)) => (section, Some(subsection.to_string())),
+        None => (first, None),
+    };
+    let valid_section = !section.is_empty()
+        && section
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-');
+    let valid_key = key.starts_with(|ch: char| ch.is_ascii_alphabetic())
+        && key
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-');
+    if !valid_section || !valid_key || name.contains('\n') {
+        return Err(format!("invalid key: {}", name));
+    }
+    Ok((section.to_lowercase(), subsection, key.to_lowercase()))
+}
+
+/// The name for a section, subsection and key, like `remote.origin.url`
+pub fn join_name(section: &str, subsection: &Option<String>, key: &str) -> String {
+    match subsection {
+        Some(subsection) => format!("{}.{}.{}", section, subsection, key),
+        None => format!("{}.{}", section, key),
+    }
+}
+
+/// Reads a value as a boolean the way git does: a key on its own, `true`, `yes`, `on` and
+/// any non-zero number are true, and `false`, `no`, `off`, an empty value and 0 are false
+pub fn parse_bool(value: Option<&str>) -> Option<bool> {
+    let value = match value {
+        Some(value) => value.to_lowercase(),
+        None => return Some(true),
+    };
+    match value.as_str() {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" | "" => Some(false),
+        _ => parse_int(&value).map(|number| number != 0),
+    }
+}
+
+/// Reads a value as a number, which may end in `k`, `m` or `g` to multiply it by 1024 once,
+/// twice or three times
+pub fn parse_int(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (digits, factor) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 1 << 10),
+        'm' => (&value[..value.len() - 1], 1 << 20),
+        'g' => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+    digits.parse::<i64>().ok()?.checked_mul(factor)
+}
+
+/// The header line for a section, escaping the subsection so it reads back the same
+pub fn format_header(section: &str, subsection: &Option<String>) -> String {
+    match subsection {
+        Some(subsection) => {
+            let escaped = subsection.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("[{} \"{}\"]", section, escaped)
+        }
+        None => format!("[{}]", section),
+    }
+}
+
+/// A value as it is written to a file: escaped, and quoted when it has spaces at either end
+/// or characters that would otherwise start a comment
+pub fn format_value(value: &str) -> String {
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';']);
+    let mut escaped = String::new();
+    for ch in value.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{8}' => escaped.push_str("\\b"),
+            _ => escaped.push(ch),
+        }
+    }
+    match needs_quotes {
+        true => format!("\"{}\"", escaped),
+        false => escaped,
+    }
+}
+
+/// Reads a header starting at `[`, returning the section, the subsection and whatever
+/// follows the closing bracket. The old `[section.subsection]` form is taken too, with the
+/// subsection lowercased as git does
+fn parse_header(line: &str) -> Option<(String, Option<String>, &str)> {
+    let inner = &line[1..];
+    let name_len = inner.find(|ch: char| !ch.is_ascii_alphanumeric() && ch != '-' && ch != '.')?;
+    let name = &inner[..name_len];
+    if name.is_empty() {
+        return None;
+    }
+    let after_name = &inner[name_len..];
+    if let Some(after) = after_name.strip_prefix(']') {
+        return Some(match name.split_once('.') {
+            Some((section, subsection)) => (
+                section.to_lowercase(),
+                Some(subsection.to_lowercase()),
+                after,
+            ),
+            None => (name.to_lowercase(), None, after),
+        });
+    }
+    if name.contains('.') {
+        return None;
+    }
+    let quoted = after_name.trim_start().strip_prefix('"')?;
+    let mut subsection = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((index, ch)) = chars.next() {
+        match ch {
+            '"' => {
+                let after = quoted[index + 1..].strip_prefix(']')?;
+                return Some((name.to_lowercase(), Some(subsection), after));
+            }
+            // any other escaped character stands for itself
+            '\\' => subsection.push(chars.next()?.1),
+            _ => subsection.push(ch),
+        }
+    }
+    None
+}
+
+/// Reads the value after `=`, following backslash continuations onto the lines after it.
+/// `number` is left on the last line the value uses
+fn parse_value(start: &str, lines: &[&str], number: &mut usize) -> Result<String, usize> {
+    let mut value = String::new();
+    // whitespace outside quotes only counts when something follows it
+    let mut spaces = String::new();
+    let mut quoted = false;
+    let mut line = start.trim_start();
+    loop {
+        let mut chars = line.chars();
+        let mut continued = false;
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' => {
+                    let escaped = match chars.next() {
+                        None => {
+                            continued = true;
+                            break;
+                        }
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some(ch @ ('\\' | '"')) => ch,
+                        Some(_) => return Err(*number + 1),
+                    };
+                    value.push_str(&spaces);
+                    spaces.clear();
+                    value.push(escaped);
+                }
+                '"' => quoted = !quoted,
+                '#' | ';' if !quoted => break,
+                ch if ch.is_whitespace() && !quoted => {
+                    if !value.is_empty() {
+                        spaces.push(' ');
+                    }
+                }
+                ch => {
+                    value.push_str(&spaces);
+                    spaces.clear();
+                    value.push(ch);
+                }
+            }
+        }
+        if !continued {
+            break;
+        }
+        *number += 1;
+        line = lines.get(*number).ok_or(*number)?;
+    }
+    match quoted {
+        true => Err(*number + 1),
+        false => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sections_quoting_and_continuations() {
+        let text = "[Core]\n\tBare = false\n[remote \"Origin\"]\n\turl = \"a b\" ; note\n\
+                    [x.Y] flag\n\tlong = one \\\n  two\n\tspaced = \" padded \"\n";
+        let parsed = parse_config(text).unwrap();
+        let names: Vec<String> = parsed.entries().iter().map(|e| e.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "core.bare",
+                "remote.Origin.url",
+                "x.y.flag",
+                "x.y.long",
+                "x.y.spaced"
+            ]
+        );
+        let values: Vec<Option<&str>> = parsed
+            .entries()
+            .iter()
+            .map(|e| e.value().as_deref())
+            .collect();
+        assert_eq!(
+            values,
+            [
+                Some("false"),
+                Some("a b"),
+                None,
+                Some("one   two"),
+                Some(" padded ")
+            ]
+        );
+        assert_eq!(*parsed.entries()[3].line_count(), 2);
+        assert_eq!(parse_config("[a]\n\tb = \"open\n").err(), Some(2));
+    }
+
+    #[test]
+    fn reads_booleans_and_numbers() {
+        assert_eq!(parse_bool(None), Some(true));
+        assert_eq!(parse_bool(Some("Off")), Some(false));
+        assert_eq!(parse_bool(Some("2k")), Some(true));
+        assert_eq!(parse_bool(Some("maybe")), None);
+        assert_eq!(parse_int("2k"), Some(2048));
+        assert_eq!(parse_int("1G"), Some(1 << 30));
+        assert_eq!(parse_int("2x"), None);
+        assert_eq!(format_value("  spaced; # x"), "\"  spaced; # x\"");
+    }
+}
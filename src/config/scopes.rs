@@ -0,0 +1,320 @@
+use std::{
+    cell::RefCell,
+    env, fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use derive_getters::Getters;
+use log::debug;
+
+use crate::{exit_with_message, utils::wildcard_match};
+
+use super::{parse_bool, parse_config_file, parse_int, split_name};
+
+/// Git gives up on includes nested deeper than this, which is usually a file including itself
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+thread_local! {
+    static CONFIG: RefCell<Option<Rc<Config>>> = const { RefCell::new(None) };
+}
+
+/// Where a setting comes from. Each scope overrides the ones before it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigScope {
+    System,
+    Global,
+    Local,
+    Worktree,
+    /// A file named with `config --file`
+    File,
+}
+
+impl ConfigScope {
+    /// How `config --show-scope` names the scope
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConfigScope::System => "system",
+            ConfigScope::Global => "global",
+            ConfigScope::Local => "local",
+            ConfigScope::Worktree => "worktree",
+            ConfigScope::File => "command",
+        }
+    }
+
+    /// The files read for the scope, in the order they are read. The system file is skipped
+    /// when `GIT_CONFIG_NOSYSTEM` is set, and `GIT_CONFIG_SYSTEM` and `GIT_CONFIG_GLOBAL` stand
+    /// in for the usual files
+    pub fn read_paths(&self) -> Vec<PathBuf> {
+        match self {
+            ConfigScope::System if env::var_os("GIT_CONFIG_NOSYSTEM").is_some() => vec![],
+            ConfigScope::Global if env::var_os("GIT_CONFIG_GLOBAL").is_some() => {
+                self.write_path().into_iter().collect()
+            }
+            ConfigScope::Global => {
+                let xdg = env::var_os("XDG_CONFIG_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".config")))
+                    .map(|dir| dir.join("git/config"));
+                xdg.into_iter().chain(self.write_path()).collect()
+            }
+            ConfigScope::Worktree if !worktree_config_enabled() => vec![],
+            _ => self.write_path().into_iter().collect(),
+        }
+    }
+
+    /// The file `config` changes for the scope. The worktree scope shares the repository's
+    /// file until `extensions.worktreeConfig` is turned on
+    pub fn write_path(&self) -> Option<PathBuf> {
+        match self {
+            ConfigScope::System => Some(
+                env::var_os("GIT_CONFIG_SYSTEM")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| PathBuf::from("/etc/gitconfig")),
+            ),
+            ConfigScope::Global => env::var_os("GIT_CONFIG_GLOBAL")
+                .map(PathBuf::from)
+                .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".gitconfig"))),
+            ConfigScope::Local => Some(PathBuf::from(".re_flogged/config")),
+            ConfigScope::Worktree if worktree_config_enabled() => {
+                Some(PathBuf::from(".re_flogged/config.worktree"))
+            }
+            ConfigScope::Worktree => Some(PathBuf::from(".re_flogged/config")),
+            ConfigScope::File => None,
+        }
+    }
+}
+
+/// A setting read from a config file
+#[derive(Clone, Debug, Getters)]
+pub struct ConfigValue {
+    /// Like `remote.origin.url`, with the section and key lowercased
+    name: String,
+    /// None for a key on its own, which is a boolean set to true
+    value: Option<String>,
+    scope: ConfigScope,
+    /// The file it was read from, which is the included one for included settings
+    origin: PathBuf,
+}
+
+/// Every setting from a set of config files, in the order git reads them, so that the last
+/// value for a name is the one that counts
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    values: Vec<ConfigValue>,
+}
+
+impl Config {
+    /// The settings from every scope, read once and kept until `reload`
+    pub fn all() -> Result<Rc<Config>, io::Error> {
+        if let Some(config) = CONFIG.with(|cached| cached.borrow().clone()) {
+            return Ok(config);
+        }
+        let scopes = [
+            ConfigScope::System,
+            ConfigScope::Global,
+            ConfigScope::Local,
+            ConfigScope::Worktree,
+        ];
+        let config = Rc::new(Config::from_scopes(&scopes, true)?);
+        CONFIG.with(|cached| *cached.borrow_mut() = Some(config.clone()));
+        Ok(config)
+    }
+
+    /// Forgets the settings read by `all`, so that changes to the files are seen
+    pub fn reload() {
+        CONFIG.with(|cached| *cached.borrow_mut() = None);
+    }
+
+    /// The settings from the scopes' files, and the files they include when `includes` is
+    /// set. Files that don't exist are skipped
+    pub fn from_scopes(scopes: &[ConfigScope], includes: bool) -> Result<Config, io::Error> {
+        let mut config = Config::default();
+        for scope in scopes.iter() {
+            for path in scope.read_paths() {
+                config.read_file(&path, *scope, includes, 0)?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// The settings from one file, and the files it includes when `includes` is set
+    pub fn from_file(path: &Path, scope: ConfigScope, includes: bool) -> Result<Config, io::Error> {
+        let mut config = Config::default();
+        config.read_file(path, scope, includes, 0)?;
+        Ok(config)
+    }
+
+    pub fn values(&self) -> &[ConfigValue] {
+        &self.values
+    }
+
+    /// The value that counts for the name, which is the last one set
+    pub fn get(&self, name: &str) -> Option<&ConfigValue> {
+        self.get_all(name).pop()
+    }
+
+    /// Every value set for the name, in the order they were read
+    pub fn get_all(&self, name: &str) -> Vec<&ConfigValue> {
+        let name = match split_name(name) {
+            Ok(name) => name,
+            Err(_) => return vec![],
+        };
+        self.values
+            .iter()
+            .filter(|value| split_name(&value.name).as_ref() == Ok(&name))
+            .collect()
+    }
+
+    /// Reads a file's settings, along with those of the files it includes straight after the
+    /// line including them
+    fn read_file(
+        &mut self,
+        path: &Path,
+        scope: ConfigScope,
+        includes: bool,
+        depth: usize,
+    ) -> io::Result<()> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let parsed = parse_config_file(&text, path)?;
+        for entry in parsed.entries().iter() {
+            self.values.push(ConfigValue {
+                name: entry.name(),
+                value: entry.value().clone(),
+                scope,
+                origin: path.to_path_buf(),
+            });
+            let included = match (entry.key().as_str(), entry.value()) {
+                ("path", Some(included)) => included,
+                _ => continue,
+            };
+            let applies = match (entry.section().as_str(), entry.subsection()) {
+                ("include", None) => true,
+                ("includeif", Some(condition)) => include_condition_holds(condition, path),
+                _ => false,
+            };
+            if !includes || !applies {
+                continue;
+            }
+            if depth >= MAX_INCLUDE_DEPTH {
+                let msg = format!(
+                    "exceeded maximum include depth ({}) while including\n\t{}\nfrom\n\t{}\nThis might be due to circular includes.",
+                    MAX_INCLUDE_DEPTH,
+                    included,
+                    path.display()
+                );
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            }
+            self.read_file(&relative_to(path, included), scope, true, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// A setting as a string, or None when it isn't set. A key on its own reads as `true`.
+/// Exits when the config files can't be read
+pub fn get_config_value(name: &str) -> Option<String> {
+    let config = Config::all().unwrap_or_else(|e| exit_with_message(&format!("fatal: {}", e)));
+    let found = config
+        .get(name)
+        .map(|value| value.value.clone().unwrap_or_else(|| String::from("true")));
+    debug!("Config {} is {:?}", name, found);
+    found
+}
+
+/// A setting read as a boolean, or None when it isn't set. Exits when it isn't one
+pub fn get_config_bool(name: &str) -> Option<bool> {
+    let config = Config::all().unwrap_or_else(|e| exit_with_message(&format!("fatal: {}", e)));
+    let value = config.get(name)?;
+    match parse_bool(value.value.as_deref()) {
+        Some(flag) => Some(flag),
+        None => {
+            let msg = format!(
+                "fatal: bad boolean config value '{}' for '{}'",
+                value.value.as_deref().unwrap_or_default(),
+                name
+            );
+            exit_with_message(&msg);
+        }
+    }
+}
+
+/// A setting read as a number, with any `k`, `m` or `g` suffix applied, or None when it
+/// isn't set. Exits when it isn't one
+pub fn get_config_int(name: &str) -> Option<i64> {
+    let config = Config::all().unwrap_or_else(|e| exit_with_message(&format!("fatal: {}", e)));
+    let value = config.get(name)?;
+    match value.value.as_deref().and_then(parse_int) {
+        Some(number) => Some(number),
+        None => {
+            let msg = format!(
+                "fatal: bad numeric config value '{}' for '{}' in file {}: invalid unit",
+                value.value.as_deref().unwrap_or_default(),
+                name,
+                value.origin.display()
+            );
+            exit_with_message(&msg);
+        }
+    }
+}
+
+/// Whether the repository keeps per-worktree settings in their own file
+fn worktree_config_enabled() -> bool {
+    Config::from_file(Path::new(".re_flogged/config"), ConfigScope::Local, true)
+        .ok()
+        .and_then(|config| {
+            let value = config.get("extensions.worktreeConfig")?;
+            parse_bool(value.value.as_deref())
+        })
+        .unwrap_or(false)
+}
+
+/// Whether an `includeIf` applies. Only `gitdir:` and the case insensitive `gitdir/i:` are
+/// understood, and other conditions never hold
+fn include_condition_holds(condition: &str, including: &Path) -> bool {
+    let (pattern, fold_case) = match condition.split_once(':') {
+        Some(("gitdir", pattern)) => (pattern, false),
+        Some(("gitdir/i", pattern)) => (pattern, true),
+        _ => return false,
+    };
+    let git_dir = match fs::canonicalize(".re_flogged") {
+        Ok(dir) => dir.to_string_lossy().to_string(),
+        Err(_) => return false,
+    };
+    let mut pattern = match (pattern.strip_prefix("~/"), pattern.strip_prefix("./")) {
+        (Some(home), _) => match env::var("HOME") {
+            Ok(dir) => format!("{}/{}", dir, home),
+            Err(_) => return false,
+        },
+        (_, Some(_)) => relative_to(including, pattern)
+            .to_string_lossy()
+            .to_string(),
+        _ if pattern.starts_with('/') => pattern.to_string(),
+        // a pattern that isn't anchored can match anywhere
+        _ => format!("**/{}", pattern),
+    };
+    // a directory matches everything inside it
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+    match fold_case {
+        true => wildcard_match(&pattern.to_lowercase(), &git_dir.to_lowercase()),
+        false => wildcard_match(&pattern, &git_dir),
+    }
+}
+
+/// An included path, which is relative to the directory of the file including it
+fn relative_to(including: &Path, path: &str) -> PathBuf {
+    let path = match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    };
+    match including.parent() {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path,
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use super::{intern_lines, myers_diff_range, Edit};
+
+/// Lines that turn up more often than this on the old side aren't used as anchors
+const MAX_CHAIN_LENGTH: usize = 64;
+
+/// Line diff using the histogram algorithm, git's extension of patience. Rather than needing
+/// lines to be unique it anchors on the common run whose rarest line is least common on the
+/// old side, preferring longer runs, then diffs either side of it the same way. Regions where
+/// every line is too common fall back to Myers
+pub fn histogram_diff(old: &[&[u8]], new: &[&[u8]]) -> Vec<Edit> {
+    let (a, b) = intern_lines(old, new);
+    let mut edits = Vec::with_capacity(a.len().max(b.len()));
+    histogram_range(&a, &b, (0, 0), (a.len(), b.len()), &mut edits);
+    edits
+}
+
+/// The common run chosen to split a region on
+struct Anchor {
+    start: (usize, usize),
+    length: usize,
+    rarity: usize,
+}
+
+fn histogram_range(
+    a: &[u32],
+    b: &[u32],
+    top_left: (usize, usize),
+    bottom_right: (usize, usize),
+    edits: &mut Vec<Edit>,
+) {
+    let (mut left, mut top) = top_left;
+    let (mut right, mut bottom) = bottom_right;
+    while left < right && top < bottom && a[left] == b[top] {
+        edits.push(Edit::Equal(left, top));
+        left += 1;
+        top += 1;
+    }
+    let mut suffix = 0;
+    while left < right && top < bottom && a[right - 1] == b[bottom - 1] {
+        right -= 1;
+        bottom -= 1;
+        suffix += 1;
+    }
+    if left == right {
+        edits.extend((top..bottom).map(Edit::Insert));
+    } else if top == bottom {
+        edits.extend((left..right).map(Edit::Delete));
+    } else {
+        match find_anchor(a, b, (left, top), (right, bottom)) {
+            Some(anchor) => {
+                let (x, y) = anchor.start;
+                histogram_range(a, b, (left, top), (x, y), edits);
+                edits.extend((0..anchor.length).map(|i| Edit::Equal(x + i, y + i)));
+                let after = (x + anchor.length, y + anchor.length);
+                histogram_range(a, b, after, (right, bottom), edits);
+            }
+            None => myers_diff_range(a, b, (left, top), (right, bottom), edits),
+        }
+    }
+    edits.extend((0..suffix).map(|i| Edit::Equal(right + i, bottom + i)));
+}
+
+fn find_anchor(
+    a: &[u32],
+    b: &[u32],
+    top_left: (usize, usize),
+    bottom_right: (usize, usize),
+) -> Option<Anchor> {
+    let (left, top) = top_left;
+    let (right, bottom) = bottom_right;
+    let mut occurrences: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (x, line) in a.iter().enumerate().take(right).skip(left) {
+        occurrences.entry(*line).or_default().push(x);
+    }
+    let mut best: Option<Anchor> = None;
+    let mut y = top;
+    while y < bottom {
+        let mut next_y = y + 1;
+        let positions = match occurrences.get(&b[y]) {
+            Some(positions) if positions.len() <= MAX_CHAIN_LENGTH => positions,
+            _ => {
+                y = next_y;
+                continue;
+            }
+        };
+        for &x in positions.iter() {
+            // grow the match both ways, tracking how common its most common line is
+            let mut rarity = positions.len();
+            let (mut start_x, mut start_y) = (x, y);
+            while start_x > left && start_y > top && a[start_x - 1] == b[start_y - 1] {
+                start_x -= 1;
+                start_y -= 1;
+                rarity = rarity.min(occurrences[&a[start_x]].len());
+            }
+            let (mut end_x, mut end_y) = (x + 1, y + 1);
+            while end_x < right && end_y < bottom && a[end_x] == b[end_y] {
+                rarity = rarity.min(occurrences[&a[end_x]].len());
+                end_x += 1;
+                end_y += 1;
+            }
+            let length = end_x - start_x;
+            let better = match &best {
+                Some(best) => {
+                    rarity < best.rarity || (rarity == best.rarity && length > best.length)
+                }
+                None => true,
+            };
+            if better {
+                best = Some(Anchor {
+                    start: (start_x, start_y),
+                    length,
+                    rarity,
+                });
+            }
+            next_y = next_y.max(end_y);
+        }
+        y = next_y;
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::split_lines;
+
+    #[test]
+    fn anchors_on_rare_lines() {
+        let old = b"x\n}\n}\nunique\n}\n";
+        let new = b"}\nunique\n}\n}\ny\n";
+        let (old_lines, new_lines) = (split_lines(old), split_lines(new));
+        let edits = histogram_diff(&old_lines, &new_lines);
+        assert!(edits.contains(&Edit::Equal(3, 1)));
+        let rebuilt: Vec<u8> = edits
+            .iter()
+            .filter_map(|edit| match edit {
+                Edit::Equal(_, y) | Edit::Insert(y) => Some(new_lines[*y]),
+                Edit::Delete(_) => None,
+            })
+            .flatten()
+            .copied()
+            .collect();
+        assert_eq!(rebuilt, new.to_vec());
+    }
+}
@@ -0,0 +1,262 @@
+use clap::ArgEnum;
+
+use super::{diff_lines, split_lines, DiffAlgorithm, Edit};
+use crate::{config::get_config_value, exit_with_message};
+
+#[derive(Clone, Copy, Debug, PartialEq, ArgEnum)]
+pub enum ConflictStyle {
+    /// Only our and their side of each conflict
+    Merge,
+    /// The base version between the two sides as well
+    Diff3,
+    /// Like diff3, but lines both sides agree on are moved out of the conflict
+    Zdiff3,
+}
+
+impl ConflictStyle {
+    /// The style asked for on the command line, otherwise the one set as
+    /// `merge.conflictStyle` in the config, otherwise merge
+    pub fn choose(requested: Option<ConflictStyle>) -> Self {
+        if let Some(style) = requested {
+            return style;
+        }
+        match get_config_value("merge.conflictStyle") {
+            Some(value) => match ConflictStyle::from_str(&value, true) {
+                Ok(style) => style,
+                Err(_) => {
+                    let msg = format!(
+                        "error: unknown value for config 'merge.conflictStyle': {}",
+                        value
+                    );
+                    exit_with_message(&msg);
+                }
+            },
+            None => ConflictStyle::Merge,
+        }
+    }
+}
+
+/// The names written after the conflict markers for the base, our and their side
+#[derive(Clone, Debug)]
+pub struct MergeLabels {
+    pub base: String,
+    pub ours: String,
+    pub theirs: String,
+}
+
+/// Merged contents, with conflict markers around any regions both sides changed differently
+#[derive(Clone, Debug, PartialEq)]
+pub struct MergeResult {
+    pub data: Vec<u8>,
+    pub conflicts: usize,
+}
+
+/// A run of lines from the three versions, either the same in all of them or changed by at
+/// least one side
+enum Chunk {
+    Stable(usize),
+    Changed {
+        base: (usize, usize),
+        ours: (usize, usize),
+        theirs: (usize, usize),
+    },
+}
+
+/// Merges two versions of some contents that both came from `base`, line by line
+pub fn merge_contents(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    labels: &MergeLabels,
+    style: ConflictStyle,
+    algorithm: DiffAlgorithm,
+) -> MergeResult {
+    let base_lines = split_lines(base);
+    let our_lines = split_lines(ours);
+    let their_lines = split_lines(theirs);
+    let chunks = find_chunks(&base_lines, &our_lines, &their_lines, algorithm);
+    let mut data = vec![];
+    let mut conflicts = 0;
+    for chunk in chunks {
+        let (base_range, our_range, their_range) = match chunk {
+            Chunk::Stable(line) => {
+                data.extend_from_slice(base_lines[line]);
+                continue;
+            }
+            Chunk::Changed { base, ours, theirs } => (base, ours, theirs),
+        };
+        let base_part = &base_lines[base_range.0..base_range.1];
+        let our_part = &our_lines[our_range.0..our_range.1];
+        let their_part = &their_lines[their_range.0..their_range.1];
+        // only one side changed these lines, or both made the same change
+        if our_part == base_part || our_part == their_part {
+            data.extend(their_part.concat());
+            continue;
+        }
+        if their_part == base_part {
+            data.extend(our_part.concat());
+            continue;
+        }
+        conflicts += 1;
+        write_conflict(&mut data, base_part, our_part, their_part, labels, style);
+    }
+    MergeResult { data, conflicts }
+}
+
+/// Walks the three versions side by side, splitting them into lines that all three share
+/// and the changed regions between them
+fn find_chunks(
+    base: &[&[u8]],
+    ours: &[&[u8]],
+    theirs: &[&[u8]],
+    algorithm: DiffAlgorithm,
+) -> Vec<Chunk> {
+    let matches = |other: &[&[u8]]| -> Vec<Option<usize>> {
+        let mut matched = vec![None; base.len()];
+        for edit in diff_lines(base, other, algorithm) {
+            if let Edit::Equal(x, y) = edit {
+                matched[x] = Some(y);
+            }
+        }
+        matched
+    };
+    let (in_ours, in_theirs) = (matches(ours), matches(theirs));
+    let mut chunks = vec![];
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    while b < base.len() || o < ours.len() || t < theirs.len() {
+        if b < base.len() && in_ours[b] == Some(o) && in_theirs[b] == Some(t) {
+            chunks.push(Chunk::Stable(b));
+            b += 1;
+            o += 1;
+            t += 1;
+            continue;
+        }
+        // the changed region runs up to the next base line both sides still have
+        let next =
+            (b..base.len()).find(|&line| in_ours[line].is_some() && in_theirs[line].is_some());
+        let (end_b, end_o, end_t) = match next {
+            Some(line) => (line, in_ours[line].unwrap(), in_theirs[line].unwrap()),
+            None => (base.len(), ours.len(), theirs.len()),
+        };
+        chunks.push(Chunk::Changed {
+            base: (b, end_b),
+            ours: (o, end_o),
+            theirs: (t, end_t),
+        });
+        b = end_b;
+        o = end_o;
+        t = end_t;
+    }
+    chunks
+}
+
+fn write_conflict(
+    data: &mut Vec<u8>,
+    base: &[&[u8]],
+    ours: &[&[u8]],
+    theirs: &[&[u8]],
+    labels: &MergeLabels,
+    style: ConflictStyle,
+) {
+    // lines at either end that both sides agree on don't need to be in the conflict
+    let (mut prefix, mut suffix) = (0, 0);
+    if style != ConflictStyle::Diff3 {
+        let shorter = ours.len().min(theirs.len());
+        while prefix < shorter && ours[prefix] == theirs[prefix] {
+            prefix += 1;
+        }
+        while suffix < shorter - prefix
+            && ours[ours.len() - 1 - suffix] == theirs[theirs.len() - 1 - suffix]
+        {
+            suffix += 1;
+        }
+    }
+    data.extend(ours[..prefix].concat());
+    let marker = |data: &mut Vec<u8>, marker: &str, label: &str| {
+        if !data.is_empty() && !data.ends_with(b"\n") {
+            data.push(b'\n');
+        }
+        data.extend_from_slice(marker.as_bytes());
+        if !label.is_empty() {
+            data.push(b' ');
+            data.extend_from_slice(label.as_bytes());
+        }
+        data.push(b'\n');
+    };
+    marker(data, "<<<<<<<", &labels.ours);
+    data.extend(ours[prefix..ours.len() - suffix].concat());
+    if style != ConflictStyle::Merge {
+        marker(data, "|||||||", &labels.base);
+        data.extend(base.concat());
+    }
+    marker(data, "=======", "");
+    data.extend(theirs[prefix..theirs.len() - suffix].concat());
+    marker(data, ">>>>>>>", &labels.theirs);
+    data.extend(ours[ours.len() - suffix..].concat());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels() -> MergeLabels {
+        MergeLabels {
+            base: String::from("base"),
+            ours: String::from("HEAD"),
+            theirs: String::from("feature"),
+        }
+    }
+
+    fn merge(base: &str, ours: &str, theirs: &str, style: ConflictStyle) -> MergeResult {
+        merge_contents(
+            base.as_bytes(),
+            ours.as_bytes(),
+            theirs.as_bytes(),
+            &labels(),
+            style,
+            DiffAlgorithm::Myers,
+        )
+    }
+
+    #[test]
+    fn clean_merge_takes_both_sides() {
+        let base = "1\n2\n3\n4\n5\n6\n7\n";
+        let ours = "1\ntwo\n3\n4\n5\n6\n7\n";
+        let theirs = "1\n2\n3\n4\n5\n6\nseven\neight\n";
+        let merged = merge(base, ours, theirs, ConflictStyle::Merge);
+        assert_eq!(merged.conflicts, 0);
+        assert_eq!(merged.data, b"1\ntwo\n3\n4\n5\n6\nseven\neight\n".to_vec());
+    }
+
+    #[test]
+    fn conflict_styles() {
+        let base = "1\n2\n3\n";
+        let ours = "1\ntwo\nsame\n3\n";
+        let theirs = "1\nTWO\nsame\n3\n";
+        let merged = merge(base, ours, theirs, ConflictStyle::Merge);
+        assert_eq!(merged.conflicts, 1);
+        assert_eq!(
+            String::from_utf8(merged.data).unwrap(),
+            "1\n<<<<<<< HEAD\ntwo\n=======\nTWO\n>>>>>>> feature\nsame\n3\n"
+        );
+        let merged = merge(base, ours, theirs, ConflictStyle::Diff3);
+        assert_eq!(
+            String::from_utf8(merged.data).unwrap(),
+            "1\n<<<<<<< HEAD\ntwo\nsame\n||||||| base\n2\n=======\nTWO\nsame\n>>>>>>> feature\n3\n"
+        );
+        let merged = merge(base, ours, theirs, ConflictStyle::Zdiff3);
+        assert_eq!(
+            String::from_utf8(merged.data).unwrap(),
+            "1\n<<<<<<< HEAD\ntwo\n||||||| base\n2\n=======\nTWO\n>>>>>>> feature\nsame\n3\n"
+        );
+    }
+
+    #[test]
+    fn missing_newline_before_marker() {
+        let merged = merge("a\n", "a\nb", "a\nc", ConflictStyle::Merge);
+        assert_eq!(
+            String::from_utf8(merged.data).unwrap(),
+            "a\n<<<<<<< HEAD\nb\n=======\nc\n>>>>>>> feature\n"
+        );
+    }
+}
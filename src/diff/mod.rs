@@ -0,0 +1,130 @@
+mod histogram;
+mod merge;
+mod myers;
+mod patience;
+mod pickaxe;
+mod rename;
+mod tree_diff;
+mod unified;
+
+use std::collections::HashMap;
+
+use clap::ArgEnum;
+
+pub use histogram::*;
+pub use merge::*;
+pub use myers::*;
+pub use patience::*;
+pub use pickaxe::*;
+pub use rename::*;
+pub use tree_diff::*;
+pub use unified::*;
+
+use crate::{config::get_config_value, exit_with_message};
+
+#[derive(Clone, Copy, Debug, PartialEq, ArgEnum)]
+pub enum DiffAlgorithm {
+    Myers,
+    /// Myers' algorithm already finds the smallest diff, so this is the same thing
+    Minimal,
+    Patience,
+    Histogram,
+}
+
+impl DiffAlgorithm {
+    /// The algorithm asked for on the command line, otherwise the one set as `diff.algorithm`
+    /// in the config, otherwise Myers
+    pub fn choose(requested: Option<DiffAlgorithm>) -> Self {
+        if let Some(algorithm) = requested {
+            return algorithm;
+        }
+        match get_config_value("diff.algorithm") {
+            Some(value) => match DiffAlgorithm::from_str(&value, true) {
+                Ok(algorithm) => algorithm,
+                Err(_) => {
+                    let msg = format!(
+                        "error: unknown value for config 'diff.algorithm': {}",
+                        value
+                    );
+                    exit_with_message(&msg);
+                }
+            },
+            None => DiffAlgorithm::Myers,
+        }
+    }
+}
+
+/// How changes are printed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiffOutput {
+    Patch,
+    NameOnly,
+    NameStatus,
+}
+
+/// Everything that decides how a set of changes is found and shown
+#[derive(Clone, Copy, Debug)]
+pub struct DiffOptions {
+    pub context: usize,
+    pub algorithm: DiffAlgorithm,
+    pub renames: RenameDetection,
+    pub output: DiffOutput,
+}
+
+impl DiffOptions {
+    /// The changes in the requested output format
+    pub fn format(&self, change: &FileChange) -> String {
+        match self.output {
+            DiffOutput::Patch => format_patch(change, self.context, self.algorithm),
+            DiffOutput::NameOnly => format!("{}\n", change.path()),
+            DiffOutput::NameStatus => format_name_status(change),
+        }
+    }
+}
+
+/// Diffs two lists of lines with the chosen algorithm
+pub fn diff_lines(old: &[&[u8]], new: &[&[u8]], algorithm: DiffAlgorithm) -> Vec<Edit> {
+    match algorithm {
+        DiffAlgorithm::Myers | DiffAlgorithm::Minimal => myers_diff(old, new),
+        DiffAlgorithm::Patience => patience_diff(old, new),
+        DiffAlgorithm::Histogram => histogram_diff(old, new),
+    }
+}
+
+/// One step of a line diff, holding the indexes of the lines it refers to in the old and
+/// new contents
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Splits contents into lines. Each line keeps its newline, so a missing newline at the end
+/// of a file counts as a change to its last line
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&b| b == b'\n').collect()
+}
+
+/// Whether the contents should be treated as binary, which like git is whether there is a
+/// null byte near the start
+pub fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8000).any(|&b| b == 0)
+}
+
+/// Numbers every distinct line, so the diff algorithms compare integers instead of slices
+fn intern_lines<'a>(old: &[&'a [u8]], new: &[&'a [u8]]) -> (Vec<u32>, Vec<u32>) {
+    let mut ids: HashMap<&'a [u8], u32> = HashMap::new();
+    let mut intern = |lines: &[&'a [u8]]| -> Vec<u32> {
+        lines
+            .iter()
+            .map(|&line| {
+                let next = ids.len() as u32;
+                *ids.entry(line).or_insert(next)
+            })
+            .collect()
+    };
+    let old = intern(old);
+    let new = intern(new);
+    (old, new)
+}
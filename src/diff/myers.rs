@@ -0,0 +1,211 @@
+use super::{intern_lines, Edit};
+
+/// The part of an edit path that crosses the middle of a box: one insert or delete and the
+/// run of equal lines next to it. Found going forwards the edit comes first, found going
+/// backwards it comes last
+struct Snake {
+    start: (usize, usize),
+    end: (usize, usize),
+    forward: bool,
+}
+
+/// Line diff using Myers' algorithm. This is the linear space version, which finds the middle
+/// snake of the edit graph and then recurses into the boxes either side of it
+pub fn myers_diff(old: &[&[u8]], new: &[&[u8]]) -> Vec<Edit> {
+    let (a, b) = intern_lines(old, new);
+    let mut edits = Vec::with_capacity(a.len().max(b.len()));
+    myers_diff_range(&a, &b, (0, 0), (a.len(), b.len()), &mut edits);
+    edits
+}
+
+/// Myers' diff of just the box between two points of already interned lines, which the other
+/// algorithms fall back on for regions they have no anchors in
+pub fn myers_diff_range(
+    a: &[u32],
+    b: &[u32],
+    top_left: (usize, usize),
+    bottom_right: (usize, usize),
+    edits: &mut Vec<Edit>,
+) {
+    let (mut left, mut top) = top_left;
+    let (mut right, mut bottom) = bottom_right;
+    while left < right && top < bottom && a[left] == b[top] {
+        edits.push(Edit::Equal(left, top));
+        left += 1;
+        top += 1;
+    }
+    let mut suffix = 0;
+    while left < right && top < bottom && a[right - 1] == b[bottom - 1] {
+        right -= 1;
+        bottom -= 1;
+        suffix += 1;
+    }
+    if left == right {
+        edits.extend((top..bottom).map(Edit::Insert));
+    } else if top == bottom {
+        edits.extend((left..right).map(Edit::Delete));
+    } else {
+        let snake = middle_snake(a, b, (left, top), (right, bottom));
+        myers_diff_range(a, b, (left, top), snake.start, edits);
+        push_snake(&snake, edits);
+        myers_diff_range(a, b, snake.end, (right, bottom), edits);
+    }
+    edits.extend((0..suffix).map(|i| Edit::Equal(right + i, bottom + i)));
+}
+
+fn push_snake(snake: &Snake, edits: &mut Vec<Edit>) {
+    let (mut x, mut y) = snake.start;
+    let (end_x, end_y) = snake.end;
+    let push_edit = |x: &mut usize, y: &mut usize, edits: &mut Vec<Edit>| {
+        if end_x - *x > end_y - *y {
+            edits.push(Edit::Delete(*x));
+            *x += 1;
+        } else if end_y - *y > end_x - *x {
+            edits.push(Edit::Insert(*y));
+            *y += 1;
+        }
+    };
+    if snake.forward {
+        push_edit(&mut x, &mut y, edits);
+    }
+    while x < end_x && y < end_y {
+        edits.push(Edit::Equal(x, y));
+        x += 1;
+        y += 1;
+    }
+    if !snake.forward {
+        push_edit(&mut x, &mut y, edits);
+    }
+}
+
+/// Searches from both corners of the box at once, one edit further each round, until the
+/// two searches overlap. Forward paths are tracked by their x on each diagonal k, backward
+/// paths by their y on each diagonal c, counted from the bottom right corner
+fn middle_snake(
+    a: &[u32],
+    b: &[u32],
+    top_left: (usize, usize),
+    bottom_right: (usize, usize),
+) -> Snake {
+    let (left, top) = (top_left.0 as isize, top_left.1 as isize);
+    let (right, bottom) = (bottom_right.0 as isize, bottom_right.1 as isize);
+    let delta = (right - left) - (bottom - top);
+    let max = ((right - left) + (bottom - top) + 1) / 2;
+    let at = |diagonal: isize| (diagonal + max + 1) as usize;
+    let mut forward = vec![0; (2 * max + 3) as usize];
+    let mut backward = vec![0; (2 * max + 3) as usize];
+    forward[at(1)] = left;
+    backward[at(1)] = bottom;
+    let same = |x: isize, y: isize| a[x as usize] == b[y as usize];
+    let point = |x: isize, y: isize| (x as usize, y as usize);
+    for d in 0..=max {
+        for k in (-d..=d).rev().step_by(2) {
+            let (start_x, mut x) =
+                match k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                    true => (forward[at(k + 1)], forward[at(k + 1)]),
+                    false => (forward[at(k - 1)], forward[at(k - 1)] + 1),
+                };
+            let mut y = top + (x - left) - k;
+            let start_y = match d == 0 || x != start_x {
+                true => y,
+                false => y - 1,
+            };
+            while x < right && y < bottom && same(x, y) {
+                x += 1;
+                y += 1;
+            }
+            forward[at(k)] = x;
+            let c = k - delta;
+            if delta % 2 != 0 && c >= -(d - 1) && c < d && y >= backward[at(c)] {
+                return Snake {
+                    start: point(start_x, start_y),
+                    end: point(x, y),
+                    forward: true,
+                };
+            }
+        }
+        for c in (-d..=d).rev().step_by(2) {
+            let (start_y, mut y) =
+                match c == -d || (c != d && backward[at(c - 1)] > backward[at(c + 1)]) {
+                    true => (backward[at(c + 1)], backward[at(c + 1)]),
+                    false => (backward[at(c - 1)], backward[at(c - 1)] - 1),
+                };
+            let k = c + delta;
+            let mut x = left + (y - top) + k;
+            let start_x = match d == 0 || y != start_y {
+                true => x,
+                false => x + 1,
+            };
+            while x > left && y > top && same(x - 1, y - 1) {
+                x -= 1;
+                y -= 1;
+            }
+            backward[at(c)] = y;
+            if delta % 2 == 0 && k >= -d && k <= d && x <= forward[at(k)] {
+                return Snake {
+                    start: point(x, y),
+                    end: point(start_x, start_y),
+                    forward: false,
+                };
+            }
+        }
+    }
+    unreachable!("the forward and backward searches always meet");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::split_lines;
+
+    /// Rebuilds the new contents from the old using the edits, checking each step agrees
+    fn apply(old: &[&[u8]], new: &[&[u8]], edits: &[Edit]) -> Vec<Vec<u8>> {
+        let (mut next_old, mut next_new) = (0, 0);
+        let mut out = vec![];
+        for edit in edits {
+            match *edit {
+                Edit::Equal(x, y) => {
+                    assert_eq!((x, y), (next_old, next_new));
+                    assert_eq!(old[x], new[y]);
+                    out.push(old[x].to_vec());
+                    next_old += 1;
+                    next_new += 1;
+                }
+                Edit::Delete(x) => {
+                    assert_eq!(x, next_old);
+                    next_old += 1;
+                }
+                Edit::Insert(y) => {
+                    assert_eq!(y, next_new);
+                    out.push(new[y].to_vec());
+                    next_new += 1;
+                }
+            }
+        }
+        assert_eq!((next_old, next_new), (old.len(), new.len()));
+        out
+    }
+
+    #[test]
+    fn shortest_edit_script() {
+        let cases: [(&[u8], &[u8], usize); 6] = [
+            (b"a\nb\nc\na\nb\nb\na\n", b"c\nb\na\nb\na\nc\n", 5),
+            (b"", b"x\ny\n", 2),
+            (b"x\ny\n", b"", 2),
+            (b"same\n", b"same\n", 0),
+            (b"a\nb\nc\n", b"a\nc\n", 1),
+            (b"one\ntwo", b"one\ntwo\n", 2),
+        ];
+        for (old, new, changes) in cases.iter() {
+            let (old, new) = (split_lines(old), split_lines(new));
+            let edits = myers_diff(&old, &new);
+            let rebuilt = apply(&old, &new, &edits);
+            assert_eq!(rebuilt, new.iter().map(|l| l.to_vec()).collect::<Vec<_>>());
+            let changed = edits
+                .iter()
+                .filter(|e| !matches!(e, Edit::Equal(_, _)))
+                .count();
+            assert_eq!(changed, *changes);
+        }
+    }
+}
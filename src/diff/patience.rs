@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use super::{intern_lines, myers_diff_range, Edit};
+
+/// Line diff using the patience algorithm. Lines that appear exactly once on each side are
+/// matched up, the longest run of them that is in the same order on both sides is kept as
+/// anchors, and the gaps between anchors are diffed the same way. Gaps without any unique
+/// lines fall back to Myers
+pub fn patience_diff(old: &[&[u8]], new: &[&[u8]]) -> Vec<Edit> {
+    let (a, b) = intern_lines(old, new);
+    let mut edits = Vec::with_capacity(a.len().max(b.len()));
+    patience_range(&a, &b, (0, 0), (a.len(), b.len()), &mut edits);
+    edits
+}
+
+fn patience_range(
+    a: &[u32],
+    b: &[u32],
+    top_left: (usize, usize),
+    bottom_right: (usize, usize),
+    edits: &mut Vec<Edit>,
+) {
+    let (mut left, mut top) = top_left;
+    let (mut right, mut bottom) = bottom_right;
+    while left < right && top < bottom && a[left] == b[top] {
+        edits.push(Edit::Equal(left, top));
+        left += 1;
+        top += 1;
+    }
+    let mut suffix = 0;
+    while left < right && top < bottom && a[right - 1] == b[bottom - 1] {
+        right -= 1;
+        bottom -= 1;
+        suffix += 1;
+    }
+    let anchors = unique_common_lines(a, b, (left, top), (right, bottom));
+    if anchors.is_empty() {
+        myers_diff_range(a, b, (left, top), (right, bottom), edits);
+    } else {
+        let (mut x, mut y) = (left, top);
+        for (anchor_x, anchor_y) in anchors {
+            patience_range(a, b, (x, y), (anchor_x, anchor_y), edits);
+            edits.push(Edit::Equal(anchor_x, anchor_y));
+            x = anchor_x + 1;
+            y = anchor_y + 1;
+        }
+        patience_range(a, b, (x, y), (right, bottom), edits);
+    }
+    edits.extend((0..suffix).map(|i| Edit::Equal(right + i, bottom + i)));
+}
+
+/// Pairs of lines that are unique on both sides of the box, cut down to the longest sequence
+/// that is increasing on both sides
+fn unique_common_lines(
+    a: &[u32],
+    b: &[u32],
+    top_left: (usize, usize),
+    bottom_right: (usize, usize),
+) -> Vec<(usize, usize)> {
+    // for each line, how often it's in each side and where it was last seen
+    let mut counts: HashMap<u32, (usize, usize, usize, usize)> = HashMap::new();
+    for (x, line) in a.iter().enumerate().take(bottom_right.0).skip(top_left.0) {
+        let count = counts.entry(*line).or_insert((0, 0, 0, 0));
+        count.0 += 1;
+        count.2 = x;
+    }
+    for (y, line) in b.iter().enumerate().take(bottom_right.1).skip(top_left.1) {
+        if let Some(count) = counts.get_mut(line) {
+            count.1 += 1;
+            count.3 = y;
+        }
+    }
+    let mut pairs: Vec<(usize, usize)> = counts
+        .values()
+        .filter(|&&(in_a, in_b, _, _)| in_a == 1 && in_b == 1)
+        .map(|&(_, _, x, y)| (x, y))
+        .collect();
+    pairs.sort_unstable();
+    longest_increasing(&pairs)
+}
+
+/// Patience sorting: each pair goes on the leftmost pile whose top has a larger new line
+/// number, remembering the top of the pile before it, and the longest sequence is read back
+/// from the last pile
+fn longest_increasing(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut pile_tops: Vec<usize> = vec![];
+    let mut previous: Vec<Option<usize>> = Vec::with_capacity(pairs.len());
+    for (i, &(_, y)) in pairs.iter().enumerate() {
+        let pile = pile_tops.partition_point(|&top| pairs[top].1 < y);
+        previous.push(match pile {
+            0 => None,
+            _ => Some(pile_tops[pile - 1]),
+        });
+        match pile == pile_tops.len() {
+            true => pile_tops.push(i),
+            false => pile_tops[pile] = i,
+        }
+    }
+    let mut sequence = vec![];
+    let mut next = pile_tops.last().copied();
+    while let Some(i) = next {
+        sequence.push(pairs[i]);
+        next = previous[i];
+    }
+    sequence.reverse();
+    sequence
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::split_lines;
+
+    #[test]
+    fn anchors_on_unique_lines() {
+        // the new function goes in whole, after the blank line that ends a()
+        let old = b"a()\n{\n  one\n}\n\nb()\n{\n  two\n}\n";
+        let new = b"a()\n{\n  one\n}\n\nc()\n{\n  three\n}\n\nb()\n{\n  two\n}\n";
+        let (old, new) = (split_lines(old), split_lines(new));
+        let edits = patience_diff(&old, &new);
+        let inserted: Vec<&[u8]> = edits
+            .iter()
+            .filter_map(|edit| match edit {
+                Edit::Insert(y) => Some(new[*y]),
+                _ => None,
+            })
+            .collect();
+        let expected: Vec<&[u8]> = vec![b"c()\n", b"{\n", b"  three\n", b"}\n", b"\n"];
+        assert_eq!(inserted, expected);
+        assert!(!edits.iter().any(|edit| matches!(edit, Edit::Delete(_))));
+    }
+
+    #[test]
+    fn longest_sequence() {
+        let pairs = [(0, 3), (1, 0), (2, 1), (3, 4), (4, 2), (5, 5)];
+        assert_eq!(
+            longest_increasing(&pairs),
+            vec![(1, 0), (2, 1), (4, 2), (5, 5)]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use regex::bytes::Regex;
+
+use super::{diff_lines, is_binary, split_lines, DiffAlgorithm, Edit, FileChange};
+
+/// What the pickaxe looks for in a change
+#[derive(Clone, Debug)]
+pub enum Pickaxe {
+    /// Changes where the pattern is found a different number of times before and after
+    Occurrences(Regex),
+    /// Changes with an added or removed line that matches. Binary files are skipped
+    Lines(Regex),
+}
+
+impl Pickaxe {
+    pub fn matches(&self, change: &FileChange) -> bool {
+        let old: &[u8] = change.old_file().as_ref().map_or(&[], |file| file.data());
+        let new: &[u8] = change.new_file().as_ref().map_or(&[], |file| file.data());
+        match self {
+            Pickaxe::Occurrences(pattern) => {
+                pattern.find_iter(old).count() != pattern.find_iter(new).count()
+            }
+            Pickaxe::Lines(pattern) => {
+                if is_binary(old) || is_binary(new) {
+                    return false;
+                }
+                let old_lines = split_lines(old);
+                let new_lines = split_lines(new);
+                // lines are matched without their newline so that `$` can match at the end
+                let is_match =
+                    |line: &[u8]| pattern.is_match(line.strip_suffix(b"\n").unwrap_or(line));
+                diff_lines(&old_lines, &new_lines, DiffAlgorithm::Myers)
+                    .iter()
+                    .any(|edit| match *edit {
+                        Edit::Delete(x) => is_match(old_lines[x]),
+                        Edit::Insert(y) => is_match(new_lines[y]),
+                        Edit::Equal(_, _) => false,
+                    })
+            }
+        }
+    }
+}
+
+/// The changes the pickaxe finds something in. With `all`, finding something in any change
+/// keeps every one of them
+pub fn pickaxe_changes(changes: Vec<FileChange>, pickaxe: &Pickaxe, all: bool) -> Vec<FileChange> {
+    match all {
+        true if changes.iter().any(|change| pickaxe.matches(change)) => changes,
+        true => vec![],
+        false => changes
+            .into_iter()
+            .filter(|change| pickaxe.matches(change))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::DiffFile;
+
+    #[test]
+    fn occurrences_and_lines() {
+        let change = |old: &str, new: &str| {
+            FileChange::new(
+                Some(DiffFile::new("f", 0o100644, old.as_bytes().to_vec())),
+                Some(DiffFile::new("f", 0o100644, new.as_bytes().to_vec())),
+            )
+        };
+        let moved = change("foo\nbar\n", "bar\nfoo\n");
+        let added = change("foo\n", "foo\nfoofoo\n");
+        let occurrences = Pickaxe::Occurrences(Regex::new("foo").unwrap());
+        assert!(!occurrences.matches(&moved));
+        assert!(occurrences.matches(&added));
+        let lines = Pickaxe::Lines(Regex::new("^fo+$").unwrap());
+        assert!(lines.matches(&moved));
+        assert!(!lines.matches(&added));
+        let kept = pickaxe_changes(vec![moved, added], &occurrences, true);
+        assert_eq!(kept.len(), 2);
+    }
+}
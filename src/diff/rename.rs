@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+
+use log::{debug, warn};
+
+use super::{ChangeKind, DiffFile, FileChange};
+use crate::config::get_config_int;
+
+/// Similarity scores are out of this, the same scale git uses
+pub const MAX_SCORE: u64 = 60000;
+/// Unless told otherwise files have to be half the same to count as renamed
+pub const DEFAULT_MIN_SCORE: u64 = MAX_SCORE / 2;
+const DEFAULT_RENAME_LIMIT: usize = 1000;
+/// Files are compared in chunks that end at a newline or at this many bytes
+const MAX_CHUNK: usize = 64;
+
+/// Whether to pair up removed and added files that have similar contents, and how similar
+/// they have to be
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenameDetection {
+    Off,
+    Renames(u64),
+    /// Copies also look at modified files as sources, and let one source have many copies
+    Copies(u64),
+}
+
+/// Parses a minimum similarity the way git does. `50%` is a percentage, while bare digits are
+/// the fraction after a decimal point, so `5` and `50` both mean half
+pub fn parse_similarity(value: &str) -> Result<u64, String> {
+    let invalid = || format!("'{}' is not a valid similarity", value);
+    let score = match value.strip_suffix('%') {
+        Some(percent) => {
+            let percent: u64 = percent.parse().map_err(|_| invalid())?;
+            percent * MAX_SCORE / 100
+        }
+        None => {
+            if value.is_empty() || !value.chars().all(|c| c.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            let fraction: f64 = format!("0.{}", value).parse().map_err(|_| invalid())?;
+            (fraction * MAX_SCORE as f64) as u64
+        }
+    };
+    Ok(score.min(MAX_SCORE))
+}
+
+/// Replaces added files that match a removed file, or with copy detection any changed file,
+/// with a single renamed or copied change. Identical contents are matched first by sha, then
+/// the rest by how many chunks of their contents are shared, unless there are more candidate
+/// pairs than `diff.renameLimit` allows
+pub fn detect_renames(changes: Vec<FileChange>, detection: RenameDetection) -> Vec<FileChange> {
+    let (min_score, copies) = match detection {
+        RenameDetection::Off => return changes,
+        RenameDetection::Renames(score) => (score, false),
+        RenameDetection::Copies(score) => (score, true),
+    };
+    // sources are indexes of changes with an old side, destinations of added files
+    let sources: Vec<usize> = (0..changes.len())
+        .filter(|&i| match changes[i].kind() {
+            ChangeKind::Deleted => true,
+            ChangeKind::Modified => copies,
+            _ => false,
+        })
+        .filter(|&i| {
+            changes[i]
+                .old_file()
+                .as_ref()
+                .is_some_and(|f| !f.data().is_empty())
+        })
+        .collect();
+    let destinations: Vec<usize> = (0..changes.len())
+        .filter(|&i| changes[i].kind() == &ChangeKind::Added)
+        .filter(|&i| {
+            changes[i]
+                .new_file()
+                .as_ref()
+                .is_some_and(|f| !f.data().is_empty())
+        })
+        .collect();
+    if sources.is_empty() || destinations.is_empty() {
+        return changes;
+    }
+    let old_side = |i: usize| changes[i].old_file().as_ref().unwrap();
+    let new_side = |i: usize| changes[i].new_file().as_ref().unwrap();
+
+    // (score, destination, source) for every pair similar enough
+    let mut candidates = vec![];
+    for &dst in destinations.iter() {
+        for &src in sources.iter() {
+            if old_side(src).sha() == new_side(dst).sha() {
+                candidates.push((MAX_SCORE, dst, src));
+            }
+        }
+    }
+    let limit = rename_limit();
+    let exact_destinations: Vec<usize> = candidates.iter().map(|&(_, dst, _)| dst).collect();
+    let remaining: Vec<usize> = destinations
+        .iter()
+        .copied()
+        .filter(|dst| !exact_destinations.contains(dst))
+        .collect();
+    if remaining.len() * sources.len() > limit * limit {
+        warn!("Skipping inexact rename detection");
+        eprintln!("warning: exhaustive rename detection was skipped due to too many files.");
+        eprintln!(
+            "warning: you may want to set your diff.renameLimit variable to at least {} and retry the command.",
+            remaining.len().max(sources.len())
+        );
+    } else if !remaining.is_empty() {
+        let source_chunks: Vec<HashMap<u32, usize>> = sources
+            .iter()
+            .map(|&src| chunk_counts(old_side(src).data()))
+            .collect();
+        for &dst in remaining.iter() {
+            let dst_file = new_side(dst);
+            let dst_chunks = chunk_counts(dst_file.data());
+            for (&src, src_chunks) in sources.iter().zip(source_chunks.iter()) {
+                let src_file = old_side(src);
+                if !sizes_allow(src_file, dst_file, min_score) {
+                    continue;
+                }
+                let score = similarity(src_chunks, &dst_chunks, src_file, dst_file);
+                if score >= min_score {
+                    candidates.push((score, dst, src));
+                }
+            }
+        }
+    }
+    // best scores first, then keep to path order
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+    let mut matched: HashMap<usize, (usize, u64)> = HashMap::new();
+    let mut source_uses: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (score, dst, src) in candidates {
+        if matched.contains_key(&dst) {
+            continue;
+        }
+        let uses = source_uses.entry(src).or_default();
+        if !copies && !uses.is_empty() {
+            continue;
+        }
+        debug!("Pairing {} with {}, score {}", src, dst, score);
+        uses.push(dst);
+        matched.insert(dst, (src, score));
+    }
+
+    // a removed file is renamed to the last of its destinations and copied to the others
+    let mut renamed_to = HashMap::new();
+    for (&src, uses) in source_uses.iter() {
+        if changes[src].kind() == &ChangeKind::Deleted {
+            renamed_to.insert(*uses.iter().max().unwrap(), src);
+        }
+    }
+    let mut result = vec![];
+    for (i, change) in changes.iter().enumerate() {
+        if let Some(&(src, score)) = matched.get(&i) {
+            let old = old_side(src).clone();
+            let new = change.new_file().clone();
+            let kind = match renamed_to.get(&i) == Some(&src) {
+                true => ChangeKind::Renamed(score),
+                false => ChangeKind::Copied(score),
+            };
+            result.push(FileChange::with_kind(Some(old), new, kind));
+        } else if !(source_uses.contains_key(&i) && change.kind() == &ChangeKind::Deleted) {
+            result.push(change.clone());
+        }
+    }
+    result.sort_by(|a, b| a.path().cmp(b.path()));
+    result
+}
+
+fn rename_limit() -> usize {
+    match get_config_int("diff.renameLimit") {
+        Some(limit) if limit > 0 => limit as usize,
+        _ => DEFAULT_RENAME_LIMIT,
+    }
+}
+
+/// Files whose sizes are too far apart can't reach the score, so don't bother comparing them
+fn sizes_allow(src: &DiffFile, dst: &DiffFile, min_score: u64) -> bool {
+    let (src_size, dst_size) = (src.data().len() as u64, dst.data().len() as u64);
+    let max_size = src_size.max(dst_size);
+    let delta_size = max_size - src_size.min(dst_size);
+    delta_size * MAX_SCORE <= max_size * (MAX_SCORE - min_score)
+}
+
+/// How much of the bigger file's contents can be found in the other, out of `MAX_SCORE`
+fn similarity(
+    src_chunks: &HashMap<u32, usize>,
+    dst_chunks: &HashMap<u32, usize>,
+    src: &DiffFile,
+    dst: &DiffFile,
+) -> u64 {
+    let copied: usize = dst_chunks
+        .iter()
+        .map(|(hash, &count)| src_chunks.get(hash).map_or(0, |&c| c.min(count)))
+        .sum();
+    let max_size = src.data().len().max(dst.data().len()) as u64;
+    copied as u64 * MAX_SCORE / max_size
+}
+
+/// How many bytes of the contents belong to each distinct chunk
+fn chunk_counts(data: &[u8]) -> HashMap<u32, usize> {
+    let mut counts = HashMap::new();
+    let (mut hash, mut length) = (0u32, 0);
+    for &byte in data.iter() {
+        hash = hash.rotate_left(7) ^ byte as u32;
+        length += 1;
+        if byte == b'\n' || length == MAX_CHUNK {
+            *counts.entry(hash).or_insert(0) += length;
+            hash = 0;
+            length = 0;
+        }
+    }
+    if length > 0 {
+        *counts.entry(hash).or_insert(0) += length;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn similarity_values() {
+        assert_eq!(parse_similarity("50%"), Ok(MAX_SCORE / 2));
+        assert_eq!(parse_similarity("5"), Ok(MAX_SCORE / 2));
+        assert_eq!(parse_similarity("75"), Ok(MAX_SCORE * 3 / 4));
+        assert_eq!(parse_similarity("100%"), Ok(MAX_SCORE));
+        assert!(parse_similarity("half").is_err());
+    }
+
+    fn lines(range: std::ops::Range<u32>) -> Vec<u8> {
+        range
+            .flat_map(|i| format!("line {}\n", i).into_bytes())
+            .collect()
+    }
+
+    fn added(path: &str, data: Vec<u8>) -> FileChange {
+        FileChange::new(None, Some(DiffFile::new(path, 0o100644, data)))
+    }
+
+    fn deleted(path: &str, data: Vec<u8>) -> FileChange {
+        FileChange::new(Some(DiffFile::new(path, 0o100644, data)), None)
+    }
+
+    fn statuses(changes: &[FileChange]) -> Vec<String> {
+        changes
+            .iter()
+            .map(|c| format!("{} {}", c.kind().status(), c.path()))
+            .collect()
+    }
+
+    #[test]
+    fn renames_and_copies() {
+        let changes = vec![
+            deleted("a", lines(0..100)),
+            added("b", lines(0..100)),
+            deleted(
+                "c",
+                lines(0..100)
+                    .into_iter()
+                    .chain(b"x\n".iter().copied())
+                    .collect(),
+            ),
+            added("d", lines(0..90)),
+            added("e", lines(500..600)),
+        ];
+        let found = detect_renames(changes.clone(), RenameDetection::Renames(DEFAULT_MIN_SCORE));
+        assert_eq!(statuses(&found), vec!["R100 b", "R089 d", "A e"]);
+        let found = detect_renames(
+            changes.clone(),
+            RenameDetection::Renames(MAX_SCORE * 9 / 10),
+        );
+        assert_eq!(statuses(&found), vec!["R100 b", "D c", "A d", "A e"]);
+        assert_eq!(
+            statuses(&detect_renames(changes, RenameDetection::Off)).len(),
+            5
+        );
+    }
+
+    #[test]
+    fn copies_keep_their_source() {
+        let changes = vec![
+            deleted("a", lines(0..100)),
+            added("b", lines(0..100)),
+            added("c", lines(0..100)),
+        ];
+        let found = detect_renames(changes.clone(), RenameDetection::Renames(DEFAULT_MIN_SCORE));
+        assert_eq!(statuses(&found), vec!["R100 b", "A c"]);
+        let found = detect_renames(changes, RenameDetection::Copies(DEFAULT_MIN_SCORE));
+        assert_eq!(statuses(&found), vec!["C100 b", "R100 c"]);
+    }
+}
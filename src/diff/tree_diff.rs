@@ -0,0 +1,254 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, Read},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+};
+
+use derive_getters::Getters;
+
+use super::MAX_SCORE;
+use crate::structures::{
+    load_blob_from_sha, load_commit_from_sha, load_tree_from_sha, Blob, Commit, Sha, TreeEntry,
+    TreeNode, TreeNodeType,
+};
+
+/// The same path before and after, either side missing when the path was added or removed
+pub type EntryPair = (Option<TreeEntry>, Option<TreeEntry>);
+
+/// One side of a changed file, with its contents
+#[derive(Clone, Debug, Getters)]
+pub struct DiffFile {
+    path: String,
+    mode: u32,
+    sha: Sha,
+    data: Vec<u8>,
+}
+
+impl DiffFile {
+    pub fn new(path: &str, mode: u32, data: Vec<u8>) -> Self {
+        let sha = Blob::new_from_bytes(data.clone(), mode).sha().clone();
+        Self {
+            path: path.to_string(),
+            mode,
+            sha,
+            data,
+        }
+    }
+
+    /// The file as it is stored in the database
+    pub fn from_entry(entry: &TreeEntry) -> Result<Self, io::Error> {
+        let data = match entry.mode() {
+            // submodules are shown as the commit they point at
+            0o160000 => format!("Subproject commit {}\n", entry.sha()).into_bytes(),
+            _ => load_blob_from_sha(entry.sha())?.data().clone(),
+        };
+        Ok(Self {
+            path: entry.path().clone(),
+            mode: *entry.mode(),
+            sha: entry.sha().clone(),
+            data,
+        })
+    }
+
+    /// The file as it is in the working tree, which may not be in the database at all
+    pub fn from_worktree(path: &str) -> Result<Self, io::Error> {
+        let metadata = fs::symlink_metadata(path)?;
+        let (mode, data) = match metadata.file_type().is_symlink() {
+            true => (
+                0o120000,
+                fs::read_link(path)?.as_os_str().as_bytes().to_vec(),
+            ),
+            false => {
+                let mut data = vec![];
+                fs::File::open(path)?.read_to_end(&mut data)?;
+                let mode = match metadata.mode() & 0o111 != 0 {
+                    true => 0o100755,
+                    false => 0o100644,
+                };
+                (mode, data)
+            }
+        };
+        Ok(Self::new(path, mode, data))
+    }
+
+    pub fn entry(&self) -> TreeEntry {
+        TreeEntry::new(self.path.clone(), self.mode, self.sha.clone())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Deleted,
+    Modified,
+    /// Changed between a regular file, a symlink and a submodule
+    TypeChanged,
+    /// Renamed or copied from the old file, with how similar the two are out of `MAX_SCORE`
+    Renamed(u64),
+    Copied(u64),
+}
+
+impl ChangeKind {
+    /// The status letter `--name-status` shows, with the similarity percentage for renames
+    /// and copies
+    pub fn status(&self) -> String {
+        match self {
+            ChangeKind::Added => "A".to_string(),
+            ChangeKind::Deleted => "D".to_string(),
+            ChangeKind::Modified => "M".to_string(),
+            ChangeKind::TypeChanged => "T".to_string(),
+            ChangeKind::Renamed(score) => format!("R{:03}", score * 100 / MAX_SCORE),
+            ChangeKind::Copied(score) => format!("C{:03}", score * 100 / MAX_SCORE),
+        }
+    }
+}
+
+/// A path that differs between two snapshots
+#[derive(Clone, Debug, Getters)]
+pub struct FileChange {
+    old_file: Option<DiffFile>,
+    new_file: Option<DiffFile>,
+    kind: ChangeKind,
+}
+
+impl FileChange {
+    pub fn new(old_file: Option<DiffFile>, new_file: Option<DiffFile>) -> Self {
+        let kind = match (&old_file, &new_file) {
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Deleted,
+            (Some(old), Some(new)) if old.mode() >> 12 != new.mode() >> 12 => {
+                ChangeKind::TypeChanged
+            }
+            _ => ChangeKind::Modified,
+        };
+        Self::with_kind(old_file, new_file, kind)
+    }
+
+    /// A change whose sides are known to be a rename or copy
+    pub fn with_kind(
+        old_file: Option<DiffFile>,
+        new_file: Option<DiffFile>,
+        kind: ChangeKind,
+    ) -> Self {
+        Self {
+            old_file,
+            new_file,
+            kind,
+        }
+    }
+
+    /// Loads both sides of a pair from the database, or the new side from the working tree
+    pub fn load(pair: &EntryPair, new_from_worktree: bool) -> Result<Self, io::Error> {
+        let old = match &pair.0 {
+            Some(entry) => Some(DiffFile::from_entry(entry)?),
+            None => None,
+        };
+        let new = match (&pair.1, new_from_worktree) {
+            (Some(entry), true) => Some(DiffFile::from_worktree(entry.path())?),
+            (Some(entry), false) => Some(DiffFile::from_entry(entry)?),
+            (None, _) => None,
+        };
+        Ok(Self::new(old, new))
+    }
+
+    /// The path after the change, or before it when the file was removed
+    pub fn path(&self) -> &str {
+        match (&self.new_file, &self.old_file) {
+            (Some(file), _) | (None, Some(file)) => file.path(),
+            (None, None) => "",
+        }
+    }
+}
+
+/// Every path whose entry differs between the two sets, in path order
+pub fn diff_entry_maps(
+    old: &BTreeMap<String, TreeEntry>,
+    new: &BTreeMap<String, TreeEntry>,
+) -> Vec<EntryPair> {
+    let paths: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    paths
+        .into_iter()
+        .map(|path| (old.get(path).cloned(), new.get(path).cloned()))
+        .filter(|(old, new)| old != new)
+        .collect()
+}
+
+/// Every blob path that differs between two trees. The trees are compared node by node, only
+/// descending into subtrees whose shas differ
+pub fn diff_trees(old: Option<&Sha>, new: Option<&Sha>) -> Result<Vec<EntryPair>, io::Error> {
+    let mut pairs = vec![];
+    diff_tree_nodes(old, new, "", &mut pairs)?;
+    pairs.sort_by(|a, b| pair_path(a).cmp(pair_path(b)));
+    Ok(pairs)
+}
+
+/// What the commit changed from its parent, with everything in a root commit counted as
+/// added. Merges have no changes of their own, unless `first_parent` compares them to their
+/// first parent
+pub fn commit_changes(commit: &Commit, first_parent: bool) -> Result<Vec<FileChange>, io::Error> {
+    let parent_tree = match commit.parent() {
+        Some(parents) if parents.len() > 1 && !first_parent => return Ok(vec![]),
+        Some(parents) => Some(load_commit_from_sha(&parents[0])?.tree_sha().clone()),
+        None => None,
+    };
+    diff_trees(parent_tree.as_ref(), Some(commit.tree_sha()))?
+        .iter()
+        .map(|pair| FileChange::load(pair, false))
+        .collect()
+}
+
+fn pair_path(pair: &EntryPair) -> &String {
+    match pair {
+        (Some(entry), _) | (None, Some(entry)) => entry.path(),
+        (None, None) => unreachable!("a pair always has a side"),
+    }
+}
+
+fn tree_nodes(sha: Option<&Sha>) -> Result<BTreeMap<String, TreeNode>, io::Error> {
+    let mut nodes = BTreeMap::new();
+    if let Some(sha) = sha {
+        for node in load_tree_from_sha(sha)?.root().nodes().iter() {
+            nodes.insert(node.clean_name(), node.clone());
+        }
+    }
+    Ok(nodes)
+}
+
+fn diff_tree_nodes(
+    old: Option<&Sha>,
+    new: Option<&Sha>,
+    prefix: &str,
+    pairs: &mut Vec<EntryPair>,
+) -> Result<(), io::Error> {
+    let old_nodes = tree_nodes(old)?;
+    let new_nodes = tree_nodes(new)?;
+    let names: BTreeSet<&String> = old_nodes.keys().chain(new_nodes.keys()).collect();
+    for name in names {
+        let old_node = old_nodes.get(name);
+        let new_node = new_nodes.get(name);
+        if let (Some(o), Some(n)) = (old_node, new_node) {
+            if o.sha() == n.sha() && o.mode_bits() == n.mode_bits() {
+                continue;
+            }
+        }
+        let path = match prefix.is_empty() {
+            true => name.clone(),
+            false => format!("{}/{}", prefix, name),
+        };
+        let is_tree = |node: &&TreeNode| node.node_type() == &TreeNodeType::Tree;
+        let old_tree = old_node.filter(is_tree).map(|node| node.sha());
+        let new_tree = new_node.filter(is_tree).map(|node| node.sha());
+        if old_tree.is_some() || new_tree.is_some() {
+            diff_tree_nodes(old_tree, new_tree, &path, pairs)?;
+        }
+        let as_entry =
+            |node: &TreeNode| TreeEntry::new(path.clone(), node.mode_bits(), node.sha().clone());
+        let old_blob = old_node.filter(|node| !is_tree(node)).map(as_entry);
+        let new_blob = new_node.filter(|node| !is_tree(node)).map(as_entry);
+        if old_blob.is_some() || new_blob.is_some() {
+            pairs.push((old_blob, new_blob));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use std::ops::Range;
+
+use super::{
+    diff_lines, is_binary, split_lines, ChangeKind, DiffAlgorithm, DiffFile, Edit, FileChange,
+    MAX_SCORE,
+};
+
+const NULL_PATH: &str = "/dev/null";
+
+/// The git style patch for a change, with `context` unchanged lines around each hunk
+pub fn format_patch(change: &FileChange, context: usize, algorithm: DiffAlgorithm) -> String {
+    let old = change.old_file().as_ref();
+    let new = change.new_file().as_ref();
+    let old_path = old.map_or(change.path(), |file| file.path());
+    let new_path = new.map_or(change.path(), |file| file.path());
+    let mut patch = format!("diff --git a/{} b/{}\n", old_path, new_path);
+    match (old, new) {
+        (None, Some(new)) => patch.push_str(&format!("new file mode {:06o}\n", new.mode())),
+        (Some(old), None) => patch.push_str(&format!("deleted file mode {:06o}\n", old.mode())),
+        (Some(old), Some(new)) if old.mode() != new.mode() => patch.push_str(&format!(
+            "old mode {:06o}\nnew mode {:06o}\n",
+            old.mode(),
+            new.mode()
+        )),
+        _ => {}
+    }
+    let similarity = match change.kind() {
+        ChangeKind::Renamed(score) => Some(("rename", score)),
+        ChangeKind::Copied(score) => Some(("copy", score)),
+        _ => None,
+    };
+    if let Some((verb, score)) = similarity {
+        patch.push_str(&format!(
+            "similarity index {}%\n{} from {}\n{} to {}\n",
+            score * 100 / MAX_SCORE,
+            verb,
+            old_path,
+            verb,
+            new_path
+        ));
+    }
+    let short_sha = |file: Option<&DiffFile>| match file {
+        Some(file) => file.sha().to_string()[..7].to_string(),
+        None => "0000000".to_string(),
+    };
+    if old.map(|file| file.sha()) == new.map(|file| file.sha()) {
+        return patch;
+    }
+    patch.push_str(&format!("index {}..{}", short_sha(old), short_sha(new)));
+    match (old, new) {
+        (Some(old), Some(new)) if old.mode() == new.mode() => {
+            patch.push_str(&format!(" {:06o}\n", old.mode()))
+        }
+        _ => patch.push('\n'),
+    }
+    let old_name = old.map_or(NULL_PATH.to_string(), |file| format!("a/{}", file.path()));
+    let new_name = new.map_or(NULL_PATH.to_string(), |file| format!("b/{}", file.path()));
+    let old_data: &[u8] = old.map_or(&[], |file| file.data());
+    let new_data: &[u8] = new.map_or(&[], |file| file.data());
+    if is_binary(old_data) || is_binary(new_data) {
+        patch.push_str(&format!(
+            "Binary files {} and {} differ\n",
+            old_name, new_name
+        ));
+        return patch;
+    }
+    patch.push_str(&format!("--- {}\n+++ {}\n", old_name, new_name));
+    let old_lines = split_lines(old_data);
+    let new_lines = split_lines(new_data);
+    let edits = diff_lines(&old_lines, &new_lines, algorithm);
+    patch.push_str(&format_hunks(&old_lines, &new_lines, &edits, context));
+    patch
+}
+
+/// The change's line for `--name-status`, with both paths for renames and copies
+pub fn format_name_status(change: &FileChange) -> String {
+    match (change.kind(), change.old_file()) {
+        (ChangeKind::Renamed(_) | ChangeKind::Copied(_), Some(old)) => format!(
+            "{}\t{}\t{}\n",
+            change.kind().status(),
+            old.path(),
+            change.path()
+        ),
+        _ => format!("{}\t{}\n", change.kind().status(), change.path()),
+    }
+}
+
+/// Groups the edits into hunks of changes with up to `context` equal lines either side.
+/// Hunks whose context would touch or overlap are joined into one
+pub fn group_hunks(edits: &[Edit], context: usize) -> Vec<Range<usize>> {
+    let mut hunks: Vec<Range<usize>> = vec![];
+    for (i, edit) in edits.iter().enumerate() {
+        if let Edit::Equal(_, _) = edit {
+            continue;
+        }
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(edits.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.end => last.end = end,
+            _ => hunks.push(start..end),
+        }
+    }
+    hunks
+}
+
+/// The hunks of a unified diff, each with its `@@` header
+pub fn format_hunks(old: &[&[u8]], new: &[&[u8]], edits: &[Edit], context: usize) -> String {
+    let mut out = String::new();
+    // how many old and new lines come before each edit
+    let mut positions = Vec::with_capacity(edits.len());
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for edit in edits.iter() {
+        positions.push((old_pos, new_pos));
+        match edit {
+            Edit::Equal(_, _) => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Edit::Delete(_) => old_pos += 1,
+            Edit::Insert(_) => new_pos += 1,
+        }
+    }
+    for hunk in group_hunks(edits, context) {
+        let (old_start, new_start) = positions[hunk.start];
+        let hunk_edits = &edits[hunk];
+        let old_count = hunk_edits
+            .iter()
+            .filter(|e| !matches!(e, Edit::Insert(_)))
+            .count();
+        let new_count = hunk_edits
+            .iter()
+            .filter(|e| !matches!(e, Edit::Delete(_)))
+            .count();
+        out.push_str(&format!(
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_count),
+            hunk_range(new_start, new_count)
+        ));
+        if let Some(function) = function_context(&old[..old_start]) {
+            out.push(' ');
+            out.push_str(&function);
+        }
+        out.push('\n');
+        for edit in hunk_edits {
+            let (marker, line) = match *edit {
+                Edit::Equal(x, _) => (' ', old[x]),
+                Edit::Delete(x) => ('-', old[x]),
+                Edit::Insert(y) => ('+', new[y]),
+            };
+            out.push(marker);
+            out.push_str(&String::from_utf8_lossy(line));
+            if !line.ends_with(b"\n") {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
+
+/// A hunk's start line and length. Git leaves the length off when it is one, and an empty
+/// range starts at the line before it
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+/// The nearest line above the hunk that looks like the start of a function, which for git's
+/// default rule is any line starting with a letter, `_` or `$`
+fn function_context(lines_before: &[&[u8]]) -> Option<String> {
+    let line = lines_before.iter().rev().find(|line| {
+        line.first()
+            .is_some_and(|&b| b.is_ascii_alphabetic() || b == b'_' || b == b'$')
+    })?;
+    let text = String::from_utf8_lossy(line);
+    let text = text.trim_end();
+    Some(text.chars().take(80).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::myers_diff;
+
+    #[test]
+    fn unified_hunks() {
+        let old: Vec<String> = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let mut new = old.clone();
+        new[1] = "two\n".to_string();
+        new.remove(17);
+        new.push("21".to_string());
+        let old_lines: Vec<&[u8]> = old.iter().map(|l| l.as_bytes()).collect();
+        let new_lines: Vec<&[u8]> = new.iter().map(|l| l.as_bytes()).collect();
+        let edits = myers_diff(&old_lines, &new_lines);
+        let hunks = format_hunks(&old_lines, &new_lines, &edits, 3);
+        let expected = "@@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+                        @@ -15,6 +15,6 @@\n 15\n 16\n 17\n-18\n 19\n 20\n+21\n\\ No newline at end of file\n";
+        assert_eq!(hunks, expected);
+        // with more context the two hunks join up
+        assert_eq!(group_hunks(&edits, 7).len(), 2);
+        assert_eq!(group_hunks(&edits, 8).len(), 1);
+    }
+}
@@ -1,6 +1,11 @@
 mod argparser;
 pub mod commands;
+pub mod config;
+pub mod diff;
+pub mod revision;
 pub mod structures;
+#[cfg(test)]
+mod test_repo;
 pub mod utils;
 
 use std::io;
@@ -0,0 +1,130 @@
+use std::io;
+
+use regex::Regex;
+
+use crate::{
+    diff::{commit_changes, Pickaxe},
+    structures::{load_tree_from_sha, Commit, Sha, TreeNode},
+};
+
+/// What a commit has to match for a walk to show it. Commits that don't match are still
+/// walked through, so their parents can be shown
+#[derive(Clone, Debug, Default)]
+pub struct CommitFilter {
+    /// Matched against the author's name and email, any one of them is enough
+    pub authors: Vec<Regex>,
+    /// Matched against the committer's name and email, any one of them is enough
+    pub committers: Vec<Regex>,
+    /// Matched against the message, any one of them is enough unless `all_match` is set
+    pub messages: Vec<Regex>,
+    pub all_match: bool,
+    /// Shows the commits whose message doesn't match instead
+    pub invert_grep: bool,
+    /// The oldest committer date shown, as a unix timestamp
+    pub since: Option<i64>,
+    /// The newest committer date shown, as a unix timestamp
+    pub until: Option<i64>,
+    /// Looked for in what each commit changed
+    pub pickaxe: Option<Pickaxe>,
+}
+
+impl CommitFilter {
+    /// Whether the filter lets every commit through
+    pub fn is_empty(&self) -> bool {
+        self.authors.is_empty()
+            && self.committers.is_empty()
+            && self.messages.is_empty()
+            && self.since.is_none()
+            && self.until.is_none()
+            && self.pickaxe.is_none()
+    }
+
+    pub fn matches(&self, commit: &Commit) -> bool {
+        let date = commit.committer().date().timestamp();
+        if date < self.since.unwrap_or(i64::MIN) || date > self.until.unwrap_or(i64::MAX) {
+            return false;
+        }
+        let matches_any = |patterns: &[Regex], text: &str| {
+            patterns.is_empty() || patterns.iter().any(|pattern| pattern.is_match(text))
+        };
+        if !matches_any(&self.authors, &commit.author().to_string_without_date())
+            || !matches_any(
+                &self.committers,
+                &commit.committer().to_string_without_date(),
+            )
+        {
+            return false;
+        }
+        if self.messages.is_empty() {
+            return true;
+        }
+        let message = commit.full_message();
+        let matched = match self.all_match {
+            true => self
+                .messages
+                .iter()
+                .all(|pattern| pattern.is_match(&message)),
+            false => self
+                .messages
+                .iter()
+                .any(|pattern| pattern.is_match(&message)),
+        };
+        matched != self.invert_grep
+    }
+
+    /// Whether the pickaxe finds what it looks for in the commit's changes, only looking at
+    /// those within the paths when there are any. Always true without a pickaxe
+    pub fn matches_changes(
+        &self,
+        commit: &Commit,
+        first_parent: bool,
+        paths: &[String],
+    ) -> Result<bool, io::Error> {
+        match &self.pickaxe {
+            Some(pickaxe) => Ok(commit_changes(commit, first_parent)?
+                .iter()
+                .filter(|change| {
+                    paths.is_empty() || paths.iter().any(|path| is_within(change.path(), path))
+                })
+                .any(|change| pickaxe.matches(change))),
+            None => Ok(true),
+        }
+    }
+}
+
+/// Whether the path is the other path or somewhere inside it
+pub fn is_within(path: &str, other: &str) -> bool {
+    let other = other.trim_end_matches('/');
+    other.is_empty()
+        || other == "."
+        || path == other
+        || path
+            .strip_prefix(other)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Whether the two trees hold the same thing at every one of the paths. A path is the same
+/// when it is missing from both
+pub fn same_at_paths(old_tree: &Sha, new_tree: &Sha, paths: &[String]) -> Result<bool, io::Error> {
+    let old_tree = load_tree_from_sha(old_tree)?;
+    let new_tree = load_tree_from_sha(new_tree)?;
+    for path in paths.iter() {
+        let sha_and_mode =
+            |node: Option<TreeNode>| node.map(|node| (node.mode_bits(), node.sha().clone()));
+        if sha_and_mode(old_tree.node_at(path)?) != sha_and_mode(new_tree.node_at(path)?) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Whether the tree holds anything at any of the paths
+pub fn has_any_path(tree: &Sha, paths: &[String]) -> Result<bool, io::Error> {
+    let tree = load_tree_from_sha(tree)?;
+    for path in paths.iter() {
+        if tree.node_at(path)?.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
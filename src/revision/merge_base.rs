@@ -0,0 +1,202 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    io, slice,
+};
+
+use crate::structures::{load_commit_from_sha, Sha};
+
+use super::is_ancestor;
+
+const PARENT1: u8 = 1;
+const PARENT2: u8 = 2;
+const STALE: u8 = 4;
+const RESULT: u8 = 8;
+
+/// The best common ancestors of `one` and any of `others`, newest first. A common ancestor is
+/// best when no other common ancestor descends from it. With more than one other commit this
+/// is the merge base of `one` and a merge of all the others
+pub fn merge_bases(one: &Sha, others: &[Sha]) -> Result<Vec<Sha>, io::Error> {
+    if others.iter().any(|other| other == one) {
+        return Ok(vec![one.clone()]);
+    }
+    let common = paint_down_to_common(one, others)?;
+    remove_redundant(common)
+}
+
+/// The best common ancestors of all the commits together, as used for an octopus merge
+pub fn merge_bases_octopus(commits: &[Sha]) -> Result<Vec<Sha>, io::Error> {
+    let (first, rest) = match commits.split_first() {
+        Some(split) => split,
+        None => return Ok(vec![]),
+    };
+    let mut bases = vec![first.clone()];
+    for commit in rest.iter() {
+        let mut next = vec![];
+        for base in bases.iter() {
+            for found in merge_bases(base, slice::from_ref(commit))? {
+                if !next.contains(&found) {
+                    next.push(found);
+                }
+            }
+        }
+        bases = next;
+    }
+    Ok(bases)
+}
+
+/// Walks back from all the commits newest first, marking each commit with which side it can
+/// be reached from. Commits reachable from both sides are common ancestors, and everything
+/// behind them goes stale so the walk can stop once only stale commits are left
+fn paint_down_to_common(one: &Sha, others: &[Sha]) -> Result<Vec<Sha>, io::Error> {
+    let mut flags: HashMap<Sha, u8> = HashMap::new();
+    let mut dates: HashMap<Sha, i64> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    let mut queued = 0;
+    let mut enqueue = |sha: &Sha, queue: &mut BinaryHeap<(i64, Reverse<usize>, Sha)>| {
+        let date = match dates.get(sha) {
+            Some(date) => *date,
+            None => {
+                let date = load_commit_from_sha(sha)?.committer().date().timestamp();
+                dates.insert(sha.clone(), date);
+                date
+            }
+        };
+        queued += 1;
+        queue.push((date, Reverse(queued), sha.clone()));
+        Ok::<(), io::Error>(())
+    };
+    flags.insert(one.clone(), PARENT1);
+    enqueue(one, &mut queue)?;
+    for other in others.iter() {
+        *flags.entry(other.clone()).or_default() |= PARENT2;
+        enqueue(other, &mut queue)?;
+    }
+
+    let mut common = vec![];
+    let still_interesting = |queue: &BinaryHeap<(i64, Reverse<usize>, Sha)>,
+                             flags: &HashMap<Sha, u8>| {
+        queue
+            .iter()
+            .any(|(_, _, sha)| flags.get(sha).copied().unwrap_or(0) & STALE == 0)
+    };
+    while still_interesting(&queue, &flags) {
+        let (_, _, sha) = match queue.pop() {
+            Some(next) => next,
+            None => break,
+        };
+        let mut sides = flags.get(&sha).copied().unwrap_or(0) & (PARENT1 | PARENT2 | STALE);
+        if sides == PARENT1 | PARENT2 {
+            let mark = flags.entry(sha.clone()).or_default();
+            if *mark & RESULT == 0 {
+                common.push(sha.clone());
+            }
+            *mark |= RESULT | STALE;
+            // everything behind a common ancestor is a worse one
+            sides |= STALE;
+        }
+        if let Some(parents) = load_commit_from_sha(&sha)?.parent() {
+            for parent in parents.iter() {
+                let mark = flags.entry(parent.clone()).or_default();
+                if *mark & sides == sides {
+                    continue;
+                }
+                *mark |= sides;
+                enqueue(parent, &mut queue)?;
+            }
+        }
+    }
+    Ok(common)
+}
+
+/// Drops the commits that are ancestors of another commit in the list
+fn remove_redundant(commits: Vec<Sha>) -> Result<Vec<Sha>, io::Error> {
+    let mut best = vec![];
+    for commit in commits.iter() {
+        let mut redundant = false;
+        for other in commits.iter() {
+            if other != commit && is_ancestor(commit, other)? {
+                redundant = true;
+                break;
+            }
+        }
+        if !redundant {
+            best.push(commit.clone());
+        }
+    }
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_repo::TestRepo;
+
+    #[test]
+    fn criss_cross_merges_have_two_bases() {
+        let repo = TestRepo::new("merge_base_criss_cross");
+        let a = repo.commit("a", &[], &[("f", "a\n")]);
+        let b = repo.commit("b", &[&a], &[("f", "b\n")]);
+        let c = repo.commit("c", &[&a], &[("f", "c\n")]);
+        let d = repo.commit("d", &[&b, &c], &[("f", "d\n")]);
+        let e = repo.commit("e", &[&c, &b], &[("f", "e\n")]);
+        // newest first, and nothing behind them
+        let bases = vec![c.clone(), b.clone()];
+        assert_eq!(
+            paint_down_to_common(&d, slice::from_ref(&e)).unwrap(),
+            bases
+        );
+        assert_eq!(merge_bases(&d, slice::from_ref(&e)).unwrap(), bases);
+        assert_eq!(
+            merge_bases(&d, slice::from_ref(&d)).unwrap(),
+            vec![d.clone()]
+        );
+        assert_eq!(
+            merge_bases(&b, slice::from_ref(&d)).unwrap(),
+            vec![b.clone()]
+        );
+    }
+
+    #[test]
+    fn redundant_bases_are_dropped() {
+        let repo = TestRepo::new("merge_base_redundant");
+        let a = repo.commit("a", &[], &[]);
+        let b = repo.commit("b", &[&a], &[]);
+        let c = repo.commit("c", &[&a], &[]);
+        let d = repo.commit("d", &[&b], &[]);
+        let kept = remove_redundant(vec![a, c.clone(), b, d.clone()]).unwrap();
+        assert_eq!(kept, [c, d]);
+    }
+
+    #[test]
+    fn octopus_bases_are_common_to_all() {
+        let repo = TestRepo::new("merge_base_octopus");
+        let r = repo.commit("r", &[], &[]);
+        let p = repo.commit("p", &[&r], &[]);
+        let q = repo.commit("q", &[&p], &[]);
+        let s = repo.commit("s", &[&p], &[]);
+        let t = repo.commit("t", &[&r], &[]);
+        let tips = [q.clone(), s.clone(), t.clone()];
+        assert_eq!(merge_bases_octopus(&tips).unwrap(), vec![r]);
+        // without --octopus, the others are taken as if they were merged together
+        assert_eq!(merge_bases(&q, &[s, t]).unwrap(), vec![p]);
+        assert_eq!(
+            merge_bases_octopus(slice::from_ref(&q)).unwrap(),
+            vec![q.clone()]
+        );
+    }
+
+    #[test]
+    fn ancestors_are_found_through_merges() {
+        let repo = TestRepo::new("merge_base_is_ancestor");
+        let a = repo.commit("a", &[], &[]);
+        let b = repo.commit("b", &[&a], &[]);
+        let c = repo.commit("c", &[&a], &[]);
+        let d = repo.commit("d", &[&b, &c], &[]);
+        assert!(is_ancestor(&a, &d).unwrap());
+        assert!(is_ancestor(&c, &d).unwrap());
+        assert!(is_ancestor(&d, &d).unwrap());
+        assert!(!is_ancestor(&d, &a).unwrap());
+        assert!(!is_ancestor(&b, &c).unwrap());
+    }
+}
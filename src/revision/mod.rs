@@ -0,0 +1,9 @@
+mod filter;
+mod merge_base;
+mod rev_parse;
+mod walk;
+
+pub use filter::*;
+pub use merge_base::*;
+pub use rev_parse::*;
+pub use walk::*;
@@ -0,0 +1,618 @@
+use std::io;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    exit_with_message,
+    structures::{
+        find_objects_with_prefix, load_commit_from_sha, load_tag_from_sha, load_tree_from_sha,
+        object_kind, shortest_unique_prefix, Author, IndexFile, RefFile, Sha,
+    },
+    utils::{previous_checkout, read_ref},
+};
+
+use super::{merge_bases, RevWalk};
+
+/// Where a ref named without its `refs/...` prefix is looked for, in order, as a prefix and
+/// suffix around the name
+const REF_RULES: [(&str, &str); 6] = [
+    ("", ""),
+    ("refs/", ""),
+    ("refs/tags/", ""),
+    ("refs/heads/", ""),
+    ("refs/remotes/", ""),
+    ("refs/remotes/", "/HEAD"),
+];
+
+/// A commit a revision argument stands for, either one to walk from or one whose history is
+/// left out
+#[derive(Clone, Debug, PartialEq)]
+pub enum Revision {
+    Include(Sha),
+    Exclude(Sha),
+}
+
+/// The object a revision names, exiting if it doesn't name one
+pub fn resolve_revision(spec: &str) -> Result<Sha, io::Error> {
+    match try_resolve_revision(spec)? {
+        Some(sha) => Ok(sha),
+        None => exit_with_message(&unknown_revision(spec)),
+    }
+}
+
+/// What git says about an argument that isn't a revision
+pub fn unknown_revision(spec: &str) -> String {
+    format!(
+        "fatal: ambiguous argument '{}': unknown revision or path not in the working tree.",
+        spec
+    )
+}
+
+/// The object a revision names: a ref, a sha or a prefix of one, `@{-n}` for the nth branch
+/// checked out before this one, `:/message` for the newest commit with a matching message,
+/// `:path` for a file in the index, or any of these followed by `:path` for something in its
+/// tree. `~n`, `^n` and `^{type}` can follow to move on to an ancestor, parent or peeled object
+pub fn try_resolve_revision(spec: &str) -> Result<Option<Sha>, io::Error> {
+    if let Some(pattern) = spec.strip_prefix(":/") {
+        let mut tips = vec![];
+        for tip in ref_tips()? {
+            tips.extend(peel(&tip, "commit")?);
+        }
+        return newest_matching(&tips, pattern);
+    }
+    if let Some(path) = spec.strip_prefix(':') {
+        return index_object(path);
+    }
+    if let Some((rev, path)) = split_tree_path(spec) {
+        let tree = match resolve_suffixes(rev)? {
+            Some(sha) => peel(&sha, "tree")?,
+            None => None,
+        };
+        return match tree {
+            Some(tree) => Ok(load_tree_from_sha(&tree)?
+                .node_at(path)?
+                .map(|node| node.sha().clone())),
+            None => Ok(None),
+        };
+    }
+    resolve_suffixes(spec)
+}
+
+/// The commits a revision argument stands for. `A..B` is B without the history of A, and
+/// `A...B` is both without the history of their merge bases, with HEAD for a missing side.
+/// `^A` leaves out the history of A. None if the argument isn't a revision
+pub fn parse_revision_arg(arg: &str) -> Result<Option<Vec<Revision>>, io::Error> {
+    if let Some(range) = parse_range(arg)? {
+        return Ok(Some(range));
+    }
+    if let Some(excluded) = arg.strip_prefix('^') {
+        return Ok(try_resolve_revision(excluded)?.map(|sha| vec![Revision::Exclude(sha)]));
+    }
+    Ok(try_resolve_revision(arg)?.map(|sha| vec![Revision::Include(sha)]))
+}
+
+fn parse_range(arg: &str) -> Result<Option<Vec<Revision>>, io::Error> {
+    let (start, symmetric) = match (arg.find("..."), arg.find("..")) {
+        (Some(start), _) => (start, true),
+        (None, Some(start)) => (start, false),
+        (None, None) => return Ok(None),
+    };
+    let end = start + if symmetric { 3 } else { 2 };
+    let from = match &arg[..start] {
+        "" => "HEAD",
+        from => from,
+    };
+    let to = match &arg[end..] {
+        "" => "HEAD",
+        to => to,
+    };
+    let (from, to) = match (try_resolve_revision(from)?, try_resolve_revision(to)?) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Ok(None),
+    };
+    if !symmetric {
+        return Ok(Some(vec![Revision::Include(to), Revision::Exclude(from)]));
+    }
+    let bases = match (peel(&from, "commit")?, peel(&to, "commit")?) {
+        (Some(from), Some(to)) => merge_bases(&from, &[to])?,
+        _ => return Ok(None),
+    };
+    let mut revisions = vec![Revision::Include(to), Revision::Include(from)];
+    revisions.extend(bases.into_iter().map(Revision::Exclude));
+    Ok(Some(revisions))
+}
+
+/// Splits `rev:path` at the first colon outside of any `^{...}`
+fn split_tree_path(spec: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    for (i, ch) in spec.char_indices() {
+        match ch {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ':' if depth == 0 && i > 0 => return Some((&spec[..i], &spec[i + 1..])),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Resolves the operators at the end of the spec, each applying to everything before it
+fn resolve_suffixes(spec: &str) -> Result<Option<Sha>, io::Error> {
+    if let (true, Some(start)) = (spec.ends_with('}'), spec.rfind("^{")) {
+        let base = match resolve_suffixes(&spec[..start])? {
+            Some(base) => base,
+            None => return Ok(None),
+        };
+        let inner = &spec[start + 2..spec.len() - 1];
+        return match inner.strip_prefix('/') {
+            Some(pattern) => match peel(&base, "commit")? {
+                Some(commit) => newest_matching(&[commit], pattern),
+                None => Ok(None),
+            },
+            None => peel(&base, inner),
+        };
+    }
+    let digits_start = spec.trim_end_matches(|ch: char| ch.is_ascii_digit()).len();
+    let operator = spec[..digits_start].chars().last();
+    if let Some(operator @ ('~' | '^')) = operator {
+        let count = match &spec[digits_start..] {
+            "" => 1,
+            digits => match digits.parse::<usize>() {
+                Ok(count) => count,
+                Err(_) => return Ok(None),
+            },
+        };
+        let commit = match resolve_suffixes(&spec[..digits_start - 1])? {
+            Some(base) => peel(&base, "commit")?,
+            None => None,
+        };
+        return match (commit, operator) {
+            (Some(commit), '~') => ancestor(commit, count),
+            (Some(commit), _) => nth_parent(commit, count),
+            (None, _) => Ok(None),
+        };
+    }
+    resolve_name(spec)
+}
+
+/// A ref, `@{-n}` or a sha, with no operators after it
+fn resolve_name(name: &str) -> Result<Option<Sha>, io::Error> {
+    if name.is_empty() {
+        return Ok(None);
+    }
+    if name == "@" {
+        return read_ref("HEAD");
+    }
+    if let Some(n) = name.strip_prefix("@{-").and_then(|n| n.strip_suffix('}')) {
+        let previous = match n.parse() {
+            Ok(n) => previous_checkout(n)?,
+            Err(_) => None,
+        };
+        return match previous {
+            Some(previous) if is_full_sha(&previous) => Ok(previous.parse().ok()),
+            Some(branch) => read_ref(&format!("refs/heads/{}", branch)),
+            None => Ok(None),
+        };
+    }
+    if is_full_sha(name) {
+        return Ok(name.parse().ok());
+    }
+    // only refs in capitals like HEAD and MERGE_HEAD live at the top of the repository
+    let top_level =
+        name.starts_with("refs/") || name.chars().all(|ch| ch.is_ascii_uppercase() || ch == '_');
+    for (i, (prefix, suffix)) in REF_RULES.iter().enumerate() {
+        if i == 0 && !top_level {
+            continue;
+        }
+        if let Some(sha) = read_ref(&format!("{}{}{}", prefix, name, suffix))? {
+            return Ok(Some(sha));
+        }
+    }
+    if name.len() >= 4 && name.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        let found = find_objects_with_prefix(name)?;
+        if found.len() > 1 {
+            print_ambiguous(name, &found)?;
+        }
+        if found.len() == 1 {
+            return Ok(found.into_iter().next());
+        }
+    }
+    Ok(None)
+}
+
+/// Explains that a short sha starts more than one object's, listing tags first, then
+/// commits, trees and blobs
+fn print_ambiguous(prefix: &str, candidates: &[Sha]) -> Result<(), io::Error> {
+    let mut described = vec![];
+    for sha in candidates.iter() {
+        let kind = object_kind(sha)?.unwrap_or_default();
+        let abbrev = shortest_unique_prefix(sha, 7)?;
+        let description = match kind.as_str() {
+            "commit" => {
+                let commit = load_commit_from_sha(sha)?;
+                let date = commit.author().to_string_date_short();
+                format!("{} commit {} - {}", abbrev, date, commit.subject())
+            }
+            "tag" => {
+                let tag = load_tag_from_sha(sha)?;
+                let date = tag.tagger().to_string_date_short();
+                format!("{} tag {} - {}", abbrev, date, tag.tag())
+            }
+            _ => format!("{} {}", abbrev, kind),
+        };
+        let order = ["tag", "commit", "tree", "blob"]
+            .iter()
+            .position(|&known| known == kind)
+            .unwrap_or(4);
+        described.push((order, sha.clone(), description));
+    }
+    described.sort();
+    println!("error: short object ID {} is ambiguous", prefix);
+    println!("hint: The candidates are:");
+    for (_, _, description) in described.iter() {
+        println!("hint:   {}", description);
+    }
+    Ok(())
+}
+
+fn is_full_sha(name: &str) -> bool {
+    name.len() == 40 && name.chars().all(|ch| ch.is_ascii_hexdigit())
+}
+
+/// Follows tags, and commits to their tree, until reaching an object of the kind. An empty
+/// kind only follows tags, and `object` stays where it is
+fn peel(sha: &Sha, kind: &str) -> Result<Option<Sha>, io::Error> {
+    let mut sha = sha.clone();
+    loop {
+        let found = match object_kind(&sha)? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        if found == kind || kind == "object" {
+            return Ok(Some(sha));
+        }
+        match found.as_str() {
+            "tag" => sha = load_tag_from_sha(&sha)?.object().clone(),
+            "commit" if kind == "tree" => sha = load_commit_from_sha(&sha)?.tree_sha().clone(),
+            _ if kind.is_empty() => return Ok(Some(sha)),
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// The commit `count` first parents back
+fn ancestor(commit: Sha, count: usize) -> Result<Option<Sha>, io::Error> {
+    let mut commit = commit;
+    for _ in 0..count {
+        commit = match load_commit_from_sha(&commit)?.parent() {
+            Some(parents) => parents[0].clone(),
+            None => return Ok(None),
+        };
+    }
+    Ok(Some(commit))
+}
+
+/// The commit's nth parent, or the commit itself for the 0th
+fn nth_parent(commit: Sha, n: usize) -> Result<Option<Sha>, io::Error> {
+    if n == 0 {
+        return Ok(Some(commit));
+    }
+    let commit = load_commit_from_sha(&commit)?;
+    Ok(commit
+        .parent()
+        .as_ref()
+        .and_then(|parents| parents.get(n - 1).cloned()))
+}
+
+/// HEAD and everything under refs, for searches that start from all of them
+fn ref_tips() -> Result<Vec<Sha>, io::Error> {
+    let mut tips: Vec<Sha> = read_ref("HEAD")?.into_iter().collect();
+    tips.extend(
+        RefFile::list("refs")?
+            .into_iter()
+            .map(|reff| reff.sha().clone()),
+    );
+    Ok(tips)
+}
+
+/// The newest commit reachable from the tips whose message matches the pattern
+fn newest_matching(tips: &[Sha], pattern: &str) -> Result<Option<Sha>, io::Error> {
+    let pattern = match Regex::new(pattern) {
+        Ok(pattern) => pattern,
+        Err(_) => return Ok(None),
+    };
+    let mut walk = RevWalk::new();
+    for tip in tips.iter() {
+        walk.push(tip)?;
+    }
+    for commit in walk {
+        let commit = commit?;
+        if pattern.is_match(&commit.full_message()) {
+            return Ok(Some(commit.sha().clone()));
+        }
+    }
+    Ok(None)
+}
+
+/// The author of the newest commit reachable from any ref whose `Name <email>` matches the
+/// pattern, ignoring case. This is how `commit --author` finds someone by part of their name
+pub fn newest_author_matching(pattern: &str) -> Result<Option<Author>, io::Error> {
+    // like git, a pattern that isn't a valid regex is looked for as it is
+    let pattern = RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .or_else(|_| {
+            RegexBuilder::new(&regex::escape(pattern))
+                .case_insensitive(true)
+                .build()
+        })
+        .unwrap();
+    let mut walk = RevWalk::new();
+    for tip in ref_tips()? {
+        if let Some(commit) = peel(&tip, "commit")? {
+            walk.push(&commit)?;
+        }
+    }
+    for commit in walk {
+        let commit = commit?;
+        if pattern.is_match(&commit.author().to_string_without_date()) {
+            return Ok(Some(commit.author().clone()));
+        }
+    }
+    Ok(None)
+}
+
+/// The blob for a path in the index, as `path` or `n:path` for a conflict stage
+fn index_object(spec: &str) -> Result<Option<Sha>, io::Error> {
+    let (stage, path) = match spec.split_once(':') {
+        Some((stage @ ("0" | "1" | "2" | "3"), path)) => (stage.parse().unwrap(), path),
+        _ => (0, spec),
+    };
+    let index = IndexFile::new(0);
+    let entry = index
+        .index_entries()
+        .iter()
+        .find(|entry| entry.stage() == stage && entry.get_readable_file_name() == path);
+    Ok(entry.map(|entry| entry.file_sha().clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_repo::TestRepo;
+
+    /// Two commits on main and one beside them, merged into main
+    fn merged(repo: &TestRepo) -> [Sha; 4] {
+        let first = repo.commit("first", &[], &[("a", "1")]);
+        let second = repo.commit("second", &[&first], &[("a", "2")]);
+        let side = repo.commit("side", &[&first], &[("a", "1"), ("b", "3")]);
+        let merge = repo.commit("merge", &[&second, &side], &[("a", "2"), ("b", "3")]);
+        repo.set_head(&merge);
+        [first, second, side, merge]
+    }
+
+    fn resolve(spec: &str) -> Option<Sha> {
+        try_resolve_revision(spec).unwrap()
+    }
+
+    #[test]
+    fn tree_path_splits_outside_braces() {
+        assert_eq!(split_tree_path("HEAD:src/a.rs"), Some(("HEAD", "src/a.rs")));
+        assert_eq!(
+            split_tree_path("HEAD^{/fix: typo}:a"),
+            Some(("HEAD^{/fix: typo}", "a"))
+        );
+        assert_eq!(split_tree_path("HEAD~2"), None);
+        assert_eq!(split_tree_path(":a"), None);
+    }
+
+    #[test]
+    fn ancestors_parents_and_peeling() {
+        let repo = TestRepo::new("rev_parse_suffixes");
+        let [first, second, side, merge] = merged(&repo);
+        assert_eq!(resolve("main~2"), Some(first.clone()));
+        assert_eq!(resolve("HEAD~"), Some(second.clone()));
+        assert_eq!(resolve("main^"), Some(second.clone()));
+        assert_eq!(resolve("main^2"), Some(side.clone()));
+        assert_eq!(resolve("@^2~1"), Some(first.clone()));
+        assert_eq!(resolve("main^0"), Some(merge.clone()));
+        assert_eq!(resolve("main^3"), None);
+        assert_eq!(resolve("main~3"), None);
+
+        let tree = load_commit_from_sha(&merge).unwrap().tree_sha().clone();
+        let tag = repo.write_object(
+            "tag",
+            format!(
+                "object {}\ntype commit\ntag v1\ntagger A U Thor <author@example.com> 1700000000 +0000\n\nrelease\n",
+                merge
+            )
+            .as_bytes(),
+        );
+        fs::write(".re_flogged/refs/tags/v1", format!("{}\n", tag)).unwrap();
+        assert_eq!(resolve("v1"), Some(tag));
+        assert_eq!(resolve("v1^{}"), Some(merge.clone()));
+        assert_eq!(resolve("v1^{commit}"), Some(merge.clone()));
+        assert_eq!(resolve("v1^{tree}"), Some(tree.clone()));
+        assert_eq!(resolve("main^{tree}"), Some(tree));
+        assert_eq!(resolve("v1~1"), Some(second));
+        assert_eq!(resolve("main^{blob}"), None);
+        assert_eq!(resolve("main^{/fir}"), Some(first));
+    }
+
+    #[test]
+    fn unknown_and_ambiguous_names() {
+        let repo = TestRepo::new("rev_parse_names");
+        let [first, second, _, merge] = merged(&repo);
+        assert_eq!(resolve("nope"), None);
+        assert_eq!(resolve("nope~1"), None);
+        assert_eq!(resolve("main~x"), None);
+        assert_eq!(resolve(""), None);
+        assert_eq!(resolve(&merge.to_string()[..7]), Some(merge.clone()));
+        // a tag wins over a branch of the same name, as it comes first in the rules
+        fs::write(".re_flogged/refs/heads/dup", format!("{}\n", first)).unwrap();
+        fs::write(".re_flogged/refs/tags/dup", format!("{}\n", second)).unwrap();
+        assert_eq!(resolve("dup"), Some(second.clone()));
+        assert_eq!(resolve("heads/dup"), Some(first));
+        assert_eq!(resolve("refs/tags/dup"), Some(second));
+        // lowercase names aren't looked for at the top of the repository
+        fs::write(".re_flogged/lower", format!("{}\n", merge)).unwrap();
+        assert_eq!(resolve("lower"), None);
+
+        let (code, printed) = repo.flog(&["rev-parse", "nope"]);
+        assert_eq!(
+            (code, printed),
+            (1, format!("{}\n", unknown_revision("nope")))
+        );
+        let (code, printed) = repo.flog(&["rev-parse", "--verify", "nope"]);
+        assert_eq!(
+            (code, printed.as_str()),
+            (1, "fatal: Needed a single revision\n")
+        );
+    }
+
+    #[test]
+    fn paths_in_trees() {
+        let repo = TestRepo::new("rev_parse_tree_paths");
+        let [first, ..] = merged(&repo);
+        let one = repo.write_object("blob", b"1");
+        let two = repo.write_object("blob", b"2");
+        assert_eq!(resolve("main:a"), Some(two));
+        assert_eq!(resolve("main~2:a"), Some(one.clone()));
+        assert_eq!(resolve(&format!("{}:a", first)), Some(one));
+        assert_eq!(resolve("main^2:b"), Some(repo.write_object("blob", b"3")));
+        assert_eq!(resolve("main:missing"), None);
+        assert_eq!(resolve("nope:a"), None);
+    }
+
+    #[test]
+    fn paths_in_the_index() {
+        let repo = TestRepo::new("rev_parse_index_paths");
+        let blob = |contents: &str| Some(repo.write_object("blob", contents.as_bytes()));
+        repo.write_file("a", "kept\n");
+        repo.write_file("c", "base\n");
+        repo.flog(&["add", "a", "c"]);
+        repo.flog(&["commit", "-m", "base"]);
+        assert_eq!(resolve(":a"), blob("kept\n"));
+        assert_eq!(resolve(":0:c"), blob("base\n"));
+        assert_eq!(resolve(":1:c"), None);
+
+        // a conflicted merge leaves each side's version in the index
+        repo.flog(&["branch", "other"]);
+        repo.write_file("c", "ours\n");
+        repo.flog(&["add", "c"]);
+        repo.flog(&["commit", "-m", "ours"]);
+        repo.flog(&["checkout", "other"]);
+        repo.write_file("c", "theirs\n");
+        repo.flog(&["add", "c"]);
+        repo.flog(&["commit", "-m", "theirs"]);
+        repo.flog(&["checkout", "main"]);
+        assert_eq!(repo.flog(&["merge", "other"]).0, 1);
+        assert_eq!(resolve(":1:c"), blob("base\n"));
+        assert_eq!(resolve(":2:c"), blob("ours\n"));
+        assert_eq!(resolve(":3:c"), blob("theirs\n"));
+        assert_eq!(resolve(":c"), None);
+        assert_eq!(resolve(":a"), blob("kept\n"));
+        assert_eq!(resolve(":missing"), None);
+    }
+
+    #[test]
+    fn previous_checkouts_and_messages() {
+        let repo = TestRepo::new("rev_parse_history");
+        let commit = |contents: &str, message: &str| {
+            repo.write_file("a", contents);
+            repo.flog(&["add", "a"]);
+            repo.flog(&["commit", "-m", message]);
+            resolve("main").unwrap()
+        };
+        let first = commit("1", "first");
+        let second = commit("2", "second");
+        let third = commit("3", "third");
+        assert_eq!(resolve("@{-1}"), None);
+        repo.flog(&["branch", "old", &first.to_string()]);
+        repo.flog(&["checkout", "old"]);
+        repo.flog(&["checkout", &second.to_string()]);
+        repo.flog(&["checkout", "main"]);
+        assert_eq!(resolve("@{-1}"), Some(second.clone()));
+        assert_eq!(resolve("@{-2}"), Some(first.clone()));
+        assert_eq!(resolve("@{-3}"), Some(third.clone()));
+        assert_eq!(resolve("@{-3}~2"), Some(first.clone()));
+        assert_eq!(resolve("@{-4}"), None);
+        assert_eq!(resolve("@{-0}"), None);
+
+        assert_eq!(resolve(":/sec"), Some(second));
+        assert_eq!(resolve(":/^th"), Some(third));
+        assert_eq!(resolve(":/f.rst"), Some(first));
+        assert_eq!(resolve(":/nothing like it"), None);
+    }
+
+    #[test]
+    fn ranges_include_and_exclude() {
+        let repo = TestRepo::new("rev_parse_ranges");
+        let [first, second, side, merge] = merged(&repo);
+        let parse = |arg: &str| parse_revision_arg(arg).unwrap();
+        assert_eq!(
+            parse("main~2..main"),
+            Some(vec![
+                Revision::Include(merge.clone()),
+                Revision::Exclude(first.clone())
+            ])
+        );
+        assert_eq!(
+            parse("main^..HEAD"),
+            Some(vec![
+                Revision::Include(merge.clone()),
+                Revision::Exclude(second.clone())
+            ])
+        );
+        assert_eq!(
+            parse("main^.."),
+            Some(vec![
+                Revision::Include(merge.clone()),
+                Revision::Exclude(second.clone())
+            ])
+        );
+        assert_eq!(
+            parse("main^...main^2"),
+            Some(vec![
+                Revision::Include(side.clone()),
+                Revision::Include(second.clone()),
+                Revision::Exclude(first.clone()),
+            ])
+        );
+        assert_eq!(parse("^main~1"), Some(vec![Revision::Exclude(second)]));
+        assert_eq!(parse("main..nope"), None);
+        assert_eq!(parse("nope...main"), None);
+
+        let (code, printed) = repo.flog(&["rev-parse", "main~2..main"]);
+        assert_eq!((code, printed), (0, format!("{}\n^{}\n", merge, first)));
+    }
+
+    #[test]
+    fn ambiguous_short_shas() {
+        let repo = TestRepo::new("rev_parse_ambiguous");
+        // two blobs whose shas both start 9a80, one packed and one loose
+        let packed = repo.write_packed_object("blob", b"x108248");
+        let loose = repo.write_object("blob", b"x171350");
+        assert_eq!(resolve("9a80"), None);
+        assert_eq!(resolve("9a809"), Some(packed.clone()));
+        assert_eq!(resolve("9a80c"), Some(loose.clone()));
+
+        let (code, printed) = repo.flog(&["rev-parse", "9a80"]);
+        assert_eq!(code, 1);
+        assert_eq!(
+            printed,
+            format!(
+                "error: short object ID 9a80 is ambiguous\nhint: The candidates are:\nhint:   9a80972 blob\nhint:   9a80c38 blob\n{}\n",
+                unknown_revision("9a80")
+            )
+        );
+        let (code, printed) = repo.flog(&["rev-parse", "--short", &loose.to_string()]);
+        assert_eq!((code, printed.as_str()), (0, "9a80c38\n"));
+        let (code, printed) = repo.flog(&["rev-parse", "--short=4", &packed.to_string()]);
+        assert_eq!((code, printed.as_str()), (0, "9a809\n"));
+        let (code, printed) = repo.flog(&["rev-parse", "--disambiguate=9a80"]);
+        assert_eq!((code, printed), (0, format!("{}\n{}\n", packed, loose)));
+    }
+}
@@ -0,0 +1,526 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap, HashSet},
+    io, vec,
+};
+
+use crate::structures::{load_commit_from_sha, Commit, Sha};
+
+use super::{has_any_path, same_at_paths, CommitFilter};
+
+/// A commit waiting in the walk's queue. Newer commits come out first, and commits with the
+/// same date come out in the order they were queued
+struct Queued {
+    date: i64,
+    order: Reverse<usize>,
+    commit: Commit,
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        (self.date, self.order) == (other.date, other.order)
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.date, self.order).cmp(&(other.date, other.order))
+    }
+}
+
+/// The order a walk hands out commits in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sorting {
+    /// Newest committer date first, in the order the walk reaches them
+    Walk,
+    /// No parent before all of its children, otherwise newest committer date first
+    Date,
+    /// No parent before all of its children, keeping each line of history together
+    Topo,
+}
+
+/// Walks back through history from some starting commits, newest committer date first.
+/// Every commit is visited once however many paths lead to it, and commits reachable from a
+/// hidden commit are left out. Commits the filter or paths leave out are still walked through,
+/// they are just not handed out
+pub struct RevWalk {
+    queue: BinaryHeap<Queued>,
+    seen: HashSet<Sha>,
+    hidden: HashSet<Sha>,
+    queued: usize,
+    sorting: Sorting,
+    first_parent: bool,
+    sorted: Option<vec::IntoIter<Commit>>,
+    filter: CommitFilter,
+    paths: Vec<String>,
+    treesame: HashSet<Sha>,
+    pickaxe_missed: HashSet<Sha>,
+    simplified: HashMap<Sha, Vec<Sha>>,
+    rewrite_parents: bool,
+}
+
+impl RevWalk {
+    pub fn new() -> Self {
+        Self {
+            queue: BinaryHeap::new(),
+            seen: HashSet::new(),
+            hidden: HashSet::new(),
+            queued: 0,
+            sorting: Sorting::Walk,
+            first_parent: false,
+            sorted: None,
+            filter: CommitFilter::default(),
+            paths: vec![],
+            treesame: HashSet::new(),
+            pickaxe_missed: HashSet::new(),
+            simplified: HashMap::new(),
+            rewrite_parents: false,
+        }
+    }
+
+    /// Hands out the commits in the given order. Any order other than the walk's own has to
+    /// walk all of history before the first commit comes out
+    pub fn sort(&mut self, sorting: Sorting) {
+        self.sorting = sorting;
+    }
+
+    /// Only follows the first parent of merge commits
+    pub fn first_parent(&mut self, first_parent: bool) {
+        self.first_parent = first_parent;
+    }
+
+    /// Only hands out the commits the filter matches
+    pub fn filter_commits(&mut self, filter: CommitFilter) {
+        self.filter = filter;
+    }
+
+    /// Only hands out the commits that change something at one of the paths. A merge that
+    /// took one parent's version of the paths is left out along with the history of its
+    /// other parents, as none of that made it into the merge
+    pub fn limit_to_paths(&mut self, paths: Vec<String>) {
+        self.paths = paths;
+    }
+
+    /// Changes the parents of the commits handed out to their nearest ancestors that change
+    /// the paths, so that the history shown stays connected. Parents the filter leaves out
+    /// are dropped. Only done when sorting, as that has walked everything before the first
+    /// commit comes out
+    pub fn rewrite_parents(&mut self, rewrite_parents: bool) {
+        self.rewrite_parents = rewrite_parents;
+    }
+
+    /// Whether the walk hands out the commit once it has been walked
+    pub fn shows(&self, commit: &Commit) -> bool {
+        !self.treesame.contains(commit.sha())
+            && !self.pickaxe_missed.contains(commit.sha())
+            && self.filter.matches(commit)
+    }
+
+    /// Starts the walk from the commit as well
+    pub fn push(&mut self, sha: &Sha) -> Result<(), io::Error> {
+        if !self.seen.insert(sha.clone()) {
+            return Ok(());
+        }
+        let commit = load_commit_from_sha(sha)?;
+        self.queued += 1;
+        self.queue.push(Queued {
+            date: commit.committer().date().timestamp(),
+            order: Reverse(self.queued),
+            commit,
+        });
+        Ok(())
+    }
+
+    /// Leaves the commit and everything reachable from it out of the walk. Hidden commits are
+    /// walked along with the others to pass that on to their parents, and the walk stops once
+    /// only hidden commits are left
+    pub fn hide(&mut self, sha: &Sha) -> Result<(), io::Error> {
+        self.hidden.insert(sha.clone());
+        self.push(sha)
+    }
+
+    fn next_commit(&mut self) -> Result<Option<Commit>, io::Error> {
+        if self.sorting == Sorting::Walk {
+            while let Some(commit) = self.next_walked()? {
+                if self.shows(&commit) {
+                    return Ok(Some(commit));
+                }
+            }
+            return Ok(None);
+        }
+        if self.sorted.is_none() {
+            let mut commits = vec![];
+            while let Some(commit) = self.next_walked()? {
+                commits.push(commit);
+            }
+            // sorting everything walked keeps parents after children through the commits
+            // that are left out
+            let sorted = sort_topologically(commits, self.sorting, |commit| {
+                self.walked_parents(commit).to_vec()
+            });
+            let sorted = match self.rewrite_parents {
+                true => self.with_rewritten_parents(sorted),
+                false => sorted.into_iter().filter(|c| self.shows(c)).collect(),
+            };
+            self.sorted = Some(sorted.into_iter());
+        }
+        Ok(self.sorted.as_mut().and_then(|sorted| sorted.next()))
+    }
+
+    fn next_walked(&mut self) -> Result<Option<Commit>, io::Error> {
+        while !self.only_hidden_left() {
+            let commit = match self.queue.pop() {
+                Some(Queued { commit, .. }) => commit,
+                None => break,
+            };
+            if self.hidden.contains(commit.sha()) {
+                for parent in commit.parent().iter().flatten() {
+                    self.hidden.insert(parent.clone());
+                    self.push(parent)?;
+                }
+                continue;
+            }
+            let parents = followed_parents(&commit, self.first_parent);
+            let parents = match self.paths.is_empty() {
+                true => parents.to_vec(),
+                false => self.simplify(&commit, parents)?,
+            };
+            for parent in parents.iter() {
+                self.push(parent)?;
+            }
+            if !self
+                .filter
+                .matches_changes(&commit, self.first_parent, &self.paths)?
+            {
+                self.pickaxe_missed.insert(commit.sha().clone());
+            }
+            return Ok(Some(commit));
+        }
+        Ok(None)
+    }
+
+    /// Whether nothing that could still be handed out is waiting to be walked
+    fn only_hidden_left(&self) -> bool {
+        self.queue
+            .iter()
+            .all(|queued| self.hidden.contains(queued.commit.sha()))
+    }
+
+    /// The parents the walk went on to from the commit
+    fn walked_parents<'a>(&'a self, commit: &'a Commit) -> &'a [Sha] {
+        match self.simplified.get(commit.sha()) {
+            Some(parents) => parents,
+            None => followed_parents(commit, self.first_parent),
+        }
+    }
+
+    /// Marks the commit as leaving the paths as they were if it does so for any of its
+    /// parents, and gives back the parents to go on to: the first such parent, or all of them
+    fn simplify(&mut self, commit: &Commit, parents: &[Sha]) -> Result<Vec<Sha>, io::Error> {
+        if parents.is_empty() {
+            if !has_any_path(commit.tree_sha(), &self.paths)? {
+                self.treesame.insert(commit.sha().clone());
+            }
+            return Ok(vec![]);
+        }
+        for parent in parents.iter() {
+            let parent_tree = load_commit_from_sha(parent)?.tree_sha().clone();
+            if same_at_paths(&parent_tree, commit.tree_sha(), &self.paths)? {
+                self.treesame.insert(commit.sha().clone());
+                self.simplified
+                    .insert(commit.sha().clone(), vec![parent.clone()]);
+                return Ok(vec![parent.clone()]);
+            }
+        }
+        Ok(parents.to_vec())
+    }
+
+    /// The commits that are shown, each with its parents swapped for the nearest commits it
+    /// descends from that change the paths. Those the filter leaves out are dropped rather
+    /// than looked through, as they are still part of the history shown
+    fn with_rewritten_parents(&self, commits: Vec<Commit>) -> Vec<Commit> {
+        let walked: HashMap<Sha, Vec<Sha>> = commits
+            .iter()
+            .map(|commit| (commit.sha().clone(), self.walked_parents(commit).to_vec()))
+            .collect();
+        let shown: HashSet<Sha> = commits
+            .iter()
+            .filter(|commit| self.shows(commit))
+            .map(|commit| commit.sha().clone())
+            .collect();
+        commits
+            .into_iter()
+            .filter(|commit| shown.contains(commit.sha()))
+            .map(|mut commit| {
+                let mut rewritten = vec![];
+                let mut seen = HashSet::new();
+                let mut to_visit: Vec<Sha> = walked[commit.sha()].iter().rev().cloned().collect();
+                while let Some(sha) = to_visit.pop() {
+                    if !seen.insert(sha.clone()) {
+                        continue;
+                    }
+                    match walked.get(&sha) {
+                        Some(parents) if self.treesame.contains(&sha) => {
+                            to_visit.extend(parents.iter().rev().cloned())
+                        }
+                        Some(_) if shown.contains(&sha) => rewritten.push(sha),
+                        _ => (),
+                    }
+                }
+                commit.set_parents(rewritten);
+                commit
+            })
+            .collect()
+    }
+}
+
+impl Default for RevWalk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Iterator for RevWalk {
+    type Item = Result<Commit, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_commit().transpose()
+    }
+}
+
+/// The parents of the commit a walk goes on to
+pub fn followed_parents(commit: &Commit, first_parent: bool) -> &[Sha] {
+    match (commit.parent(), first_parent) {
+        (Some(parents), true) => &parents[..parents.len().min(1)],
+        (Some(parents), false) => parents,
+        (None, _) => &[],
+    }
+}
+
+/// Reorders walked commits so that none comes before any of its children. Commits become
+/// ready once all their children are out; date order takes the newest ready commit, topo
+/// order the one made ready last so that a line of history is finished before the next
+fn sort_topologically(
+    commits: Vec<Commit>,
+    sorting: Sorting,
+    parents_of: impl Fn(&Commit) -> Vec<Sha>,
+) -> Vec<Commit> {
+    let position: HashMap<&Sha, usize> = commits
+        .iter()
+        .enumerate()
+        .map(|(index, commit)| (commit.sha(), index))
+        .collect();
+    let parents: Vec<Vec<usize>> = commits
+        .iter()
+        .map(|commit| {
+            parents_of(commit)
+                .iter()
+                .filter_map(|parent| position.get(parent).copied())
+                .collect()
+        })
+        .collect();
+    let mut children = vec![0; commits.len()];
+    for index in parents.iter().flatten() {
+        children[*index] += 1;
+    }
+
+    let mut ready = BinaryHeap::new();
+    let mut made_ready = 0;
+    let mut make_ready = |index: usize, ready: &mut BinaryHeap<(i64, usize, usize)>| {
+        made_ready += 1;
+        let entry = match sorting {
+            Sorting::Date => {
+                let date = commits[index].committer().date().timestamp();
+                (date, usize::MAX - made_ready, index)
+            }
+            _ => (0, made_ready, index),
+        };
+        ready.push(entry);
+    };
+    let tips: Vec<usize> = (0..commits.len()).filter(|&i| children[i] == 0).collect();
+    // the first tip has to come out first in either order
+    match sorting {
+        Sorting::Date => tips.iter().for_each(|&tip| make_ready(tip, &mut ready)),
+        _ => tips
+            .iter()
+            .rev()
+            .for_each(|&tip| make_ready(tip, &mut ready)),
+    }
+
+    let mut sorted = Vec::with_capacity(commits.len());
+    while let Some((_, _, index)) = ready.pop() {
+        for parent in parents[index].iter() {
+            children[*parent] -= 1;
+            if children[*parent] == 0 {
+                make_ready(*parent, &mut ready);
+            }
+        }
+        sorted.push(index);
+    }
+    let mut commits: Vec<Option<Commit>> = commits.into_iter().map(Some).collect();
+    sorted
+        .into_iter()
+        .filter_map(|index| commits[index].take())
+        .collect()
+}
+
+/// Whether the ancestor can be reached by following parents back from the descendant
+pub fn is_ancestor(ancestor: &Sha, descendant: &Sha) -> Result<bool, io::Error> {
+    let mut seen = HashSet::new();
+    let mut to_visit = vec![descendant.clone()];
+    while let Some(sha) = to_visit.pop() {
+        if &sha == ancestor {
+            return Ok(true);
+        }
+        if !seen.insert(sha.clone()) {
+            continue;
+        }
+        if let Some(parents) = load_commit_from_sha(&sha)?.parent() {
+            to_visit.extend(parents.iter().cloned());
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::bytes::Regex;
+
+    use super::*;
+    use crate::{diff::Pickaxe, test_repo::TestRepo};
+
+    fn subjects(commits: impl Iterator<Item = Result<Commit, io::Error>>) -> Vec<String> {
+        commits.map(|commit| commit.unwrap().subject()).collect()
+    }
+
+    fn walk_from(tip: &Sha, sorting: Sorting, first_parent: bool) -> RevWalk {
+        let mut walk = RevWalk::new();
+        walk.sort(sorting);
+        walk.first_parent(first_parent);
+        walk.push(tip).unwrap();
+        walk
+    }
+
+    /// Two lines of two commits each from a root, merged together
+    fn two_lines(repo: &TestRepo) -> Sha {
+        let a = repo.commit("a", &[], &[]);
+        let b1 = repo.commit("b1", &[&a], &[]);
+        let c1 = repo.commit("c1", &[&a], &[]);
+        let b2 = repo.commit("b2", &[&b1], &[]);
+        let c2 = repo.commit("c2", &[&c1], &[]);
+        repo.commit("m", &[&b2, &c2], &[])
+    }
+
+    #[test]
+    fn each_commit_comes_out_once() {
+        let repo = TestRepo::new("walk_diamond");
+        let a = repo.commit("a", &[], &[]);
+        let b = repo.commit("b", &[&a], &[]);
+        let c = repo.commit("c", &[&a], &[]);
+        let d = repo.commit("d", &[&b, &c], &[]);
+        let mut walk = walk_from(&d, Sorting::Walk, false);
+        walk.push(&b).unwrap();
+        assert_eq!(subjects(walk), ["d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn sorting_keeps_parents_after_children() {
+        let repo = TestRepo::new("walk_sorting");
+        let a = repo.commit_at("a", &[], &[], 1_900_000_100);
+        // dated before its parent, so the walk reaches the root first
+        let p = repo.commit_at("p", &[&a], &[], 1_900_000_000);
+        let q = repo.commit_at("q", &[&a], &[], 1_900_000_300);
+        let m = repo.commit_at("m", &[&p, &q], &[], 1_900_000_400);
+        assert_eq!(
+            subjects(walk_from(&m, Sorting::Walk, false)),
+            ["m", "q", "a", "p"]
+        );
+        assert_eq!(
+            subjects(walk_from(&m, Sorting::Date, false)),
+            ["m", "q", "p", "a"]
+        );
+        assert_eq!(
+            subjects(walk_from(&m, Sorting::Topo, false)),
+            ["m", "q", "p", "a"]
+        );
+
+        let m = two_lines(&repo);
+        let date = ["m", "c2", "b2", "c1", "b1", "a"];
+        assert_eq!(subjects(walk_from(&m, Sorting::Date, false)), date);
+        let topo = ["m", "c2", "c1", "b2", "b1", "a"];
+        assert_eq!(subjects(walk_from(&m, Sorting::Topo, false)), topo);
+    }
+
+    #[test]
+    fn first_parent_skip_count_and_reverse() {
+        let repo = TestRepo::new("walk_limits");
+        let m = two_lines(&repo);
+        let first_parent = ["m", "b2", "b1", "a"];
+        assert_eq!(subjects(walk_from(&m, Sorting::Walk, true)), first_parent);
+        // as log does it, the skip and count pick commits before they are reversed
+        let picked = subjects(walk_from(&m, Sorting::Walk, false).skip(1).take(3));
+        assert_eq!(picked, ["c2", "b2", "c1"]);
+        let mut reversed = picked;
+        reversed.reverse();
+        assert_eq!(reversed, ["c1", "b2", "c2"]);
+    }
+
+    #[test]
+    fn hidden_history_is_not_walked() {
+        let repo = TestRepo::new("walk_hide");
+        let root = repo.commit("root", &[], &[]);
+        let old = repo.commit("old", &[&root], &[]);
+        let base = repo.commit("base", &[&old], &[]);
+        let side = repo.commit("side", &[&old], &[]);
+        let tip = repo.commit("tip", &[&base, &side], &[]);
+        // the walk has to stop at the hidden commits, as what lies behind them is gone
+        std::fs::remove_file(root.to_path()).unwrap();
+        let mut walk = walk_from(&tip, Sorting::Walk, false);
+        walk.hide(&base).unwrap();
+        assert_eq!(subjects(walk), ["tip", "side"]);
+        let mut walk = RevWalk::new();
+        walk.hide(&side).unwrap();
+        walk.push(&tip).unwrap();
+        assert_eq!(subjects(walk), ["tip", "base"]);
+        let mut walk = walk_from(&tip, Sorting::Topo, false);
+        walk.hide(&tip).unwrap();
+        assert!(subjects(walk).is_empty());
+    }
+
+    #[test]
+    fn pickaxe_only_looks_within_the_paths() {
+        let repo = TestRepo::new("pickaxe_paths");
+        let first = repo.commit("first", &[], &[("f", "a\n"), ("other", "x\n")]);
+        let second = repo.commit(
+            "second",
+            &[&first],
+            &[("f", "b\n"), ("other", "x\nneedle\n")],
+        );
+        let third = repo.commit(
+            "third",
+            &[&second],
+            &[("f", "needle\n"), ("other", "x\nneedle\n")],
+        );
+        let walk_with = |paths: Vec<String>| {
+            let mut walk = RevWalk::new();
+            walk.filter_commits(CommitFilter {
+                pickaxe: Some(Pickaxe::Occurrences(Regex::new("needle").unwrap())),
+                ..CommitFilter::default()
+            });
+            walk.limit_to_paths(paths);
+            walk.push(&third).unwrap();
+            walk
+        };
+        assert_eq!(subjects(walk_with(vec![])), ["third", "second"]);
+        assert_eq!(subjects(walk_with(vec![String::from("f")])), ["third"]);
+        assert!(subjects(walk_with(vec![String::from("missing")])).is_empty());
+    }
+}
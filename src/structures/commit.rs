@@ -1,61 +1,206 @@
 use std::{
+    env,
     fs::{create_dir_all, File},
-    io::{self, stdin, BufRead, Seek, Write},
+    io::{self, stdin, BufRead, Read, Seek, Write},
 };
 
-use chrono::{DateTime, Local};
+use chrono::{DateTime, FixedOffset, Local};
 use derive_getters::Getters;
 use log::{debug, info};
 
 use crate::{
-    exit_with_message,
-    structures::{compress, load_commit_from_sha},
-    utils::iterable_to_string_no_truncate,
+    config::get_config_value, exit_with_message, revision::newest_author_matching,
+    structures::compress, utils::parse_identity_date,
 };
 
-use super::commit_printer::Printer;
 use super::{GitObject, Sha};
 
 #[derive(Clone, Debug, Getters)]
 pub struct Author {
     name: String,
     email: String,
-    date: DateTime<Local>,
+    /// Kept in the offset it was recorded with, which is how git shows it
+    date: DateTime<FixedOffset>,
+    /// The identity exactly as it was read, which is what gets written back, so that one
+    /// with odd spacing or a name that isn't UTF-8 keeps the object's sha
+    raw: Option<Vec<u8>>,
 }
 
 impl Author {
-    pub fn new(name: String, email: String, date: DateTime<Local>) -> Self {
-        Self { name, email, date }
+    pub fn new(name: String, email: String, date: DateTime<FixedOffset>) -> Self {
+        Self {
+            name,
+            email,
+            date,
+            raw: None,
+        }
     }
 
     pub fn empty() -> Self {
-        Self {
-            name: String::new(),
-            email: String::new(),
-            date: Local::now(),
+        Self::new(String::new(), String::new(), now())
+    }
+
+    /// Who new commits are written by, from `GIT_AUTHOR_NAME`, `GIT_AUTHOR_EMAIL` and
+    /// `GIT_AUTHOR_DATE`, or else `user.name`, `user.email` and the current time. Exits when
+    /// there is no name or email to use
+    pub fn current_author() -> Self {
+        Self::from_environment("AUTHOR").unwrap_or_else(|| unknown_identity("Author", "AUTHOR"))
+    }
+
+    /// Who new commits and tags are made by, like `current_author` but from the
+    /// `GIT_COMMITTER_*` variables
+    pub fn current_committer() -> Self {
+        Self::from_environment("COMMITTER")
+            .unwrap_or_else(|| unknown_identity("Committer", "COMMITTER"))
+    }
+
+    /// The identity for a role like `AUTHOR` or `COMMITTER`, from its `GIT_<role>_*` variables
+    /// or the user's config. None when there is no name or email
+    pub fn from_environment(role: &str) -> Option<Self> {
+        let setting = |variable: &str, config: &str| {
+            env::var(format!("GIT_{}_{}", role, variable))
+                .ok()
+                .or_else(|| get_config_value(config))
+                .filter(|value| !value.trim().is_empty())
+        };
+        let name = setting("NAME", "user.name")?;
+        let email = setting("EMAIL", "user.email")?;
+        Some(Self::new(
+            name.trim().to_string(),
+            format!("<{}>", email.trim()),
+            date_from_environment(role),
+        ))
+    }
+
+    /// An author given as `Name <email>`, as `commit --author` takes it, dated like
+    /// `current_author`. Anything else is looked for in the authors of existing commits, and
+    /// the newest one it matches is used
+    pub fn from_author_option(value: &str) -> Self {
+        let parsed = value.split_once('<').and_then(|(name, rest)| {
+            let (email, after) = rest.split_once('>')?;
+            match name.trim().is_empty() || !after.trim().is_empty() {
+                true => None,
+                false => Some((name.trim().to_string(), format!("<{}>", email.trim()))),
+            }
+        });
+        let found = match parsed {
+            Some(parsed) => Some(parsed),
+            None => newest_author_matching(value)
+                .unwrap_or_else(|e| exit_with_message(&format!("fatal: {}", e)))
+                .map(|author| (author.name, author.email)),
+        };
+        match found {
+            Some((name, email)) => Self::new(name, email, date_from_environment("AUTHOR")),
+            None => {
+                let msg = format!(
+                    "fatal: --author '{}' is not 'Name <email>' and matches no existing author",
+                    value
+                );
+                exit_with_message(&msg);
+            }
         }
     }
 
+    /// Someone to record when nobody has said who they are, named after the login user
+    pub fn anonymous() -> Self {
+        let user = env::var("USER").unwrap_or_else(|_| String::from("unknown"));
+        let email = format!("<{}@localhost>", user);
+        Self::new(user, email, now())
+    }
+
+    /// Parses an identity line like `Name <email> 1647742703 -0700`. The name may be empty
+    /// or not UTF-8, and the line is kept as it is to be written back
+    pub fn new_from_bytes(data: &[u8]) -> Result<Self, io::Error> {
+        let corrupt = || {
+            let msg = format!("corrupt identity line: {}", String::from_utf8_lossy(data));
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        };
+        let email_end = data.iter().rposition(|&b| b == b'>').ok_or_else(corrupt)?;
+        let email_start = data[..email_end]
+            .iter()
+            .rposition(|&b| b == b'<')
+            .ok_or_else(corrupt)?;
+        let name = String::from_utf8_lossy(&data[..email_start]);
+        let email = String::from_utf8_lossy(&data[email_start..=email_end]);
+        let date_string = String::from_utf8_lossy(&data[email_end + 1..]);
+        let date = DateTime::parse_from_str(date_string.trim(), "%s %z").map_err(|_| corrupt())?;
+        let mut author = Self::new(name.trim().to_string(), email.to_string(), date);
+        author.raw = Some(data.to_vec());
+        Ok(author)
+    }
+
     pub fn to_string_with_date(&self) -> String {
         format!("{} {} {}", self.name, self.email, self.date.format("%s %z"))
     }
 
+    /// The identity as it is stored in an object: as it was read, or else as
+    /// `to_string_with_date` gives it
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match &self.raw {
+            Some(raw) => raw.clone(),
+            None => self.to_string_with_date().into_bytes(),
+        }
+    }
+
     pub fn to_string_without_date(&self) -> String {
         format!("{} {}", self.name, self.email)
     }
 
     pub fn to_string_date(&self) -> String {
-        // Date:   Tue Mar 15 20:13:58 2022 -0700
-        format!("{}", self.date.format("%a %b %d %H:%M:%S %Y %z"))
+        // Date:   Tue Mar 5 20:13:58 2022 -0700
+        format!("{}", self.date.format("%a %b %-d %H:%M:%S %Y %z"))
+    }
+
+    /// The date as used in email headers, like `Tue, 5 Mar 2022 20:13:58 -0700`
+    pub fn to_string_date_rfc2822(&self) -> String {
+        format!("{}", self.date.format("%a, %-d %b %Y %H:%M:%S %z"))
+    }
+
+    /// Just the day, like `2022-03-05`
+    pub fn to_string_date_short(&self) -> String {
+        format!("{}", self.date.format("%Y-%m-%d"))
+    }
+}
+
+/// The current time in the local timezone's offset
+fn now() -> DateTime<FixedOffset> {
+    let now = Local::now();
+    now.with_timezone(now.offset())
+}
+
+/// The date from `GIT_<role>_DATE`, or the current time when it isn't set
+fn date_from_environment(role: &str) -> DateTime<FixedOffset> {
+    let date = match env::var(format!("GIT_{}_DATE", role)) {
+        Ok(date) => date,
+        Err(_) => return now(),
+    };
+    match parse_identity_date(&date) {
+        Some(parsed) => parsed,
+        None => exit_with_message(&format!("fatal: invalid date format: {}", date)),
     }
 }
 
+fn unknown_identity(title: &str, role: &str) -> ! {
+    let msg = format!(
+        "{} identity unknown\n\n*** Please tell me who you are.\n\nRun\n\n  flog config --global user.email \"you@example.com\"\n  flog config --global user.name \"Your Name\"\n\nto set your account's default identity.\nOmit --global to set the identity only in this repository,\nor set GIT_{}_NAME and GIT_{}_EMAIL in the environment.\n\nfatal: unable to work out a name and email to use",
+        title, role, role
+    );
+    exit_with_message(&msg);
+}
+
 #[derive(Clone, Debug, Getters)]
 pub struct Commit {
-    message: Vec<String>,
+    /// The message exactly as stored, which need not be UTF-8 when an `encoding` header says so
+    message: Vec<u8>,
     author: Author,
     committer: Author,
     parent: Option<Vec<Sha>>,
+    /// Headers after the committer, like `encoding`, `mergetag` and `gpgsig`, in the order they
+    /// were read. The lines of a multi-line value are joined with newlines
+    extra_headers: Vec<(Vec<u8>, Vec<u8>)>,
+    /// What follows the last header: a newline and the blank line before the message, unless
+    /// the object was written without them
+    headers_end: Vec<u8>,
     sha: Sha,
     tree_sha: Sha,
 }
@@ -70,16 +215,43 @@ impl Commit {
         // parent 406b00943149ced320d43c489e6bca6ef423f8b4
         // author Mark Chaitin <markchaitin@gmail.com> 1647742703 -0700
         // committer Mark Chaitin <markchaitin@gmail.com> 1647742703 -0700
+        // gpgsig -----BEGIN PGP SIGNATURE-----
+        //  <more lines of the signature, each indented by a space>
+        //
         // added a second file
         info!("Reading commit from disk. Total size: {} bytes", count);
-        let mut total = 0;
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        debug!("total read: {} vs total in file: {}", data.len(), count);
+        let (headers, headers_end, message) = match data.windows(2).position(|pair| pair == b"\n\n")
+        {
+            Some(end) => (&data[..end], &b"\n\n"[..], &data[end + 2..]),
+            None => match data.strip_suffix(b"\n") {
+                Some(headers) => (headers, &b"\n"[..], &[][..]),
+                None => (&data[..], &[][..], &[][..]),
+            },
+        };
         let mut commit = Commit::empty();
-        while let Some((name, data)) = Commit::read_file_line(reader, &mut total) {
-            commit.add_field(name, data);
+        let mut fields: Vec<(Vec<u8>, Vec<u8>)> = vec![];
+        for line in headers.split(|&b| b == b'\n') {
+            match (line.strip_prefix(b" "), fields.last_mut()) {
+                (Some(continued), Some((_, value))) => {
+                    value.push(b'\n');
+                    value.extend_from_slice(continued);
+                }
+                _ => match line.iter().position(|&b| b == b' ') {
+                    Some(space) => {
+                        fields.push((line[..space].to_vec(), line[space + 1..].to_vec()))
+                    }
+                    None => return Err(corrupt_commit(sha)),
+                },
+            }
         }
-        debug!("total read: {} vs total in file: {}", total, count);
-        let message = Commit::read_messages(reader, &mut total)?;
-        commit.message = message;
+        for (name, value) in fields {
+            commit.add_field(name, value, sha)?;
+        }
+        commit.headers_end = headers_end.to_vec();
+        commit.message = message.to_vec();
         commit.sha = sha.clone();
         Ok(commit)
     }
@@ -88,30 +260,40 @@ impl Commit {
         tree_sha: &Sha,
         messages: &Option<Vec<String>>,
         parent: &Option<Vec<Sha>>,
+        author: Option<Author>,
     ) -> Result<Self, io::Error> {
         info!("New commit from tree sha");
-        let messages = match messages {
-            Some(msg) => msg.clone(),
+        // like git commit-tree, each -m is its own paragraph ending in a newline, while a
+        // message from stdin is taken exactly as it is
+        let message = match messages {
+            Some(messages) => messages
+                .iter()
+                .fold(String::new(), |mut message, paragraph| {
+                    if !message.is_empty() {
+                        message.push('\n');
+                    }
+                    message.push_str(paragraph);
+                    if !message.ends_with('\n') {
+                        message.push('\n');
+                    }
+                    message
+                }),
             None => {
                 let mut message = String::new();
-                let stdin = stdin();
-                stdin.read_line(&mut message)?;
-                vec![message]
+                stdin().read_to_string(&mut message)?;
+                message
             }
         };
-        debug!("{:?}", messages);
-        let dt = Local::now();
-        let author = Author::new(
-            String::from("Mark Chaitin"),
-            String::from("<markchaitin@gmail.com>"),
-            dt,
-        );
-        let committer = author.clone();
+        debug!("{:?}", message);
+        let author = author.unwrap_or_else(Author::current_author);
+        let committer = Author::current_committer();
         let mut me = Self {
-            message: messages,
+            message: message.into_bytes(),
             author,
             committer,
             parent: None,
+            extra_headers: vec![],
+            headers_end: b"\n\n".to_vec(),
             sha: Sha::empty(),
             tree_sha: tree_sha.clone(),
         };
@@ -123,26 +305,10 @@ impl Commit {
         Ok(me)
     }
 
+    /// Names the commit by the hash of its contents, as they are written to disk
     pub fn hash(&mut self) -> Result<(), io::Error> {
         info!("Hashing new commit object");
-        let mut bytes = vec![];
-        bytes.write(b"tree ")?;
-        bytes.write(self.tree_sha.buf())?;
-        if let Some(parents) = &self.parent {
-            for parent in parents.iter() {
-                bytes.write(b"\nparent ")?;
-                bytes.write(parent.buf())?;
-            }
-        }
-        let author_string = format!("\nauthor {}", self.author.to_string_with_date());
-        bytes.write(author_string.as_bytes())?;
-        let committer_string = format!("\ncommitter {}", self.committer.to_string_with_date());
-        bytes.write(committer_string.as_bytes())?;
-        let mut heading = Commit::create_heading(bytes.len());
-        let mut all_out = vec![];
-        all_out.write(&mut heading)?;
-        all_out.write(&mut bytes)?;
-        self.sha = Sha::new_hash(all_out);
+        self.sha = Sha::new_hash(self.to_bytes());
         Ok(())
     }
 
@@ -152,23 +318,84 @@ impl Commit {
             author: Author::empty(),
             committer: Author::empty(),
             parent: None,
+            extra_headers: vec![],
+            headers_end: b"\n\n".to_vec(),
             sha: Sha::empty(),
             tree_sha: Sha::empty(),
         }
     }
 
-    pub fn print_recursive(&self, printer: &dyn Printer) {
-        printer.print_commit(self);
-        if let Some(parent_shas) = &self.parent {
-            for sha in parent_shas {
-                let next = load_commit_from_sha(sha).unwrap();
-                next.print_recursive(printer);
+    pub fn get_sha(&self) -> &Sha {
+        &self.sha
+    }
+
+    /// Replaces the parents, as a walk does when it leaves commits out of the history it shows
+    pub fn set_parents(&mut self, parents: Vec<Sha>) {
+        self.parent = match parents.is_empty() {
+            true => None,
+            false => Some(parents),
+        };
+    }
+
+    /// The message as it was written, with anything that isn't UTF-8 replaced
+    pub fn full_message(&self) -> String {
+        String::from_utf8_lossy(&self.message).to_string()
+    }
+
+    /// The first paragraph of the message, as it was written
+    pub fn first_paragraph(&self) -> String {
+        let message = self.full_message();
+        let first = message.split("\n\n").next().unwrap_or_default();
+        first.to_string()
+    }
+
+    /// The first paragraph of the message, on a single line
+    pub fn subject(&self) -> String {
+        let first = self.first_paragraph();
+        let lines: Vec<&str> = first.lines().map(str::trim).collect();
+        lines.join(" ")
+    }
+
+    /// Everything in the message after the subject paragraph
+    pub fn body(&self) -> String {
+        let rest = match self.full_message().split_once("\n\n") {
+            Some((_, rest)) => rest.to_string(),
+            None => String::new(),
+        };
+        rest.trim_start_matches('\n').to_string()
+    }
+
+    /// The commit as git stores it, without the header
+    fn contents(&self) -> Vec<u8> {
+        let mut lines = vec![format!("tree {}", self.tree_sha).into_bytes()];
+        for parent in self.parent.iter().flatten() {
+            lines.push(format!("parent {}", parent).into_bytes());
+        }
+        lines.push([&b"author "[..], &self.author.to_bytes()].concat());
+        lines.push([&b"committer "[..], &self.committer.to_bytes()].concat());
+        for (name, value) in self.extra_headers.iter() {
+            // the later lines of a multi-line value are indented by a space
+            let mut line = name.clone();
+            line.push(b' ');
+            for &byte in value.iter() {
+                line.push(byte);
+                if byte == b'\n' {
+                    line.push(b' ');
+                }
             }
+            lines.push(line);
         }
+        let mut contents = lines.join(&b'\n');
+        contents.extend_from_slice(&self.headers_end);
+        contents.extend_from_slice(&self.message);
+        contents
     }
 
-    pub fn get_sha(&self) -> &Sha {
-        &self.sha
+    fn to_bytes(&self) -> Vec<u8> {
+        let contents = self.contents();
+        let mut bytes = Commit::create_heading(contents.len());
+        bytes.extend_from_slice(&contents);
+        bytes
     }
 
     fn create_heading(size: usize) -> Vec<u8> {
@@ -176,44 +403,19 @@ impl Commit {
         heading.chars().map(|ch| ch as u8).collect()
     }
 
-    fn read_file_line<R: BufRead>(reader: &mut R, total: &mut usize) -> Option<(String, String)> {
-        let msg = "Database is corrupt";
-        let mut buf = String::new();
-        *total += {
-            let this = reader.read_line(&mut buf);
-            match this {
-                Ok(t) => t,
-                Err(_e) => exit_with_message(msg),
-            }
-        };
-        let split = buf.split_once(" ");
-        if let Some((start, rest)) = split {
-            return Some((start.to_string(), rest.to_string()));
+    fn add_field(&mut self, field_name: Vec<u8>, value: Vec<u8>, sha: &Sha) -> io::Result<()> {
+        let parse_sha = |value: &[u8]| -> io::Result<Sha> {
+            let text = String::from_utf8_lossy(value);
+            text.trim().parse().map_err(|_| corrupt_commit(sha))
         };
-        None
-    }
-
-    fn read_messages<R: BufRead>(
-        reader: &mut R,
-        total: &mut usize,
-    ) -> Result<Vec<String>, io::Error> {
-        let mut buf = vec![];
-        *total += reader.read_to_end(&mut buf)?;
-        let full_string = iterable_to_string_no_truncate(&mut buf.iter());
-        let v = full_string.split("\n\n").map(|s| s.to_string()).collect();
-        Ok(v)
-    }
-
-    fn add_field(&mut self, field_name: String, data: String) {
-        let data = data.trim().to_string();
-        match field_name.as_str() {
-            "tree" => {
-                let sha = data.parse().unwrap();
+        match field_name.as_slice() {
+            b"tree" => {
+                let sha = parse_sha(&value)?;
                 debug!("Setting tree_sha to {}", sha);
                 self.tree_sha = sha;
             }
-            "parent" => {
-                let sha = data.parse().unwrap();
+            b"parent" => {
+                let sha = parse_sha(&value)?;
                 debug!("Setting parent to {}", sha);
                 if self.parent.is_some() {
                     let opt = self.parent.clone();
@@ -224,16 +426,9 @@ impl Commit {
                     self.parent = Some(vec![sha]);
                 }
             }
-            "author" | "committer" => {
-                let start_email_index = data.find('<').unwrap();
-                let end_email_index = data.find('>').unwrap();
-                let name = &data[0..start_email_index - 1];
-                let email = &data[start_email_index..=end_email_index];
-                let date_string = &data[(end_email_index + 1)..];
-                let date = DateTime::parse_from_str(date_string, "%s %z").unwrap();
-                let date: DateTime<Local> = DateTime::from(date);
-                let val = Author::new(name.to_string(), email.to_string(), date);
-                if field_name.as_str() == "author" {
+            b"author" | b"committer" => {
+                let val = Author::new_from_bytes(&value).map_err(|_| corrupt_commit(sha))?;
+                if field_name == b"author" {
                     debug!("Setting author to: {:?}", val);
                     self.author = val;
                 } else {
@@ -241,35 +436,21 @@ impl Commit {
                     self.committer = val;
                 }
             }
-            _ => panic!("Unknown field"),
+            // anything else is kept untouched so the commit is written back the same
+            _ => self.extra_headers.push((field_name, value)),
         }
+        Ok(())
     }
 }
 
+fn corrupt_commit(sha: &Sha) -> io::Error {
+    let msg = format!("corrupt commit object {}", sha);
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
 impl GitObject for Commit {
     fn write_to_disk(&mut self) -> Result<(), std::io::Error> {
-        let mut out = String::new();
-        out.push_str("tree ");
-        out.push_str(&self.tree_sha.to_string());
-        if let Some(parents) = &self.parent {
-            for parent in parents.iter() {
-                out.push_str("\nparent ");
-                out.push_str(&parent.to_string());
-            }
-        }
-        let author_string = format!("\nauthor {}", self.author.to_string_with_date());
-        out.push_str(&author_string);
-        let committer_string = format!("\ncommitter {}", self.committer.to_string_with_date());
-        out.push_str(&committer_string);
-        for str in self.message.iter() {
-            let msg = format!("\n\n{}", str);
-            out.push_str(&msg);
-        }
-        let mut heading = Commit::create_heading(out.len());
-        let mut output = vec![];
-        output.write(&mut heading)?;
-        output.write(&mut out.as_bytes())?;
-        let compressed = compress(&*output);
+        let compressed = compress(&*self.to_bytes());
         let full_path = self.sha.to_path();
         let dir_path = full_path.parent().unwrap();
         debug!("write directory: {:?}", dir_path);
@@ -285,17 +466,8 @@ impl GitObject for Commit {
     }
 
     fn pretty_print(&self) {
-        println!("tree {}", self.tree_sha);
-        if let Some(parents) = &self.parent {
-            for parent in parents.iter() {
-                println!("parent {}", parent);
-            }
-        }
-        println!("author {}", self.author.to_string_with_date());
-        println!("committer {}", self.committer.to_string_with_date());
-        for msg in self.message.iter() {
-            println!("{}", msg);
-        }
+        // the message may not be UTF-8, so it is written out as it is
+        let _ = io::stdout().write_all(&self.contents());
     }
 
     fn print_type(&self) {
@@ -309,12 +481,230 @@ impl GitObject for Commit {
 
 #[cfg(test)]
 mod tests {
-    use std::fs::File;
+    use std::{fs::File, io::Cursor};
+
+    use crate::{
+        config::Config,
+        structures::{decompress, Sha},
+        test_repo::{lock_cwd, TestRepo},
+    };
+
+    use super::{date_from_environment, Author, Commit};
+
+    #[test]
+    fn subject_and_body() {
+        let mut commit = Commit::empty();
+        let message = "first line\nsecond line\n\nbody one\nbody two\n\n\nlast\n";
+        commit.message = message.as_bytes().to_vec();
+        assert_eq!(commit.full_message(), message);
+        assert_eq!(commit.subject(), "first line second line");
+        assert_eq!(commit.body(), "body one\nbody two\n\n\nlast\n");
+    }
+
+    #[test]
+    fn hash_covers_what_is_written() {
+        let _repo = identity_repo("commit_hash", &FIXED_IDENTITY, "");
+        let empty_tree = "4b825dc642cb6eb9a060e54bf8d69288fbee4904".parse().unwrap();
+        let parent: Sha = "ec13e53e4f9d4661ef5a45ed980a029d3d1e89ab".parse().unwrap();
+        let messages = Some(vec![String::from("a"), String::from("b")]);
+        let mut commit =
+            Commit::new_from_tree_sha(&empty_tree, &messages, &Some(vec![parent]), None).unwrap();
+        // what `git commit-tree -p ec13e53 -m a -m b` gives with the same environment
+        assert_eq!(
+            commit.sha().to_string(),
+            "08e155bbd93d486b34a071fd0513c75c78da7711"
+        );
+        // and with the message from stdin, which keeps its lack of a last newline
+        commit.message = b"no newline".to_vec();
+        commit.hash().unwrap();
+        assert_eq!(
+            commit.sha().to_string(),
+            "bd5555477d652e403e4e0059f84f95691e9901d3"
+        );
+    }
+
+    /// Reads the commit and checks it is written back byte for byte, under the same sha
+    fn round_trip(data: &[u8]) -> Commit {
+        let sha = Sha::new_hash([format!("commit {}\0", data.len()).as_bytes(), data].concat());
+        let commit = Commit::new_from_disk(&mut Cursor::new(data), data.len(), &sha).unwrap();
+        assert_eq!(commit.contents(), data);
+        let mut rehashed = commit.clone();
+        rehashed.hash().unwrap();
+        assert_eq!(rehashed.sha, sha);
+        commit
+    }
+
+    #[test]
+    fn reading_and_writing_round_trips() {
+        let mut data = b"tree c49897f29f9819a0ab6850d7e22443508a1a29d5\n\
+parent a18338a62f884d090d1795e988927d4dadef50ee\n\
+author A U Thor <a@example.com> 1792217367 +0530\n\
+committer C O Mitter <c@example.com> 1792217400 -0700\n\
+encoding ISO-8859-1\n\
+gpgsig -----BEGIN PGP SIGNATURE-----\n \n iQEz\n -----END PGP SIGNATURE-----\n\n\
+caf"
+        .to_vec();
+        data.extend_from_slice(b"\xe9 au lait\n\n");
+        let commit = round_trip(&data);
+        assert_eq!(
+            commit.author().to_string_with_date(),
+            "A U Thor <a@example.com> 1792217367 +0530"
+        );
+        assert_eq!(
+            commit.extra_headers()[1].1,
+            b"-----BEGIN PGP SIGNATURE-----\n\niQEz\n-----END PGP SIGNATURE-----"
+        );
+    }
+
+    #[test]
+    fn odd_headers_round_trip() {
+        // a name that isn't UTF-8, in the encoding the commit says it uses
+        let latin1 = b"tree c49897f29f9819a0ab6850d7e22443508a1a29d5\n\
+author \xe9t\xe9 <e@example.com> 1700000000 +0000\n\
+committer \xe9t\xe9 <e@example.com> 1700000000 +0000\n\
+encoding ISO-8859-1\n\
+x-note \xe9\n\nmsg\n";
+        let commit = round_trip(latin1);
+        assert_eq!(commit.author().name(), "\u{fffd}t\u{fffd}");
+        assert_eq!(commit.extra_headers()[1].1, b"\xe9");
+
+        // an empty name, which fsck allows
+        let nameless = b"tree c49897f29f9819a0ab6850d7e22443508a1a29d5\n\
+author  <a@x> 1700000000 +0000\n\
+committer  <a@x> 1700000000 +0000\n\nmsg\n";
+        let commit = round_trip(nameless);
+        assert_eq!(commit.author().name(), "");
+        assert_eq!(commit.author().email(), "<a@x>");
+
+        // no blank line or message after the headers, with and without the last newline
+        let bare = b"tree c49897f29f9819a0ab6850d7e22443508a1a29d5\n\
+author A <a@x> 1700000000 +0000\n\
+committer A <a@x> 1700000000 +0000\n";
+        round_trip(bare);
+        round_trip(&bare[..bare.len() - 1]);
+    }
 
-    use crate::structures::decompress;
+    #[test]
+    fn corrupt_identities_are_errors() {
+        assert!(Author::new_from_bytes(b"A <a@x>").is_err());
+        assert!(Author::new_from_bytes(b"A a@x 1700000000 +0000").is_err());
+        let data = b"tree c49897f29f9819a0ab6850d7e22443508a1a29d5\nauthor A <a@x> soon\n\nmsg\n";
+        let sha = Sha::new_hash(data);
+        assert!(Commit::new_from_disk(&mut Cursor::new(&data[..]), data.len(), &sha).is_err());
+    }
+
+    /// The identity the shas in these tests were made with by git
+    const FIXED_IDENTITY: [(&str, &str); 6] = [
+        ("GIT_AUTHOR_NAME", "A U Thor"),
+        ("GIT_AUTHOR_EMAIL", "author@example.com"),
+        ("GIT_AUTHOR_DATE", "1112911993 -0700"),
+        ("GIT_COMMITTER_NAME", "C O Mitter"),
+        ("GIT_COMMITTER_EMAIL", "committer@example.com"),
+        ("GIT_COMMITTER_DATE", "1112912053 -0700"),
+    ];
+
+    /// A repository where only the given identity variables and config are set
+    fn identity_repo(name: &str, vars: &[(&str, &str)], config: &str) -> TestRepo {
+        let repo = TestRepo::new(name);
+        for role in ["AUTHOR", "COMMITTER"] {
+            for part in ["NAME", "EMAIL", "DATE"] {
+                repo.set_env(&format!("GIT_{}_{}", role, part), None);
+            }
+        }
+        for (name, value) in vars {
+            repo.set_env(name, Some(value));
+        }
+        std::fs::write(".re_flogged/config", config).unwrap();
+        Config::reload();
+        repo
+    }
+
+    #[test]
+    fn environment_overrides_config() {
+        let config = "[user]\n\tname = Con Fig\n\temail = config@example.com\n";
+        let _repo = identity_repo("identity_env", &[("GIT_AUTHOR_NAME", "En Viron")], config);
+        let author = Author::from_environment("AUTHOR").unwrap();
+        assert_eq!(
+            author.to_string_without_date(),
+            "En Viron <config@example.com>"
+        );
+        let committer = Author::from_environment("COMMITTER").unwrap();
+        assert_eq!(
+            committer.to_string_without_date(),
+            "Con Fig <config@example.com>"
+        );
+    }
+
+    #[test]
+    fn no_identity_without_name_and_email() {
+        {
+            let _repo = identity_repo("identity_none", &[("GIT_AUTHOR_NAME", "Only Name")], "");
+            assert!(Author::from_environment("AUTHOR").is_none());
+            assert!(Author::from_environment("COMMITTER").is_none());
+        }
+        // an empty setting counts as unset
+        let vars = [
+            ("GIT_AUTHOR_NAME", " "),
+            ("GIT_AUTHOR_EMAIL", "a@example.com"),
+        ];
+        let _repo = identity_repo("identity_blank", &vars, "");
+        assert!(Author::from_environment("AUTHOR").is_none());
+    }
+
+    #[test]
+    fn dates_come_from_the_environment() {
+        let vars = [
+            ("GIT_AUTHOR_DATE", "1112911993 -0700"),
+            ("GIT_COMMITTER_DATE", "@1112912053 +0200"),
+        ];
+        let _repo = identity_repo("identity_dates", &vars, "");
+        assert_eq!(
+            date_from_environment("AUTHOR").to_rfc3339(),
+            "2005-04-07T15:13:13-07:00"
+        );
+        assert_eq!(
+            date_from_environment("COMMITTER").to_rfc3339(),
+            "2005-04-08T00:14:13+02:00"
+        );
+    }
+
+    #[test]
+    fn author_option_is_parsed_or_looked_up() {
+        let vars = [("GIT_AUTHOR_DATE", "1112911993 -0700")];
+        let repo = identity_repo("identity_option", &vars, "");
+        let author = Author::from_author_option("  Jane Doe <  jane@example.com > ");
+        assert_eq!(
+            author.to_string_without_date(),
+            "Jane Doe <jane@example.com>"
+        );
+        assert_eq!(author.date().to_rfc3339(), "2005-04-07T15:13:13-07:00");
+
+        let root = repo.commit("root", &[], &[]);
+        repo.set_head(&root);
+        let found = Author::from_author_option("THOR");
+        assert_eq!(
+            found.to_string_without_date(),
+            "A U Thor <author@example.com>"
+        );
+        assert_eq!(found.date().to_rfc3339(), "2005-04-07T15:13:13-07:00");
+    }
+
+    #[test]
+    fn fixed_identities_give_the_sha_git_does() {
+        let _repo = identity_repo("identity_sha", &FIXED_IDENTITY, "");
+        let empty_tree = "4b825dc642cb6eb9a060e54bf8d69288fbee4904".parse().unwrap();
+        let messages = Some(vec![String::from("initial")]);
+        let commit = Commit::new_from_tree_sha(&empty_tree, &messages, &None, None).unwrap();
+        // what `git commit-tree -m initial` gives with the same environment
+        assert_eq!(
+            commit.sha().to_string(),
+            "ec13e53e4f9d4661ef5a45ed980a029d3d1e89ab"
+        );
+    }
 
     #[test]
     fn strap() {
+        let _cwd = lock_cwd();
         let f =
             File::open(".re_flogged/objects/01/095adb293f7ba296426fb5e5ddab5e65ec13c4").unwrap();
         let decomp = decompress(f);
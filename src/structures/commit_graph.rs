@@ -0,0 +1,613 @@
+use crate::revision::followed_parents;
+
+use super::{Commit, Sha};
+
+/// Which kind of line the graph draws next for the current commit
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    /// Everything for the commit is drawn, so only lines carrying the branches on
+    Padding,
+    /// Lines for the last commit were never drawn, so they are marked with `...`
+    Skip,
+    /// Spreads the branches out to make room for an octopus merge
+    PreCommit,
+    Commit,
+    /// Splits the lines off to the parents of a merge
+    PostMerge,
+    /// Moves branch lines left until each is in its own column
+    Collapsing,
+}
+
+/// Draws the branch and merge lines of `log --graph` in the left margin, one line at a time.
+/// Each branch line being drawn is a column holding the commit it leads down to, and the
+/// mapping says which column each screen position is heading for
+pub struct CommitGraph {
+    first_parent: bool,
+    commit: Option<Sha>,
+    parents: Vec<Sha>,
+    /// How wide the graph is for the current commit, so all its lines can be padded to it
+    width: usize,
+    expansion_row: usize,
+    state: State,
+    prev_state: State,
+    commit_index: usize,
+    prev_commit_index: usize,
+    /// Whether a merge's first parent is to the left of it (0), below it (1), or unknown (-1)
+    merge_layout: isize,
+    edges_added: isize,
+    prev_edges_added: isize,
+    columns: Vec<Sha>,
+    new_columns: Vec<Sha>,
+    mapping: Vec<isize>,
+    old_mapping: Vec<isize>,
+    mapping_size: usize,
+}
+
+impl CommitGraph {
+    pub fn new(first_parent: bool) -> Self {
+        Self {
+            first_parent,
+            commit: None,
+            parents: vec![],
+            width: 0,
+            expansion_row: 0,
+            state: State::Padding,
+            prev_state: State::Padding,
+            commit_index: 0,
+            prev_commit_index: 0,
+            merge_layout: -1,
+            edges_added: 0,
+            prev_edges_added: 0,
+            columns: vec![],
+            new_columns: vec![],
+            mapping: vec![],
+            old_mapping: vec![],
+            mapping_size: 0,
+        }
+    }
+
+    /// Moves on to the next commit to be shown
+    pub fn update(&mut self, commit: &Commit) {
+        self.commit = Some(commit.sha().clone());
+        self.parents = followed_parents(commit, self.first_parent).to_vec();
+        self.prev_commit_index = self.commit_index;
+        self.update_columns();
+        self.expansion_row = 0;
+        self.state = if self.state != State::Padding {
+            State::Skip
+        } else if self.needs_pre_commit_line() {
+            State::PreCommit
+        } else {
+            State::Commit
+        };
+    }
+
+    /// The graph lines leading up to and including the commit's own line, without a newline
+    /// after it so the commit's text can follow on the same line
+    pub fn show_commit(&mut self) -> String {
+        let mut out = String::new();
+        if self.is_commit_finished() {
+            out.push_str(&self.padding_line());
+            return out;
+        }
+        let mut shown = false;
+        while !shown && !self.is_commit_finished() {
+            let (line, commit_line) = self.next_line();
+            out.push_str(&line);
+            shown = commit_line;
+            if !shown {
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// The commit's text with the graph drawn in front of every line after the first, and
+    /// whatever is left of the graph for the commit after it
+    pub fn show_commit_text(&mut self, text: &str) -> String {
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(end) = rest.find('\n') {
+            out.push_str(&rest[..=end]);
+            rest = &rest[end + 1..];
+            if !rest.is_empty() {
+                out.push_str(&self.next_line().0);
+            }
+        }
+        out.push_str(rest);
+        if !self.is_commit_finished() {
+            let newline_terminated = text.ends_with('\n');
+            if !newline_terminated {
+                out.push('\n');
+            }
+            out.push_str(&self.remainder());
+            if newline_terminated {
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// A line that carries the branches on without changing them, for lines between commits
+    pub fn padding_line(&mut self) -> String {
+        if self.state != State::Commit {
+            return self.next_line().0;
+        }
+        let mut line = String::new();
+        for column in self.columns.iter() {
+            line.push('|');
+            match Some(column) == self.commit.as_ref() && self.parents.len() > 2 {
+                true => line.push_str(&" ".repeat((self.parents.len() - 2) * 2)),
+                false => line.push(' '),
+            }
+        }
+        self.pad_horizontally(&mut line);
+        self.prev_state = State::Padding;
+        line
+    }
+
+    fn remainder(&mut self) -> String {
+        let mut out = String::new();
+        while !self.is_commit_finished() {
+            out.push_str(&self.next_line().0);
+            if !self.is_commit_finished() {
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    fn is_commit_finished(&self) -> bool {
+        self.state == State::Padding
+    }
+
+    /// The next line of the graph, and whether it is the one with the commit on
+    fn next_line(&mut self) -> (String, bool) {
+        let mut line = String::new();
+        let mut commit_line = false;
+        match self.state {
+            State::Padding => self.output_padding_line(&mut line),
+            State::Skip => self.output_skip_line(&mut line),
+            State::PreCommit => self.output_pre_commit_line(&mut line),
+            State::Commit => {
+                self.output_commit_line(&mut line);
+                commit_line = true;
+            }
+            State::PostMerge => self.output_post_merge_line(&mut line),
+            State::Collapsing => self.output_collapsing_line(&mut line),
+        }
+        self.pad_horizontally(&mut line);
+        (line, commit_line)
+    }
+
+    fn update_state(&mut self, state: State) {
+        self.prev_state = self.state;
+        self.state = state;
+    }
+
+    /// Two rows to route around every dashed line of an octopus merge. A merge skewed to the
+    /// left has one parent fewer to the right of it, so it needs fewer
+    fn num_expansion_rows(&self) -> usize {
+        self.num_dashed_parents().max(0) as usize * 2
+    }
+
+    fn num_dashed_parents(&self) -> isize {
+        self.parents.len() as isize + self.merge_layout - 3
+    }
+
+    fn needs_pre_commit_line(&self) -> bool {
+        self.parents.len() >= 3
+            && self.commit_index + 1 < self.columns.len()
+            && self.expansion_row < self.num_expansion_rows()
+    }
+
+    /// Whether every branch line is already in the column it is heading for
+    fn is_mapping_correct(&self) -> bool {
+        self.mapping[..self.mapping_size]
+            .iter()
+            .enumerate()
+            .all(|(i, &target)| target < 0 || target == (i / 2) as isize)
+    }
+
+    /// Works out the columns below the commit: its parents take its place, and every other
+    /// branch carries on
+    fn update_columns(&mut self) {
+        std::mem::swap(&mut self.columns, &mut self.new_columns);
+        self.new_columns.clear();
+        let max_new_columns = self.columns.len() + self.parents.len();
+        self.mapping_size = 2 * max_new_columns;
+        if self.mapping.len() < self.mapping_size {
+            self.mapping.resize(self.mapping_size, -1);
+            self.old_mapping.resize(self.mapping_size, -1);
+        }
+        self.mapping[..self.mapping_size]
+            .iter_mut()
+            .for_each(|target| *target = -1);
+        self.width = 0;
+        self.prev_edges_added = self.edges_added;
+        self.edges_added = 0;
+
+        let commit = self.commit.clone().unwrap_or_else(Sha::empty);
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column = match self.columns.get(i) {
+                Some(column) => column.clone(),
+                None if seen_this => break,
+                // a commit no branch line leads to yet starts a new one on the right
+                None => commit.clone(),
+            };
+            if column == commit {
+                seen_this = true;
+                self.commit_index = i;
+                self.merge_layout = -1;
+                for parent in self.parents.clone().iter() {
+                    self.insert_into_new_columns(parent, Some(i));
+                }
+                // the commit takes up space even when it has no parents
+                if self.parents.is_empty() {
+                    self.width += 2;
+                }
+            } else {
+                self.insert_into_new_columns(&column, None);
+            }
+        }
+        while self.mapping_size > 1 && self.mapping[self.mapping_size - 1] < 0 {
+            self.mapping_size -= 1;
+        }
+    }
+
+    fn insert_into_new_columns(&mut self, sha: &Sha, commit_index: Option<usize>) {
+        let i = match self.new_columns.iter().position(|column| column == sha) {
+            Some(i) => i,
+            None => {
+                self.new_columns.push(sha.clone());
+                self.new_columns.len() - 1
+            }
+        } as isize;
+        let mapping_index;
+        match commit_index {
+            Some(index) if self.parents.len() > 1 && self.merge_layout == -1 => {
+                // the first parent of a merge picks how the merge lines are laid out, by
+                // whether it is in a column to the left of the merge
+                let dist = index as isize - i;
+                let shift = if dist > 1 { 2 * dist - 3 } else { 1 };
+                self.merge_layout = if dist > 0 { 0 } else { 1 };
+                self.edges_added = self.parents.len() as isize + self.merge_layout - 2;
+                mapping_index = self.width as isize + (self.merge_layout - 1) * shift;
+                self.width += 2 * self.merge_layout as usize;
+            }
+            // a column a merge added that is found in the last existing one joins it straight
+            // away
+            _ if self.edges_added > 0 && self.width >= 2 && i == self.mapping[self.width - 2] => {
+                mapping_index = self.width as isize - 2;
+                self.edges_added = -1;
+            }
+            _ => {
+                mapping_index = self.width as isize;
+                self.width += 2;
+            }
+        }
+        self.mapping[mapping_index as usize] = i;
+    }
+
+    fn pad_horizontally(&self, line: &mut String) {
+        if line.len() < self.width {
+            line.push_str(&" ".repeat(self.width - line.len()));
+        }
+    }
+
+    fn output_padding_line(&self, line: &mut String) {
+        // nothing is drawn before the first commit
+        if self.commit.is_none() {
+            return;
+        }
+        for _ in self.new_columns.iter() {
+            line.push_str("| ");
+        }
+    }
+
+    fn output_skip_line(&mut self, line: &mut String) {
+        line.push_str("...");
+        match self.needs_pre_commit_line() {
+            true => self.update_state(State::PreCommit),
+            false => self.update_state(State::Commit),
+        }
+    }
+
+    /// Spreads the branch lines to the right of an octopus merge out, two columns for every
+    /// parent past the second
+    fn output_pre_commit_line(&mut self, line: &mut String) {
+        let mut seen_this = false;
+        for (i, column) in self.columns.iter().enumerate() {
+            if Some(column) == self.commit.as_ref() {
+                seen_this = true;
+                line.push('|');
+                line.push_str(&" ".repeat(self.expansion_row));
+            } else if seen_this && self.expansion_row == 0 {
+                // lines a merge just above drew as `\` carry on that way
+                match self.prev_state == State::PostMerge && self.prev_commit_index < i {
+                    true => line.push('\\'),
+                    false => line.push('|'),
+                }
+            } else if seen_this {
+                line.push('\\');
+            } else {
+                line.push('|');
+            }
+            line.push(' ');
+        }
+        self.expansion_row += 1;
+        if !self.needs_pre_commit_line() {
+            self.update_state(State::Commit);
+        }
+    }
+
+    fn output_commit_line(&mut self, line: &mut String) {
+        let commit = self.commit.clone().unwrap_or_else(Sha::empty);
+        let mut seen_this = false;
+        for i in 0..=self.columns.len() {
+            let column = match self.columns.get(i) {
+                Some(column) => column,
+                None if seen_this => break,
+                None => &commit,
+            };
+            if column == &commit {
+                seen_this = true;
+                line.push('*');
+                if self.parents.len() > 2 {
+                    self.draw_octopus_merge(line);
+                }
+            } else if seen_this && self.edges_added > 1 {
+                line.push('\\');
+            } else if seen_this && self.edges_added == 1 {
+                // a merge just above may have left this line as `\`
+                match self.prev_state == State::PostMerge
+                    && self.prev_edges_added > 0
+                    && self.prev_commit_index < i
+                {
+                    true => line.push('\\'),
+                    false => line.push('|'),
+                }
+            } else if self.prev_state == State::Collapsing
+                && self.old_mapping.get(2 * i + 1) == Some(&(i as isize))
+                && self.mapping.get(2 * i).copied().unwrap_or(-1) < i as isize
+            {
+                line.push('/');
+            } else {
+                line.push('|');
+            }
+            line.push(' ');
+        }
+        if self.parents.len() > 1 {
+            self.update_state(State::PostMerge);
+        } else if self.is_mapping_correct() {
+            self.update_state(State::Padding);
+        } else {
+            self.update_state(State::Collapsing);
+        }
+    }
+
+    /// The dashes leading from an octopus merge to its parents past the first two
+    fn draw_octopus_merge(&self, line: &mut String) {
+        let dashed_parents = self.num_dashed_parents();
+        for i in 0..dashed_parents {
+            line.push('-');
+            line.push(if i == dashed_parents - 1 { '.' } else { '-' });
+        }
+    }
+
+    fn output_post_merge_line(&mut self, line: &mut String) {
+        let merge_chars = ['/', '|', '\\'];
+        let commit = self.commit.clone().unwrap_or_else(Sha::empty);
+        let mut seen_this = false;
+        let mut seen_parent = false;
+        for i in 0..=self.columns.len() {
+            let column = match self.columns.get(i) {
+                Some(column) => column,
+                None if seen_this => break,
+                None => &commit,
+            };
+            if column == &commit {
+                // each parent's line heads off towards the column it is in below the merge
+                seen_this = true;
+                let mut index = self.merge_layout as usize;
+                for j in 0..self.parents.len() {
+                    line.push(merge_chars[index]);
+                    if index == 2 {
+                        if self.edges_added > 0 || j < self.parents.len() - 1 {
+                            line.push(' ');
+                        }
+                    } else {
+                        index += 1;
+                    }
+                }
+                if self.edges_added == 0 {
+                    line.push(' ');
+                }
+            } else if seen_this {
+                match self.edges_added > 0 {
+                    true => line.push_str("\\ "),
+                    false => line.push_str("| "),
+                }
+            } else {
+                line.push('|');
+                // a first parent further left is joined up to with a horizontal line
+                if self.merge_layout != 0 || i + 1 != self.commit_index {
+                    line.push(if seen_parent { '_' } else { ' ' });
+                }
+            }
+            if self.parents.first() == Some(column) {
+                seen_parent = true;
+            }
+        }
+        match self.is_mapping_correct() {
+            true => self.update_state(State::Padding),
+            false => self.update_state(State::Collapsing),
+        }
+    }
+
+    /// Moves every branch line not yet in its column one step left. Only one line may cross
+    /// others horizontally at a time, drawn with underscores
+    fn output_collapsing_line(&mut self, line: &mut String) {
+        let size = self.mapping_size;
+        std::mem::swap(&mut self.mapping, &mut self.old_mapping);
+        self.mapping[..size]
+            .iter_mut()
+            .for_each(|target| *target = -1);
+
+        let mut horizontal_edge = -1;
+        let mut horizontal_edge_target = -1;
+        for i in 0..size {
+            let target = self.old_mapping[i];
+            if target < 0 {
+                continue;
+            }
+            // branches only ever move left, so that whenever two cross only one is moving
+            if (target * 2) as usize == i {
+                self.mapping[i] = target;
+            } else if self.mapping[i - 1] < 0 {
+                self.mapping[i - 1] = target;
+                if horizontal_edge == -1 {
+                    horizontal_edge = i as isize;
+                    horizontal_edge_target = target;
+                    let mut j = (target * 2 + 3) as usize;
+                    while j + 2 < i {
+                        self.mapping[j] = target;
+                        j += 2;
+                    }
+                }
+            } else if self.mapping[i - 1] == target {
+                // the line to the left goes to the same parent, so this one merges into it
+            } else {
+                // cross over the line to the left, which isn't going the same way
+                self.mapping[i - 2] = target;
+                if horizontal_edge == -1 {
+                    horizontal_edge_target = target;
+                    horizontal_edge = i as isize - 1;
+                    let mut j = (target * 2 + 3) as usize;
+                    while j + 2 < i {
+                        self.mapping[j] = target;
+                        j += 2;
+                    }
+                }
+            }
+        }
+        self.old_mapping[..size].copy_from_slice(&self.mapping[..size]);
+        if self.mapping[self.mapping_size - 1] < 0 {
+            self.mapping_size -= 1;
+        }
+
+        let mut used_horizontal = false;
+        for i in 0..self.mapping_size {
+            let target = self.mapping[i];
+            if target < 0 {
+                line.push(' ');
+            } else if (target * 2) as usize == i {
+                line.push('|');
+            } else if target == horizontal_edge_target && i as isize != horizontal_edge - 1 {
+                // only the first segment of the horizontal line carries on to the next line
+                if i as isize != target * 2 + 3 {
+                    self.mapping[i] = -1;
+                }
+                used_horizontal = true;
+                line.push('_');
+            } else {
+                if used_horizontal && (i as isize) < horizontal_edge {
+                    self.mapping[i] = -1;
+                }
+                line.push('/');
+            }
+        }
+        if self.is_mapping_correct() {
+            self.update_state(State::Padding);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// Draws the commits in the order given, each a subject and the subjects of its parents,
+    /// the way `log --graph --format=%s` shows them
+    fn draw(history: &[(&str, &[&str])]) -> String {
+        let sha = |subject: &str| Sha::new_hash(subject);
+        let mut graph = CommitGraph::new(false);
+        let mut out = String::new();
+        for (subject, parents) in history.iter() {
+            let mut data = String::from("tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n");
+            for parent in parents.iter() {
+                data.push_str(&format!("parent {}\n", sha(parent)));
+            }
+            data.push_str("author A <a@x> 1700000000 +0000\n");
+            data.push_str(&format!(
+                "committer A <a@x> 1700000000 +0000\n\n{}\n",
+                subject
+            ));
+            let commit = Commit::new_from_disk(&mut Cursor::new(&data), data.len(), &sha(subject));
+            graph.update(&commit.unwrap());
+            out.push_str(&graph.show_commit());
+            out.push_str(&graph.show_commit_text(subject));
+            out.push('\n');
+        }
+        out
+    }
+
+    #[test]
+    fn linear_history() {
+        let history: [(&str, &[&str]); 3] = [("c", &["b"]), ("b", &["a"]), ("a", &[])];
+        assert_eq!(draw(&history), "* c\n* b\n* a\n");
+    }
+
+    #[test]
+    fn merge_of_two_branches() {
+        let history: [(&str, &[&str]); 4] =
+            [("m", &["b", "c"]), ("c", &["a"]), ("b", &["a"]), ("a", &[])];
+        assert_eq!(draw(&history), "*   m\n|\\  \n| * c\n* | b\n|/  \n* a\n");
+    }
+
+    #[test]
+    fn octopus_merge() {
+        let history: [(&str, &[&str]); 5] = [
+            ("oct", &["o1", "o2", "o3"]),
+            ("o3", &["a"]),
+            ("o2", &["a"]),
+            ("o1", &["a"]),
+            ("a", &[]),
+        ];
+        assert_eq!(
+            draw(&history),
+            "*-.   oct\n|\\ \\  \n| | * o3\n| * | o2\n| |/  \n* / o1\n|/  \n* a\n"
+        );
+    }
+
+    #[test]
+    fn lanes_collapse_as_branches_join() {
+        let history: [(&str, &[&str]); 7] = [
+            ("z", &["x3"]),
+            ("y2", &["y1", "x3"]),
+            ("x3", &["a"]),
+            ("y1", &["x1", "x2"]),
+            ("x2", &["a"]),
+            ("x1", &["a"]),
+            ("a", &[]),
+        ];
+        let expected = "* z\n\
+| *   y2\n\
+| |\\  \n\
+| |/  \n\
+|/|   \n\
+* | x3\n\
+| *   y1\n\
+| |\\  \n\
+| | * x2\n\
+| |/  \n\
+|/|   \n\
+| * x1\n\
+|/  \n\
+* a\n";
+        assert_eq!(draw(&history), expected);
+    }
+}
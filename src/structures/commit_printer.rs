@@ -3,40 +3,185 @@ use log::debug;
 use super::Commit;
 
 pub trait Printer {
-    fn print_commit(&self, commit: &Commit);
+    /// The commit as this layout shows it
+    fn format_commit(&self, commit: &Commit) -> String;
+
+    /// Whether every commit is followed by a newline. Otherwise commits are separated by one,
+    /// which keeps layouts of more than one line apart with an empty line
+    fn terminated(&self) -> bool {
+        false
+    }
+
+    /// Whether an empty line is left between the commit and the changes shown after it
+    fn separates_changes(&self) -> bool {
+        true
+    }
+}
+
+/// The `Merge:` line listing the abbreviated parents of a merge commit
+fn merge_line(commit: &Commit) -> String {
+    match commit.parent() {
+        Some(parents) if parents.len() > 1 => {
+            let short: Vec<String> = parents
+                .iter()
+                .map(|p| p.to_string()[..7].to_string())
+                .collect();
+            format!("Merge: {}\n", short.join(" "))
+        }
+        _ => String::new(),
+    }
+}
+
+/// The message indented by four spaces, without its trailing empty lines
+fn indented(message: &str) -> String {
+    message
+        .trim_end_matches('\n')
+        .lines()
+        .map(|line| format!("    {}\n", line))
+        .collect()
 }
 
-pub struct OneLinePrinter {}
+/// The sha and subject of each commit on a single line, the sha abbreviated if asked
+pub struct OneLinePrinter {
+    pub abbrev: bool,
+}
 
 impl Printer for OneLinePrinter {
-    fn print_commit(&self, commit: &Commit) {
-        let msg = format!("{} {}\n", commit.sha(), commit.message().get(0).unwrap());
-        print!("{}", msg);
+    fn format_commit(&self, commit: &Commit) -> String {
+        let sha = commit.sha().to_string();
+        let sha = match self.abbrev {
+            true => &sha[..7],
+            false => &sha,
+        };
+        format!("{} {}", sha, commit.subject())
+    }
+
+    fn terminated(&self) -> bool {
+        true
+    }
+
+    fn separates_changes(&self) -> bool {
+        false
     }
 }
 
 pub struct MediumPrinter {}
 
 impl Printer for MediumPrinter {
-    fn print_commit(&self, commit: &Commit) {
+    fn format_commit(&self, commit: &Commit) -> String {
         debug!("{:?}", commit);
-        println!("commit {}", commit.sha());
-        println!("Author:\t{}", commit.author().to_string_without_date());
-        println!("Date:\t{}\n", commit.author().to_string_date());
-        for msg in commit.message().iter() {
-            println!("    {}\n", msg);
-        }
+        format!(
+            "commit {}\n{}Author: {}\nDate:   {}\n\n{}",
+            commit.sha(),
+            merge_line(commit),
+            commit.author().to_string_without_date(),
+            commit.author().to_string_date(),
+            indented(&commit.full_message())
+        )
     }
 }
 
 pub struct ShortPrinter {}
 
 impl Printer for ShortPrinter {
-    fn print_commit(&self, commit: &Commit) {
-        println!("commit {}", commit.sha());
-        println!("Author:\t{}\n", commit.author().to_string_without_date());
-        for msg in commit.message().iter() {
-            println!("    {}\n", msg);
-        }
+    fn format_commit(&self, commit: &Commit) -> String {
+        format!(
+            "commit {}\n{}Author: {}\n\n{}",
+            commit.sha(),
+            merge_line(commit),
+            commit.author().to_string_without_date(),
+            indented(&commit.first_paragraph())
+        )
+    }
+}
+
+pub struct FullPrinter {}
+
+impl Printer for FullPrinter {
+    fn format_commit(&self, commit: &Commit) -> String {
+        format!(
+            "commit {}\n{}Author: {}\nCommit: {}\n\n{}",
+            commit.sha(),
+            merge_line(commit),
+            commit.author().to_string_without_date(),
+            commit.committer().to_string_without_date(),
+            indented(&commit.full_message())
+        )
+    }
+}
+
+pub struct FullerPrinter {}
+
+impl Printer for FullerPrinter {
+    fn format_commit(&self, commit: &Commit) -> String {
+        format!(
+            "commit {}\n{}Author:     {}\nAuthorDate: {}\nCommit:     {}\nCommitDate: {}\n\n{}",
+            commit.sha(),
+            merge_line(commit),
+            commit.author().to_string_without_date(),
+            commit.author().to_string_date(),
+            commit.committer().to_string_without_date(),
+            commit.committer().to_string_date(),
+            indented(&commit.full_message())
+        )
+    }
+}
+
+/// The abbreviated sha with the subject and date, as commits are referred to in messages
+pub struct ReferencePrinter {}
+
+impl Printer for ReferencePrinter {
+    fn format_commit(&self, commit: &Commit) -> String {
+        format!(
+            "{} ({}, {})",
+            &commit.sha().to_string()[..7],
+            commit.subject(),
+            commit.author().to_string_date_short()
+        )
+    }
+
+    fn terminated(&self) -> bool {
+        true
+    }
+}
+
+/// Each commit as an mbox message ready to be sent as a patch
+pub struct EmailPrinter {}
+
+impl Printer for EmailPrinter {
+    fn format_commit(&self, commit: &Commit) -> String {
+        // the fixed date marks this as a patch rather than a real mailbox
+        format!(
+            "From {} Mon Sep 17 00:00:00 2001\nFrom: {}\nDate: {}\nSubject: [PATCH] {}\n\n{}",
+            commit.sha(),
+            commit.author().to_string_without_date(),
+            commit.author().to_string_date_rfc2822(),
+            commit.subject(),
+            commit.body()
+        )
+    }
+}
+
+/// The commit object as it is stored, with the message indented
+pub struct RawPrinter {}
+
+impl Printer for RawPrinter {
+    fn format_commit(&self, commit: &Commit) -> String {
+        let parents: String = match commit.parent() {
+            Some(parents) => parents
+                .iter()
+                .map(|parent| format!("parent {}\n", parent))
+                .collect(),
+            None => String::new(),
+        };
+        format!(
+            "commit {}\ntree {}\n{}author {}\ncommitter {}\n\n{}",
+            commit.sha(),
+            commit.tree_sha(),
+            parents,
+            commit.author().to_string_with_date(),
+            commit.committer().to_string_with_date(),
+            indented(&commit.full_message())
+        )
     }
 }
@@ -1,7 +1,4 @@
-use std::{
-    fs::File,
-    io::{self, BufReader, Read},
-};
+use std::io::{self, Read};
 
 use flate2::{
     read::{ZlibDecoder, ZlibEncoder},
@@ -11,26 +8,26 @@ use log::{error, info};
 
 use crate::structures::Sha;
 
-pub fn read_next_u16(reader: &mut BufReader<File>) -> Result<u16, io::Error> {
+pub fn read_next_u16<R: Read>(reader: &mut R) -> Result<u16, io::Error> {
     let mut u16_buf = [0; 2];
     reader.read_exact(&mut u16_buf)?;
     Ok(u16::from_be_bytes(u16_buf))
 }
 
-pub fn read_next_u32(reader: &mut BufReader<File>) -> Result<u32, io::Error> {
+pub fn read_next_u32<R: Read>(reader: &mut R) -> Result<u32, io::Error> {
     let mut u32_buf: [u8; 4] = [0; 4];
     reader.read_exact(&mut u32_buf)?;
     Ok(u32::from_be_bytes(u32_buf))
 }
 
-pub fn read_next_sha(reader: &mut BufReader<File>) -> Result<Sha, io::Error> {
+pub fn read_next_sha<R: Read>(reader: &mut R) -> Result<Sha, io::Error> {
     let mut sha_buf = [0; 20];
     reader.read_exact(&mut sha_buf)?;
     Ok(Sha::new_from_bytes(sha_buf))
 }
 
-pub fn read_next_variable_length(
-    reader: &mut BufReader<File>,
+pub fn read_next_variable_length<R: Read>(
+    reader: &mut R,
     length: usize,
 ) -> Result<Vec<u8>, io::Error> {
     let mut buf = vec![0; length];
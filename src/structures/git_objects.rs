@@ -1,5 +1,6 @@
 use std::{
-    fs::{metadata, File},
+    cell::Cell,
+    fs::{self, metadata, File},
     io::{self, BufRead, BufReader, Cursor},
     os::unix::prelude::MetadataExt,
     path::Path,
@@ -11,12 +12,22 @@ pub use crate::structures::{Blob, Tree};
 
 use crate::{
     exit_with_message,
-    structures::{decompress, Commit},
+    structures::{decompress, is_packed, read_packed_object, Commit, PackIndex, Tag},
     utils::iterable_to_string,
 };
 
 use super::Sha;
 
+thread_local! {
+    static VERIFY_OBJECTS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Makes every object read from here on be rehashed, failing on one whose contents don't
+/// hash to its name
+pub fn verify_objects_on_read(verify: bool) {
+    VERIFY_OBJECTS.with(|flag| flag.set(verify));
+}
+
 pub trait GitObject {
     fn write_to_disk(&mut self) -> Result<(), io::Error>;
     fn pretty_print(&self);
@@ -25,9 +36,8 @@ pub trait GitObject {
 }
 
 pub fn check_file_is_of_kind(sha: &Sha, kind: &str) -> bool {
-    let path = sha.to_path();
     let mut reader = {
-        let this = init_bufreader(&path);
+        let this = init_bufreader(sha);
         match this {
             Ok(t) => t,
             Err(e) => {
@@ -43,10 +53,13 @@ pub fn check_file_is_of_kind(sha: &Sha, kind: &str) -> bool {
         "commit" => vec!['c', 'o', 'm', 'm', 'i', 't'],
         "tree" => vec!['t', 'r', 'e', 'e'],
         "blob" => vec!['b', 'l', 'o', 'b'],
+        "tag" => vec!['t', 'a', 'g'],
         _ => panic!("Invalid object type"),
     };
-    let count = check_header_is_valid(&buf, signature);
-    count > 0
+    // check_header_is_valid aborts on a mismatch, so only compare the type here
+    let expected: Vec<u8> = signature.iter().map(|&c| c as u8).collect();
+    let found = buf.split(|&b| b == b' ').next().unwrap_or_default();
+    found == expected.as_slice()
 }
 
 pub fn read_from_disk(sha: Sha) -> Result<Box<dyn GitObject>, io::Error> {
@@ -55,22 +68,33 @@ pub fn read_from_disk(sha: Sha) -> Result<Box<dyn GitObject>, io::Error> {
     // let path = Path::new("/home/samuwen/Documents/repos/re_flog/.re_flogged/objects/24/e0ed76e64a48945bd93a1d2cf00ba9c6294a8c");
     let path = sha.to_path();
     debug!("Path: {:?}", path);
-    let mut reader = init_bufreader(&path)?;
+    let mut reader = init_bufreader(&sha)?;
     reader.read_until(0, &mut buf)?;
-    let object: Box<dyn GitObject> = match buf[0] as char {
-        'b' => {
-            let mode = metadata(path).unwrap().mode() as u32;
+    // tree and tag share a first letter, so match on the whole type
+    let kind = buf
+        .split(|&b| b == b' ')
+        .next()
+        .unwrap_or_default()
+        .to_vec();
+    let object: Box<dyn GitObject> = match kind.as_slice() {
+        b"blob" => {
+            // packed blobs have no file of their own to take a mode from
+            let mode = metadata(path).map_or(0o100644, |m| m.mode());
             let count = check_header_is_valid(&buf, vec!['b', 'l', 'o', 'b']);
             Box::new(Blob::new_from_disk(&mut reader, count, sha, mode)?)
         }
-        'c' => {
+        b"commit" => {
             let count = check_header_is_valid(&buf, vec!['c', 'o', 'm', 'm', 'i', 't']);
             Box::new(Commit::new_from_disk(&mut reader, count, &sha)?)
         }
-        't' => {
+        b"tree" => {
             let count = check_header_is_valid(&buf, vec!['t', 'r', 'e', 'e']);
             Box::new(Tree::new_from_disk(&mut reader, count, sha)?)
         }
+        b"tag" => {
+            let count = check_header_is_valid(&buf, vec!['t', 'a', 'g']);
+            Box::new(Tag::new_from_disk(&mut reader, count, sha)?)
+        }
         _ => exit_with_message("Fatal: unknown type with this sha"),
     };
     Ok(object)
@@ -78,16 +102,158 @@ pub fn read_from_disk(sha: Sha) -> Result<Box<dyn GitObject>, io::Error> {
 
 pub fn load_commit_from_sha(sha: &Sha) -> Result<Commit, io::Error> {
     let mut buf = vec![];
-    let path = sha.to_path();
-    let mut reader = init_bufreader(&path)?;
+    let mut reader = init_bufreader(sha)?;
     reader.read_until(0, &mut buf)?;
     let count = check_header_is_valid(&buf, vec!['c', 'o', 'm', 'm', 'i', 't']);
     Ok(Commit::new_from_disk(&mut reader, count, sha)?)
 }
 
-fn init_bufreader(path: &Path) -> Result<BufReader<Cursor<Vec<u8>>>, io::Error> {
-    let file = File::open(&path)?;
-    let data = decompress(file);
+pub fn load_tree_from_sha(sha: &Sha) -> Result<Tree, io::Error> {
+    let mut buf = vec![];
+    let mut reader = init_bufreader(sha)?;
+    reader.read_until(0, &mut buf)?;
+    let count = check_header_is_valid(&buf, vec!['t', 'r', 'e', 'e']);
+    Tree::new_from_disk(&mut reader, count, sha.clone())
+}
+
+pub fn load_blob_from_sha(sha: &Sha) -> Result<Blob, io::Error> {
+    let mut buf = vec![];
+    let mut reader = init_bufreader(sha)?;
+    reader.read_until(0, &mut buf)?;
+    let count = check_header_is_valid(&buf, vec!['b', 'l', 'o', 'b']);
+    Blob::new_from_disk(&mut reader, count, sha.clone(), 0o100644)
+}
+
+pub fn load_tag_from_sha(sha: &Sha) -> Result<Tag, io::Error> {
+    let mut buf = vec![];
+    let mut reader = init_bufreader(sha)?;
+    reader.read_until(0, &mut buf)?;
+    let count = check_header_is_valid(&buf, vec!['t', 'a', 'g']);
+    Tag::new_from_disk(&mut reader, count, sha.clone())
+}
+
+/// Follows annotated tags until reaching something that isn't a tag
+pub fn peel_tags(sha: &Sha) -> Result<Sha, io::Error> {
+    let mut sha = sha.clone();
+    while check_file_is_of_kind(&sha, "tag") {
+        sha = load_tag_from_sha(&sha)?.object().clone();
+    }
+    Ok(sha)
+}
+
+/// Resolves a commit-ish to the sha of a commit, following annotated tags if needed
+pub fn peel_to_commit(sha: &Sha) -> Result<Sha, io::Error> {
+    let sha = peel_tags(sha)?;
+    if check_file_is_of_kind(&sha, "commit") {
+        return Ok(sha);
+    }
+    let msg = format!("fatal: not a commit object: {}", sha);
+    exit_with_message(&msg);
+}
+
+/// Resolves a tree-ish to the sha of a tree, following tags and commits to their tree if needed
+pub fn peel_to_tree(sha: &Sha) -> Result<Sha, io::Error> {
+    let sha = peel_tags(sha)?;
+    if check_file_is_of_kind(&sha, "tree") {
+        return Ok(sha);
+    }
+    if check_file_is_of_kind(&sha, "commit") {
+        let commit = load_commit_from_sha(&sha)?;
+        return Ok(commit.tree_sha().clone());
+    }
+    let msg = format!("fatal: not a tree object: {}", sha);
+    exit_with_message(&msg);
+}
+
+/// Whether the object is stored loose or in a pack
+pub fn object_exists(sha: &Sha) -> bool {
+    sha.to_path().is_file() || is_packed(sha)
+}
+
+/// The kind of object stored under the sha, like `commit` or `blob`, if there is one
+pub fn object_kind(sha: &Sha) -> Result<Option<String>, io::Error> {
+    if !object_exists(sha) {
+        return Ok(None);
+    }
+    let data = read_object_data(sha)?;
+    let kind = data.split(|&b| b == b' ').next().unwrap_or_default();
+    Ok(Some(String::from_utf8_lossy(kind).to_string()))
+}
+
+/// Every object, loose or packed, whose sha starts with the hex prefix, in sha order
+pub fn find_objects_with_prefix(prefix: &str) -> Result<Vec<Sha>, io::Error> {
+    let prefix = prefix.to_ascii_lowercase();
+    if prefix.len() < 2 {
+        return Ok(vec![]);
+    }
+    let dir = Path::new(".re_flogged/objects").join(&prefix[..2]);
+    let mut found = vec![];
+    if dir.is_dir() {
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            let is_object = name.len() == 38 && name.chars().all(|ch| ch.is_ascii_hexdigit());
+            if is_object && name.starts_with(&prefix[2..]) {
+                found.push(format!("{}{}", &prefix[..2], name).parse().unwrap());
+            }
+        }
+    }
+    for index in PackIndex::all()?.iter() {
+        found.extend(index.find_prefix(&prefix));
+    }
+    found.sort();
+    found.dedup();
+    Ok(found)
+}
+
+/// The shortest start of the sha, at least `min_len` and never under 4 characters long,
+/// that no other object's sha starts with
+pub fn shortest_unique_prefix(sha: &Sha, min_len: usize) -> Result<String, io::Error> {
+    let hex = sha.to_string();
+    for len in min_len.clamp(4, 40)..40 {
+        if find_objects_with_prefix(&hex[..len])?.len() <= 1 {
+            return Ok(hex[..len].to_string());
+        }
+    }
+    Ok(hex)
+}
+
+/// The full contents of an object, header included, read from its loose file or, when there
+/// isn't one, from whichever pack holds it
+pub fn read_object_data(sha: &Sha) -> Result<Vec<u8>, io::Error> {
+    let path = sha.to_path();
+    let data = match path.is_file() {
+        true => decompress(File::open(&path)?),
+        false => match read_packed_object(sha)? {
+            Some(packed) => {
+                let mut data = format!("{} {}\0", packed.kind, packed.data.len()).into_bytes();
+                data.extend(packed.data);
+                data
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("object {} not found", sha),
+                ))
+            }
+        },
+    };
+    if VERIFY_OBJECTS.with(Cell::get) {
+        let actual = Sha::new_hash(&data);
+        if actual != *sha {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "hash mismatch for object {}, its contents hash to {}",
+                    sha, actual
+                ),
+            ));
+        }
+    }
+    Ok(data)
+}
+
+fn init_bufreader(sha: &Sha) -> Result<BufReader<Cursor<Vec<u8>>>, io::Error> {
+    let data = read_object_data(sha)?;
     debug!("{}", iterable_to_string(&mut data.iter()));
     let cursor = Cursor::new(data);
     Ok(BufReader::new(cursor))
@@ -142,12 +308,48 @@ fn check_header_is_valid(buf: &Vec<u8>, expected: Vec<char>) -> usize {
 
 #[cfg(test)]
 mod tests {
+    use std::slice;
+
     use flexi_logger::*;
 
     use super::*;
+    use crate::test_repo::{lock_cwd, TestRepo};
+
+    #[test]
+    fn prefixes_match_loose_and_packed_objects() {
+        let repo = TestRepo::new("object_prefixes");
+        // two blobs whose shas both start 9a80, one packed and one loose
+        let packed = repo.write_packed_object("blob", b"x108248");
+        let loose = repo.write_object("blob", b"x171350");
+        assert_eq!(
+            packed.to_string(),
+            "9a809728e6e3861c2fd3d33ddae4cb5f83d2e481"
+        );
+        assert_eq!(
+            loose.to_string(),
+            "9a80c382a7237395513835c7862daf6505d1c514"
+        );
+        assert!(!packed.to_path().exists());
+        let both = vec![packed.clone(), loose.clone()];
+        assert_eq!(find_objects_with_prefix("9a80").unwrap(), both);
+        assert_eq!(find_objects_with_prefix("9A80").unwrap(), both);
+        assert_eq!(
+            find_objects_with_prefix("9a809").unwrap(),
+            slice::from_ref(&packed)
+        );
+        assert_eq!(
+            find_objects_with_prefix("9a80c").unwrap(),
+            slice::from_ref(&loose)
+        );
+        assert_eq!(find_objects_with_prefix("9a81").unwrap(), []);
+        assert_eq!(shortest_unique_prefix(&packed, 4).unwrap(), "9a809");
+        assert_eq!(shortest_unique_prefix(&loose, 0).unwrap(), "9a80c");
+        assert_eq!(shortest_unique_prefix(&loose, 7).unwrap(), "9a80c38");
+    }
 
     #[test]
     fn test_sha() {
+        let _cwd = lock_cwd();
         Logger::try_with_str("debug")
             .unwrap()
             .duplicate_to_stdout(Duplicate::All)
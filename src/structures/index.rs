@@ -4,23 +4,25 @@ use crate::structures::git_objects::Blob;
 use crate::utils::iterable_to_string;
 use derive_getters::Getters;
 use log::*;
+use std::collections::BTreeSet;
 use std::ffi::OsStr;
-use std::fs::File;
+use std::fs::{self, File, Metadata};
 use std::io::{self, BufRead, Seek};
 use std::io::{BufReader, Read, Write};
+use std::os::unix::ffi::OsStringExt;
 use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use std::{fmt, vec};
 
-use super::{GitObject, Sha};
+use super::{GitObject, Sha, TreeEntry};
 
 const VERSION_NUMBER: u32 = 2;
 
-fn first_flags_byte() -> u16 {
+fn first_flags_byte(stage: u8) -> u16 {
     let assume_valid = 0;
     let extended_flag = 0; // false in v2
-    let stage_bits = 0; // 2 bits, used during merge?
-    let total = assume_valid & extended_flag << 1 & stage_bits << 2;
+    let stage_bits = (stage & 0b11) as u16; // 2 bits, 1-3 hold the base/ours/theirs sides of a conflict
+    let total = assume_valid << 3 | extended_flag << 2 | stage_bits;
     total << 0xC // shift to make it the upper 4 bits
 }
 
@@ -74,7 +76,7 @@ fn get_needed_padding_count(index_len: isize) -> usize {
     (x - 8).abs() as usize
 }
 
-#[derive(Debug, Getters, PartialEq)]
+#[derive(Clone, Debug, Getters, PartialEq)]
 pub struct IndexEntry {
     ctime: u32,
     ctime_nsec: u32,
@@ -105,7 +107,7 @@ impl IndexEntry {
         let file_bytes: Vec<u8> = file.bytes().map(|b| b.unwrap()).collect();
         let mut blob = Blob::new_from_bytes(file_bytes, mode);
         blob.write_to_disk()?;
-        let flags_byte = first_flags_byte();
+        let flags_byte = first_flags_byte(0);
         let file_name = path_name(file_path);
         let name_length = if file_name.len() < 0xFFF {
             file_name.len()
@@ -134,8 +136,37 @@ impl IndexEntry {
         })
     }
 
+    /// New index entry for a blob out of a tree. There's no file to stat yet, so like git
+    /// the stat data stays zeroed until the entry is refreshed from the working tree
+    pub fn new_from_tree_entry(entry: &TreeEntry, stage: u8) -> Self {
+        let file_name: Vec<u8> = entry.path().as_bytes().to_vec();
+        let name_length = if file_name.len() < 0xFFF {
+            file_name.len()
+        } else {
+            0xFFF
+        } as u16;
+        let flags = name_length | first_flags_byte(stage);
+        let padding_count = get_needed_padding_count((62 + file_name.len()) as isize);
+        Self {
+            ctime: 0,
+            ctime_nsec: 0,
+            mtime: 0,
+            mtime_nsec: 0,
+            dev: 0,
+            ino: 0,
+            mode: *entry.mode(),
+            uid: 0,
+            gid: 0,
+            size: 0,
+            file_sha: entry.sha().clone(),
+            flags,
+            file_name,
+            padding: vec![0; padding_count],
+        }
+    }
+
     /// Constructs index entry object from an existing git/reflogged index file
-    pub fn from_index_file(reader: &mut BufReader<File>) -> Result<Self, io::Error> {
+    pub fn from_index_file<R: Read + Seek>(reader: &mut R) -> Result<Self, io::Error> {
         let before = reader.stream_position()?;
         let ctime = read_next_u32(reader)?;
         let ctime_nsec = read_next_u32(reader)?;
@@ -191,6 +222,66 @@ impl IndexEntry {
         Ok(())
     }
 
+    /// Takes the stat data from the file now in the working tree, after it has been written out
+    pub fn refresh_stat(&mut self, metadata: &Metadata) {
+        self.ctime = metadata.ctime() as u32;
+        self.ctime_nsec = metadata.ctime_nsec() as u32;
+        self.mtime = metadata.mtime() as u32;
+        self.mtime_nsec = metadata.mtime_nsec() as u32;
+        self.dev = metadata.dev() as u32;
+        self.ino = metadata.ino() as u32;
+        self.uid = metadata.uid();
+        self.gid = metadata.gid();
+        self.size = metadata.size() as u32;
+    }
+
+    /// Whether the stat data says the file hasn't been touched since it was staged
+    pub fn is_stat_clean(&self, metadata: &Metadata) -> bool {
+        self.mtime == metadata.mtime() as u32
+            && self.mtime_nsec == metadata.mtime_nsec() as u32
+            && self.ctime == metadata.ctime() as u32
+            && self.ctime_nsec == metadata.ctime_nsec() as u32
+            && self.ino == metadata.ino() as u32
+            && self.size == metadata.size() as u32
+    }
+
+    /// Whether the working tree file still has the staged content. Files whose stat data
+    /// matches are trusted without being rehashed
+    pub fn matches_worktree(&self) -> bool {
+        let name = self.get_readable_file_name();
+        let path = Path::new(&name);
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(m) => m,
+            Err(_) => return false,
+        };
+        if self.is_stat_clean(&metadata) {
+            return true;
+        }
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            let target = match fs::read_link(path) {
+                Ok(t) => t,
+                Err(_) => return false,
+            };
+            let blob = Blob::new_from_bytes(target.into_os_string().into_vec(), 0o120000);
+            return self.mode == 0o120000 && blob.sha() == &self.file_sha;
+        }
+        if !file_type.is_file() {
+            return false;
+        }
+        let executable = |mode: u32| mode & 0o111 != 0;
+        if executable(self.mode) != executable(metadata.mode()) {
+            return false;
+        }
+        let blob = Blob::new_from_raw_file(path);
+        blob.sha() == &self.file_sha
+    }
+
+    /// The merge stage of the entry, 0 for normal entries and 1-3 for the sides of a conflict
+    pub fn stage(&self) -> u8 {
+        ((self.flags >> 0xC) & 0b11) as u8
+    }
+
     pub fn get_readable_sha(&self) -> String {
         self.file_sha.to_string()
     }
@@ -314,9 +405,44 @@ impl IndexFile {
         idx_file
     }
 
+    /// An index with no entries that only exists in memory until it is written
+    pub fn empty() -> Self {
+        Self {
+            header: Header::new(0),
+            index_entries: vec![],
+        }
+    }
+
+    /// Adds a single entry, keeping the index sorted by path and stage. A stage 0 entry
+    /// resolves the path and drops its conflict stages, and a conflict stage drops stage 0
+    pub fn add_entry(&mut self, entry: IndexEntry) {
+        self.index_entries.retain(|existing| {
+            existing.file_name() != entry.file_name()
+                || (existing.stage() != entry.stage()
+                    && existing.stage() != 0
+                    && entry.stage() != 0)
+        });
+        let position = self.index_entries.partition_point(|existing| {
+            (existing.file_name(), existing.stage()) < (entry.file_name(), entry.stage())
+        });
+        self.index_entries.insert(position, entry);
+        self.header.file_count = self.index_entries.len() as u32;
+    }
+
+    /// Retakes the stat data of the named entries from their files in the working tree
+    pub fn refresh_entries(&mut self, paths: &BTreeSet<String>) -> Result<(), io::Error> {
+        for entry in self.index_entries.iter_mut() {
+            let name = entry.get_readable_file_name();
+            if paths.contains(&name) {
+                entry.refresh_stat(&fs::symlink_metadata(&name)?);
+            }
+        }
+        Ok(())
+    }
+
     pub fn add_files(&mut self, path_strings: &Vec<PathBuf>) {
         info!("Adding files to index: {:?}", path_strings);
-        let mut index_entries = path_strings
+        let index_entries: Vec<IndexEntry> = path_strings
             .iter()
             .map(|s| {
                 if !s.exists() {
@@ -334,8 +460,10 @@ impl IndexFile {
                 ie.unwrap()
             })
             .collect();
-        self.index_entries.append(&mut index_entries);
-        self.header.file_count = self.index_entries.len() as u32;
+        // re-adding a path restages it rather than listing it twice
+        for entry in index_entries {
+            self.add_entry(entry);
+        }
     }
 
     pub fn remove_files(&mut self, path_strings: &Vec<PathBuf>) {
@@ -399,7 +527,7 @@ impl IndexFile {
                     let entry = TreeExtensionEntry::new_from_disk(reader, &mut bytes_read)?;
                     entries.push(entry);
                 }
-                println!("{:?}", entries);
+                debug!("{:?}", entries);
             }
             'R' => {
                 // Resolve undo
@@ -443,6 +571,7 @@ impl fmt::Display for IndexFile {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_repo::lock_cwd;
 
     fn get_hardcoded_entry() -> IndexEntry {
         IndexEntry {
@@ -487,6 +616,7 @@ mod tests {
 
     #[test]
     fn idx_entry_deserialize() {
+        let _cwd = lock_cwd();
         let file = File::open("test_data/index").unwrap();
         let mut reader = BufReader::new(file);
         Header::validate(&mut reader).unwrap();
@@ -497,6 +627,7 @@ mod tests {
 
     #[test]
     fn idx_entry_serialize() {
+        let _cwd = lock_cwd();
         let ie = IndexEntry::new("/home/samuwen/Documents/repos/entirely_fake_repo/boop/README.md")
             .unwrap();
         let hardcoded = get_hardcoded_entry();
@@ -505,6 +636,7 @@ mod tests {
 
     #[test]
     fn new_index_file() {
+        let _cwd = lock_cwd();
         // validates that our new index file construction matches the index file git generates for the same single file
         let path_strings = vec![PathBuf::from(
             "/home/samuwen/Documents/repos/entirely_fake_repo/boop/README.md",
@@ -515,6 +647,40 @@ mod tests {
         assert_eq!(idx_file, from_disk);
     }
 
+    #[test]
+    fn idx_entry_from_tree_entry() {
+        let hardcoded = get_hardcoded_entry();
+        let tree_entry = TreeEntry::new(
+            String::from("boop/README.md"),
+            33188,
+            hardcoded.file_sha().clone(),
+        );
+        let ie = IndexEntry::new_from_tree_entry(&tree_entry, 0);
+        assert_eq!(ie.flags, hardcoded.flags);
+        assert_eq!(ie.padding, hardcoded.padding);
+        assert_eq!(ie.file_name, hardcoded.file_name);
+        assert_eq!(ie.ctime, 0);
+    }
+
+    #[test]
+    fn idx_entry_stage_round_trip() {
+        let tree_entry = TreeEntry::new(
+            String::from("boop/README.md"),
+            33188,
+            get_hardcoded_entry().file_sha().clone(),
+        );
+        for stage in 0..=3 {
+            let ie = IndexEntry::new_from_tree_entry(&tree_entry, stage);
+            assert_eq!(ie.stage(), stage);
+            let mut out = vec![];
+            ie.write(&mut out).unwrap();
+            let mut reader = BufReader::new(io::Cursor::new(out));
+            let from_bytes = IndexEntry::from_index_file(&mut reader).unwrap();
+            assert_eq!(from_bytes.stage(), stage);
+            assert_eq!(from_bytes, ie);
+        }
+    }
+
     #[test]
     fn get_padding_count() {
         let count = get_needed_padding_count(5);
@@ -529,6 +695,7 @@ mod tests {
 
     #[test]
     fn tree_ext() {
+        let _cwd = lock_cwd();
         let f = IndexFile::from_disk().unwrap();
         assert_eq!(true, false);
     }
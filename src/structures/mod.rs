@@ -1,19 +1,27 @@
 mod blob;
 mod commit;
+mod commit_graph;
 mod commit_printer;
 mod common;
 mod git_objects;
 mod index;
+mod pack;
+mod pretty_format;
 mod ref_file;
 mod sha;
+mod tag;
 mod tree;
 
 pub use blob::*;
 pub use commit::*;
+pub use commit_graph::*;
 pub use commit_printer::*;
 pub use common::*;
 pub use git_objects::*;
 pub use index::*;
+pub use pack::*;
+pub use pretty_format::*;
 pub use ref_file::*;
 pub use sha::*;
+pub use tag::*;
 pub use tree::*;
@@ -0,0 +1,799 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+};
+
+use derive_getters::Getters;
+use flate2::{bufread::ZlibDecoder, Crc};
+use log::{debug, info};
+use sha1::{Digest, Sha1};
+
+use super::{compress, read_next_sha, read_next_u32, Sha};
+
+pub const PACK_DIRECTORY: &str = ".re_flogged/objects/pack";
+
+const IDX_MAGIC: [u8; 4] = [0xff, b't', b'O', b'c'];
+pub const OBJ_COMMIT: u8 = 1;
+pub const OBJ_TREE: u8 = 2;
+pub const OBJ_BLOB: u8 = 3;
+pub const OBJ_TAG: u8 = 4;
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+/// Longest delta chain followed before the pack is assumed to be corrupt
+const MAX_DELTA_DEPTH: usize = 10_000;
+/// Size of the base blocks a new delta looks for in its target
+const DELTA_BLOCK: usize = 16;
+const MAX_BLOCK_CANDIDATES: usize = 64;
+const MAX_INSERT: usize = 0x7f;
+const MAX_COPY: usize = 0x10000;
+/// Most room reserved up front for an inflated object, as the size comes from the pack and
+/// a corrupt one could ask for any amount
+const MAX_PREALLOCATION: u64 = 1 << 24;
+
+thread_local! {
+    static PACK_INDEXES: RefCell<Option<Rc<Vec<PackIndex>>>> = const { RefCell::new(None) };
+}
+
+fn corrupt(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// A version 2 pack index, mapping the names of the objects in a pack to their offsets
+#[derive(Debug, Getters)]
+pub struct PackIndex {
+    pack_path: PathBuf,
+    shas: Vec<Sha>,
+    crcs: Vec<u32>,
+    offsets: Vec<u64>,
+}
+
+impl PackIndex {
+    pub fn from_file(path: &Path) -> Result<Self, io::Error> {
+        debug!("Reading pack index {}", path.display());
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != IDX_MAGIC || read_next_u32(&mut reader)? != 2 {
+            return Err(corrupt("only version 2 pack indexes are supported"));
+        }
+        let mut fanout = [0; 256];
+        for slot in fanout.iter_mut() {
+            *slot = read_next_u32(&mut reader)?;
+        }
+        let count = fanout[255] as usize;
+        let mut shas = Vec::with_capacity(count);
+        for _i in 0..count {
+            shas.push(read_next_sha(&mut reader)?);
+        }
+        let mut crcs = Vec::with_capacity(count);
+        for _i in 0..count {
+            crcs.push(read_next_u32(&mut reader)?);
+        }
+        let mut small_offsets = Vec::with_capacity(count);
+        for _i in 0..count {
+            small_offsets.push(read_next_u32(&mut reader)?);
+        }
+        // offsets with the high bit set index into a table of 8 byte offsets for big packs
+        let large_count = small_offsets
+            .iter()
+            .filter(|&&o| o & 0x8000_0000 != 0)
+            .count();
+        let mut large_offsets = Vec::with_capacity(large_count);
+        for _i in 0..large_count {
+            let mut buf = [0; 8];
+            reader.read_exact(&mut buf)?;
+            large_offsets.push(u64::from_be_bytes(buf));
+        }
+        let mut offsets = Vec::with_capacity(count);
+        for offset in small_offsets {
+            match offset & 0x8000_0000 != 0 {
+                true => {
+                    let large = large_offsets
+                        .get((offset & 0x7fff_ffff) as usize)
+                        .ok_or_else(|| corrupt("pack index large offset out of range"))?;
+                    offsets.push(*large);
+                }
+                false => offsets.push(offset as u64),
+            }
+        }
+        Ok(Self {
+            pack_path: path.with_extension("pack"),
+            shas,
+            crcs,
+            offsets,
+        })
+    }
+
+    /// Every pack index in the repository, read once and shared for the rest of the process
+    pub fn all() -> Result<Rc<Vec<PackIndex>>, io::Error> {
+        if let Some(indexes) = PACK_INDEXES.with(|cache| cache.borrow().clone()) {
+            return Ok(indexes);
+        }
+        let mut indexes = vec![];
+        let dir = Path::new(PACK_DIRECTORY);
+        if dir.is_dir() {
+            let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "idx"))
+                .collect();
+            paths.sort();
+            for path in paths {
+                indexes.push(PackIndex::from_file(&path)?);
+            }
+        }
+        let indexes = Rc::new(indexes);
+        PACK_INDEXES.with(|cache| *cache.borrow_mut() = Some(indexes.clone()));
+        Ok(indexes)
+    }
+
+    /// Forgets the cached indexes so packs written or deleted since are seen
+    pub fn reload() {
+        PACK_INDEXES.with(|cache| *cache.borrow_mut() = None);
+    }
+
+    /// The offset of the object in the pack, if the pack has it
+    pub fn find(&self, sha: &Sha) -> Option<u64> {
+        self.shas
+            .binary_search_by(|probe| probe.buf().cmp(sha.buf()))
+            .ok()
+            .map(|position| self.offsets[position])
+    }
+
+    /// The objects in the pack whose hex name starts with the prefix
+    pub fn find_prefix(&self, prefix: &str) -> Vec<Sha> {
+        // the names are sorted, so the matches sit together from the first one not below it
+        let start = self
+            .shas
+            .partition_point(|sha| sha.to_string().as_str() < prefix);
+        self.shas[start..]
+            .iter()
+            .take_while(|sha| sha.to_string().starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    /// Reads the object out of this index's pack, if the pack has it
+    pub fn read_object(&self, sha: &Sha) -> Result<Option<PackedObject>, io::Error> {
+        let offset = match self.find(sha) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        info!("Reading {} from {}", sha, self.pack_path.display());
+        let reader = BufReader::new(File::open(&self.pack_path)?);
+        let (kind, data) = read_object_at(reader, offset)?;
+        Ok(Some(PackedObject {
+            kind: kind_name(kind)?,
+            data,
+        }))
+    }
+}
+
+/// An object read out of a pack, with any deltas already applied
+pub struct PackedObject {
+    pub kind: &'static str,
+    pub data: Vec<u8>,
+}
+
+/// Looks for the object in every pack, returning its type and contents if one has it
+pub fn read_packed_object(sha: &Sha) -> Result<Option<PackedObject>, io::Error> {
+    for index in PackIndex::all()?.iter() {
+        if let Some(object) = index.read_object(sha)? {
+            return Ok(Some(object));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether any pack has the object
+pub fn is_packed(sha: &Sha) -> bool {
+    match PackIndex::all() {
+        Ok(indexes) => indexes.iter().any(|index| index.find(sha).is_some()),
+        Err(_) => false,
+    }
+}
+
+pub fn kind_number(kind: &str) -> Result<u8, io::Error> {
+    match kind {
+        "commit" => Ok(OBJ_COMMIT),
+        "tree" => Ok(OBJ_TREE),
+        "blob" => Ok(OBJ_BLOB),
+        "tag" => Ok(OBJ_TAG),
+        _ => Err(corrupt("unknown object type")),
+    }
+}
+
+pub fn kind_name(kind: u8) -> Result<&'static str, io::Error> {
+    match kind {
+        OBJ_COMMIT => Ok("commit"),
+        OBJ_TREE => Ok("tree"),
+        OBJ_BLOB => Ok("blob"),
+        OBJ_TAG => Ok("tag"),
+        _ => Err(corrupt("unknown object type in pack")),
+    }
+}
+
+/// Reads the object stored at the offset, following its chain of deltas down to the base
+/// object and applying them back up. A chain longer than git would ever write is taken to
+/// loop back on itself
+fn read_object_at(
+    mut reader: BufReader<File>,
+    mut offset: u64,
+) -> Result<(u8, Vec<u8>), io::Error> {
+    let mut deltas = vec![];
+    let (kind, mut data) = loop {
+        if deltas.len() > MAX_DELTA_DEPTH {
+            return Err(corrupt("delta chain is too long"));
+        }
+        reader.seek(SeekFrom::Start(offset))?;
+        let (kind, size) = read_object_header(&mut reader)?;
+        match kind {
+            OBJ_OFS_DELTA => {
+                let distance = read_offset_delta_distance(&mut reader)?;
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .ok_or_else(|| corrupt("delta base offset is before the start of the pack"))?;
+                deltas.push(inflate(&mut reader, size)?);
+                offset = base_offset;
+            }
+            OBJ_REF_DELTA => {
+                let base_sha = read_next_sha(&mut reader)?;
+                deltas.push(inflate(&mut reader, size)?);
+                // the base can live in another pack, or loose when the pack is thin
+                match find_in_packs(&base_sha)? {
+                    Some((pack_path, base_offset)) if !base_sha.to_path().is_file() => {
+                        reader = BufReader::new(File::open(pack_path)?);
+                        offset = base_offset;
+                    }
+                    _ => break read_object_content(&base_sha)?,
+                }
+            }
+            _ => {
+                kind_name(kind)?;
+                break (kind, inflate(&mut reader, size)?);
+            }
+        }
+    };
+    for delta in deltas.iter().rev() {
+        data = apply_delta(&data, delta)?;
+    }
+    Ok((kind, data))
+}
+
+/// The pack holding the object and where in it the object starts
+fn find_in_packs(sha: &Sha) -> Result<Option<(PathBuf, u64)>, io::Error> {
+    Ok(PackIndex::all()?
+        .iter()
+        .find_map(|index| Some((index.pack_path.clone(), index.find(sha)?))))
+}
+
+/// The object's type and contents, wherever it is stored
+pub fn read_object_content(sha: &Sha) -> Result<(u8, Vec<u8>), io::Error> {
+    let data = super::read_object_data(sha)?;
+    let null = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| corrupt("object has no header"))?;
+    let kind = data.split(|&b| b == b' ').next().unwrap_or_default();
+    let kind = kind_number(&String::from_utf8_lossy(kind))?;
+    Ok((kind, data[null + 1..].to_vec()))
+}
+
+/// Reads the type and inflated size packed into the variable length object header
+pub fn read_object_header<R: Read>(reader: &mut R) -> Result<(u8, u64), io::Error> {
+    let mut byte = read_byte(reader)?;
+    let kind = (byte >> 4) & 0b111;
+    let mut size = (byte & 0x0f) as u64;
+    let mut shift = 4;
+    while byte & 0x80 != 0 {
+        if shift >= 64 {
+            return Err(corrupt("object header size is too long"));
+        }
+        byte = read_byte(reader)?;
+        size |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+    }
+    Ok((kind, size))
+}
+
+/// OFS_DELTA bases are named by how far back in the pack they start. Each continuation
+/// byte adds one before shifting so that no two encodings mean the same distance
+fn read_offset_delta_distance<R: Read>(reader: &mut R) -> Result<u64, io::Error> {
+    let mut byte = read_byte(reader)?;
+    let mut distance = (byte & 0x7f) as u64;
+    while byte & 0x80 != 0 {
+        byte = read_byte(reader)?;
+        distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+    }
+    Ok(distance)
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> Result<u8, io::Error> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn inflate<R: Read>(reader: &mut R, size: u64) -> Result<Vec<u8>, io::Error> {
+    let mut decoder = ZlibDecoder::new(BufReader::new(reader));
+    let mut data = Vec::with_capacity(size.min(MAX_PREALLOCATION) as usize);
+    decoder.read_to_end(&mut data)?;
+    if data.len() as u64 != size {
+        return Err(corrupt("packed object has the wrong size"));
+    }
+    Ok(data)
+}
+
+fn read_delta_size(delta: &[u8], position: &mut usize) -> Result<usize, io::Error> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *delta
+            .get(*position)
+            .ok_or_else(|| corrupt("delta ends inside its header"))?;
+        *position += 1;
+        if shift >= usize::BITS {
+            return Err(corrupt("delta size is too long"));
+        }
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+/// Rebuilds an object from its delta base. A delta is the two sizes followed by
+/// instructions to either copy a range of the base or insert new bytes
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, io::Error> {
+    let mut position = 0;
+    let base_size = read_delta_size(delta, &mut position)?;
+    if base_size != base.len() {
+        return Err(corrupt("delta base has the wrong size"));
+    }
+    let result_size = read_delta_size(delta, &mut position)?;
+    let mut result = Vec::with_capacity(result_size);
+    while position < delta.len() {
+        let op = delta[position];
+        position += 1;
+        if op & 0x80 != 0 {
+            // the low four bits say which offset bytes follow, the next three the size bytes
+            let mut fields = [0usize; 7];
+            for (bit, field) in fields.iter_mut().enumerate() {
+                if op & (1 << bit) != 0 {
+                    *field = *delta
+                        .get(position)
+                        .ok_or_else(|| corrupt("delta ends inside a copy"))?
+                        as usize;
+                    position += 1;
+                }
+            }
+            let offset = fields[0] | fields[1] << 8 | fields[2] << 16 | fields[3] << 24;
+            let mut size = fields[4] | fields[5] << 8 | fields[6] << 16;
+            if size == 0 {
+                size = 0x10000;
+            }
+            let copied = base
+                .get(offset..offset + size)
+                .ok_or_else(|| corrupt("delta copies past the end of its base"))?;
+            result.extend_from_slice(copied);
+        } else if op != 0 {
+            let inserted = delta
+                .get(position..position + op as usize)
+                .ok_or_else(|| corrupt("delta ends inside an insert"))?;
+            result.extend_from_slice(inserted);
+            position += op as usize;
+        } else {
+            return Err(corrupt("delta has a reserved instruction"));
+        }
+    }
+    if result.len() != result_size {
+        return Err(corrupt("delta produced the wrong size"));
+    }
+    Ok(result)
+}
+
+/// Writes objects into a new version 2 pack, then writes its index. An object has to be
+/// written before any delta that uses it as a base
+pub struct PackWriter {
+    dir: PathBuf,
+    temp_path: PathBuf,
+    writer: BufWriter<File>,
+    hasher: Sha1,
+    offset: u64,
+    expected_count: u32,
+    entries: Vec<(Sha, u32, u64)>,
+}
+
+impl PackWriter {
+    pub fn new(dir: &Path, count: u32) -> Result<Self, io::Error> {
+        fs::create_dir_all(dir)?;
+        let temp_path = dir.join(format!("tmp_pack_{}", process::id()));
+        let mut pack = Self {
+            dir: dir.to_path_buf(),
+            writer: BufWriter::new(File::create(&temp_path)?),
+            temp_path,
+            hasher: Sha1::new(),
+            offset: 0,
+            expected_count: count,
+            entries: Vec::with_capacity(count as usize),
+        };
+        let mut header = b"PACK".to_vec();
+        header.extend(2u32.to_be_bytes());
+        header.extend(count.to_be_bytes());
+        pack.write_raw(&header)?;
+        Ok(pack)
+    }
+
+    /// Writes a whole object, returning the offset it starts at
+    pub fn write_object(&mut self, sha: &Sha, kind: u8, data: &[u8]) -> Result<u64, io::Error> {
+        let mut entry = encode_object_header(kind, data.len() as u64);
+        entry.extend(compress(data));
+        self.write_entry(sha, &entry)
+    }
+
+    /// Writes an object as a delta against a base earlier in the pack, returning the offset
+    /// it starts at
+    pub fn write_delta(
+        &mut self,
+        sha: &Sha,
+        base_offset: u64,
+        delta: &[u8],
+    ) -> Result<u64, io::Error> {
+        let mut entry = encode_object_header(OBJ_OFS_DELTA, delta.len() as u64);
+        entry.extend(encode_offset_delta_distance(self.offset - base_offset));
+        entry.extend(compress(delta));
+        self.write_entry(sha, &entry)
+    }
+
+    /// Writes the pack's checksum and its index, naming both after the checksum. Returns
+    /// the path of the index
+    pub fn finish(mut self) -> Result<PathBuf, io::Error> {
+        if self.entries.len() != self.expected_count as usize {
+            return Err(corrupt("pack has the wrong number of objects"));
+        }
+        let checksum: [u8; 20] = self.hasher.clone().finalize().into();
+        self.writer.write_all(&checksum)?;
+        self.writer.flush()?;
+        let pack_path = self
+            .dir
+            .join(format!("pack-{}.pack", hex::encode(checksum)));
+        fs::rename(&self.temp_path, &pack_path)?;
+        let idx_path = pack_path.with_extension("idx");
+        debug!("Writing pack index {}", idx_path.display());
+        fs::write(&idx_path, build_index(&mut self.entries, &checksum))?;
+        Ok(idx_path)
+    }
+
+    fn write_entry(&mut self, sha: &Sha, entry: &[u8]) -> Result<u64, io::Error> {
+        let offset = self.offset;
+        let mut crc = Crc::new();
+        crc.update(entry);
+        self.entries.push((sha.clone(), crc.sum(), offset));
+        self.write_raw(entry)?;
+        Ok(offset)
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> Result<(), io::Error> {
+        self.hasher.update(bytes);
+        self.writer.write_all(bytes)?;
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+fn build_index(entries: &mut [(Sha, u32, u64)], pack_checksum: &[u8; 20]) -> Vec<u8> {
+    entries.sort_by(|a, b| a.0.buf().cmp(b.0.buf()));
+    let mut idx = IDX_MAGIC.to_vec();
+    idx.extend(2u32.to_be_bytes());
+    let mut fanout = [0u32; 256];
+    for (sha, _, _) in entries.iter() {
+        fanout[sha.buf()[0] as usize] += 1;
+    }
+    let mut total = 0;
+    for count in fanout.iter() {
+        total += count;
+        idx.extend(total.to_be_bytes());
+    }
+    for (sha, _, _) in entries.iter() {
+        idx.extend(sha.buf());
+    }
+    for (_, crc, _) in entries.iter() {
+        idx.extend(crc.to_be_bytes());
+    }
+    let mut large_offsets = vec![];
+    for (_, _, offset) in entries.iter() {
+        // offsets that don't fit in 31 bits go in a table of 8 byte offsets after this one
+        match *offset < 0x8000_0000 {
+            true => idx.extend((*offset as u32).to_be_bytes()),
+            false => {
+                idx.extend((0x8000_0000 | large_offsets.len() as u32).to_be_bytes());
+                large_offsets.push(*offset);
+            }
+        }
+    }
+    for offset in large_offsets {
+        idx.extend(offset.to_be_bytes());
+    }
+    idx.extend(pack_checksum);
+    let checksum = Sha::new_hash(&idx);
+    idx.extend(checksum.buf());
+    idx
+}
+
+fn encode_object_header(kind: u8, size: u64) -> Vec<u8> {
+    let mut bytes = vec![(kind << 4) | (size & 0x0f) as u8];
+    let mut size = size >> 4;
+    while size != 0 {
+        *bytes.last_mut().unwrap() |= 0x80;
+        bytes.push((size & 0x7f) as u8);
+        size >>= 7;
+    }
+    bytes
+}
+
+fn encode_offset_delta_distance(distance: u64) -> Vec<u8> {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    let mut distance = distance >> 7;
+    while distance != 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+fn encode_delta_size(size: usize, delta: &mut Vec<u8>) {
+    let mut size = size;
+    while size >= 0x80 {
+        delta.push(0x80 | (size & 0x7f) as u8);
+        size >>= 7;
+    }
+    delta.push(size as u8);
+}
+
+/// Builds a delta that turns the base into the target. The base is indexed in fixed size
+/// blocks, and wherever a block turns up in the target the match is grown as far as it goes
+/// and copied, with everything else inserted
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = vec![];
+    encode_delta_size(base.len(), &mut delta);
+    encode_delta_size(target.len(), &mut delta);
+    let mut blocks: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for start in (0..base.len().saturating_sub(DELTA_BLOCK - 1)).step_by(DELTA_BLOCK) {
+        let starts = blocks.entry(&base[start..start + DELTA_BLOCK]).or_default();
+        if starts.len() < MAX_BLOCK_CANDIDATES {
+            starts.push(start);
+        }
+    }
+    let mut insert = vec![];
+    let mut position = 0;
+    while position < target.len() {
+        let best = target
+            .get(position..position + DELTA_BLOCK)
+            .and_then(|block| blocks.get(block))
+            .and_then(|starts| {
+                starts
+                    .iter()
+                    .map(|&start| (start, common_prefix(&base[start..], &target[position..])))
+                    .max_by_key(|&(_, length)| length)
+            });
+        match best {
+            Some((start, length)) => {
+                position += length;
+                // the bytes just before the match may match too, copy them instead of inserting
+                let (mut start, mut length) = (start, length);
+                while start > 0 && insert.last() == Some(&base[start - 1]) {
+                    insert.pop();
+                    start -= 1;
+                    length += 1;
+                }
+                flush_insert(&mut insert, &mut delta);
+                push_copy(start, length, &mut delta);
+            }
+            None => {
+                insert.push(target[position]);
+                position += 1;
+                if insert.len() == MAX_INSERT {
+                    flush_insert(&mut insert, &mut delta);
+                }
+            }
+        }
+    }
+    flush_insert(&mut insert, &mut delta);
+    delta
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count()
+}
+
+fn flush_insert(insert: &mut Vec<u8>, delta: &mut Vec<u8>) {
+    if !insert.is_empty() {
+        delta.push(insert.len() as u8);
+        delta.append(insert);
+    }
+}
+
+fn push_copy(offset: usize, length: usize, delta: &mut Vec<u8>) {
+    let (mut offset, mut length) = (offset, length);
+    while length > 0 {
+        let size = length.min(MAX_COPY);
+        let mut op = 0x80;
+        let mut fields = vec![];
+        for bit in 0..4 {
+            let byte = (offset >> (8 * bit)) & 0xff;
+            if byte != 0 {
+                op |= 1 << bit;
+                fields.push(byte as u8);
+            }
+        }
+        // a size of zero means the largest copy, so that size needs no bytes at all
+        for bit in 0..3 {
+            let byte = (size >> (8 * bit)) & 0xff;
+            if size != MAX_COPY && byte != 0 {
+                op |= 1 << (4 + bit);
+                fields.push(byte as u8);
+            }
+        }
+        delta.push(op);
+        delta.extend(fields);
+        offset += size;
+        length -= size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_repo::TestRepo;
+
+    #[test]
+    fn object_header() {
+        // blob of 300 bytes: 300 = 0b1_0010_1100
+        let mut reader = Cursor::new(vec![0b1011_1100, 0b0001_0010]);
+        assert_eq!(read_object_header(&mut reader).unwrap(), (OBJ_BLOB, 300));
+        let mut reader = Cursor::new(vec![0b0001_0101]);
+        assert_eq!(read_object_header(&mut reader).unwrap(), (OBJ_COMMIT, 5));
+        // continuation bytes past what a size can hold mean the pack is corrupt
+        let mut reader = Cursor::new(vec![0xff; 32]);
+        let err = read_object_header(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(apply_delta(b"", &[0xff; 32]).is_err());
+        // nor is room made for however big the header says the object is
+        let mut reader = Cursor::new(compress(&b"abc"[..]));
+        assert!(inflate(&mut reader, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn offset_delta_distance() {
+        let mut reader = Cursor::new(vec![0x05]);
+        assert_eq!(read_offset_delta_distance(&mut reader).unwrap(), 5);
+        // two bytes: ((0x01 + 1) << 7) | 0x00
+        let mut reader = Cursor::new(vec![0x81, 0x00]);
+        assert_eq!(read_offset_delta_distance(&mut reader).unwrap(), 256);
+    }
+
+    #[test]
+    fn delta_copy_and_insert() {
+        let base = b"hello there world";
+        let delta = vec![
+            17, // base size
+            17, // result size
+            0x90, 6, // copy 6 bytes from offset 0
+            5, b'b', b'r', b'a', b'v', b'e', // insert "brave"
+            0x91, 11, 6, // copy 6 bytes from offset 11
+        ];
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"hello brave world");
+    }
+
+    #[test]
+    fn header_and_distance_round_trip() {
+        for size in [0, 15, 16, 300, 1 << 20, u32::MAX as u64] {
+            let mut reader = Cursor::new(encode_object_header(OBJ_TREE, size));
+            assert_eq!(read_object_header(&mut reader).unwrap(), (OBJ_TREE, size));
+        }
+        for distance in [1, 127, 128, 256, 16511, 16512, 1 << 30] {
+            let mut reader = Cursor::new(encode_offset_delta_distance(distance));
+            assert_eq!(read_offset_delta_distance(&mut reader).unwrap(), distance);
+        }
+    }
+
+    #[test]
+    fn create_delta_round_trip() {
+        let base: Vec<u8> = (0..2000)
+            .flat_map(|i| format!("line {}\n", i).into_bytes())
+            .collect();
+        let mut target = base.clone();
+        target.splice(500..510, b"something else entirely".iter().cloned());
+        target.extend(b"and a new ending");
+        let delta = create_delta(&base, &target);
+        assert!(delta.len() < target.len() / 20);
+        assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+        // nothing in common still gives a working delta
+        let delta = create_delta(b"abc", &base);
+        assert_eq!(apply_delta(b"abc", &delta).unwrap(), base);
+        // copies longer than one instruction can hold get split up
+        let big = vec![7; 200_000];
+        let delta = create_delta(&big, &big);
+        assert_eq!(apply_delta(&big, &delta).unwrap(), big);
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        let dir = std::env::temp_dir().join(format!("re_flog_pack_test_{}", process::id()));
+        let base = b"hello there world, this is a fairly long blob to delta against".to_vec();
+        let target = b"hello there world, this is a fairly long blob that was changed".to_vec();
+        let base_sha = Sha::new_hash(&base);
+        let target_sha = Sha::new_hash(&target);
+        let mut writer = PackWriter::new(&dir, 2).unwrap();
+        let base_offset = writer.write_object(&base_sha, OBJ_BLOB, &base).unwrap();
+        let delta = create_delta(&base, &target);
+        writer
+            .write_delta(&target_sha, base_offset, &delta)
+            .unwrap();
+        let idx_path = writer.finish().unwrap();
+        let index = PackIndex::from_file(&idx_path).unwrap();
+        assert_eq!(index.shas().len(), 2);
+        let object = index.read_object(&base_sha).unwrap().unwrap();
+        assert_eq!((object.kind, object.data), ("blob", base));
+        let object = index.read_object(&target_sha).unwrap().unwrap();
+        assert_eq!((object.kind, object.data), ("blob", target));
+        assert!(index.read_object(&Sha::empty()).unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn ref_delta_entry(base: &Sha, delta: &[u8]) -> Vec<u8> {
+        let mut entry = encode_object_header(OBJ_REF_DELTA, delta.len() as u64);
+        entry.extend_from_slice(base.buf());
+        entry.extend(compress(delta));
+        entry
+    }
+
+    #[test]
+    fn ref_delta_base_in_another_pack() {
+        let _repo = TestRepo::new("pack_ref_delta");
+        let dir = Path::new(PACK_DIRECTORY);
+        let base = b"hello there world, this is a fairly long blob to delta against".to_vec();
+        let target = b"hello there world, this is a fairly long blob that was changed".to_vec();
+        let base_sha = Sha::new_hash(&base);
+        let target_sha = Sha::new_hash(&target);
+        let mut writer = PackWriter::new(dir, 1).unwrap();
+        writer.write_object(&base_sha, OBJ_BLOB, &base).unwrap();
+        writer.finish().unwrap();
+        let mut writer = PackWriter::new(dir, 1).unwrap();
+        let entry = ref_delta_entry(&base_sha, &create_delta(&base, &target));
+        writer.write_entry(&target_sha, &entry).unwrap();
+        writer.finish().unwrap();
+        PackIndex::reload();
+        let object = read_packed_object(&target_sha).unwrap().unwrap();
+        assert_eq!((object.kind, object.data), ("blob", target));
+    }
+
+    #[test]
+    fn looping_ref_deltas_are_corrupt() {
+        let _repo = TestRepo::new("pack_ref_delta_loop");
+        let sha = Sha::new_hash("its own base");
+        let mut writer = PackWriter::new(Path::new(PACK_DIRECTORY), 1).unwrap();
+        let entry = ref_delta_entry(&sha, &create_delta(b"abc", b"abd"));
+        writer.write_entry(&sha, &entry).unwrap();
+        writer.finish().unwrap();
+        PackIndex::reload();
+        let error = read_packed_object(&sha).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn delta_wrong_base_size() {
+        let delta = vec![3, 1, 1, b'x'];
+        assert!(apply_delta(b"ab", &delta).is_err());
+    }
+}
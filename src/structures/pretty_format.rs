@@ -0,0 +1,601 @@
+use std::{
+    collections::HashMap,
+    io::{self, IsTerminal},
+};
+
+use crate::{
+    config::{get_config_value, parse_bool},
+    utils::{get_current_branch, get_head_sha},
+};
+
+use super::{peel_tags, Commit, Printer, RefFile, Sha};
+
+const RESET: &str = "\x1b[m";
+const HASH_COLOR: &str = "\x1b[33m";
+const DECORATION_COLOR: &str = "\x1b[33m";
+const HEAD_COLOR: &str = "\x1b[1;36m";
+const BRANCH_COLOR: &str = "\x1b[1;32m";
+const REMOTE_COLOR: &str = "\x1b[1;31m";
+const TAG_COLOR: &str = "\x1b[1;33m";
+
+/// A piece of a parsed `--format` template
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+    /// An escape sequence, only written when colours are on unless it is always wanted
+    Color {
+        code: String,
+        always: bool,
+    },
+    /// Colours the fields after it the way git colours them by default
+    AutoColor,
+    /// Pads or truncates the next field
+    Align(Alignment),
+}
+
+/// Something about the commit a placeholder stands for
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Hash,
+    AbbrevHash,
+    Tree,
+    AbbrevTree,
+    Parents,
+    AbbrevParents,
+    AuthorName,
+    AuthorEmail,
+    AuthorDate,
+    CommitterName,
+    CommitterEmail,
+    CommitterDate,
+    Subject,
+    Body,
+    RawBody,
+    Decorations,
+}
+
+/// Which side of its space a padded field sits on
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Side {
+    Left,
+    Right,
+    Center,
+}
+
+/// What is cut from a field too long for its space, replaced with `..`
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Truncate {
+    Nothing,
+    End,
+    Start,
+    Middle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Alignment {
+    side: Side,
+    width: usize,
+    /// The width is the column the field ends at rather than the space it takes
+    to_column: bool,
+    truncate: Truncate,
+}
+
+impl Alignment {
+    fn apply(&self, text: &str, column: usize) -> String {
+        let width = match self.to_column {
+            true => self.width.saturating_sub(column),
+            false => self.width,
+        };
+        let length = visible_width(text);
+        if length > width {
+            return truncate(text, width, self.truncate);
+        }
+        let padding = width - length;
+        let (left, right) = match self.side {
+            Side::Left => (0, padding),
+            Side::Right => (padding, 0),
+            Side::Center => (padding / 2, padding - padding / 2),
+        };
+        format!("{}{}{}", " ".repeat(left), text, " ".repeat(right))
+    }
+}
+
+/// A ref shown next to the commit it points at, as pieces of text with their colours
+type Decoration = Vec<(&'static str, String)>;
+
+/// Prints commits through a template of `%` placeholders, like `log --format='%h %s'`
+pub struct FormatPrinter {
+    parts: Vec<Part>,
+    terminated: bool,
+    color: bool,
+    decorations: HashMap<Sha, Vec<Decoration>>,
+}
+
+impl FormatPrinter {
+    /// A printer for the template. A terminated template ends every commit with a newline like
+    /// `tformat:`, otherwise the newlines only go between commits like `format:`
+    pub fn new(template: &str, terminated: bool) -> Result<Self, io::Error> {
+        let parts = parse_template(template);
+        let decorations = match parts.contains(&Part::Field(Field::Decorations)) {
+            true => load_decorations()?,
+            false => HashMap::new(),
+        };
+        Ok(Self {
+            parts,
+            terminated,
+            color: color_enabled(),
+            decorations,
+        })
+    }
+
+    fn format(&self, commit: &Commit) -> String {
+        let mut out = String::new();
+        let mut alignment = None;
+        let mut auto_color = false;
+        for part in self.parts.iter() {
+            match part {
+                Part::Literal(text) => out.push_str(text),
+                Part::Color { code, always } => {
+                    if self.color || *always {
+                        out.push_str(code);
+                    }
+                    auto_color = false;
+                }
+                Part::AutoColor => {
+                    if self.color {
+                        out.push_str(RESET);
+                    }
+                    auto_color = self.color;
+                }
+                Part::Align(next) => alignment = Some(*next),
+                Part::Field(field) => {
+                    let text = self.expand(*field, commit, auto_color);
+                    match alignment.take() {
+                        Some(alignment) => {
+                            let line = out.rsplit('\n').next().unwrap_or("");
+                            out.push_str(&alignment.apply(&text, visible_width(line)));
+                        }
+                        None => out.push_str(&text),
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn expand(&self, field: Field, commit: &Commit, auto_color: bool) -> String {
+        let abbrev = |sha: &Sha| sha.to_string()[..7].to_string();
+        let parents = |show: &dyn Fn(&Sha) -> String| match commit.parent() {
+            Some(parents) => parents.iter().map(show).collect::<Vec<_>>().join(" "),
+            None => String::new(),
+        };
+        let email = |email: &str| {
+            email
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        };
+        match field {
+            Field::Hash => paint(auto_color, HASH_COLOR, &commit.sha().to_string()),
+            Field::AbbrevHash => paint(auto_color, HASH_COLOR, &abbrev(commit.sha())),
+            Field::Tree => commit.tree_sha().to_string(),
+            Field::AbbrevTree => abbrev(commit.tree_sha()),
+            Field::Parents => parents(&|sha| sha.to_string()),
+            Field::AbbrevParents => parents(&abbrev),
+            Field::AuthorName => commit.author().name().clone(),
+            Field::AuthorEmail => email(commit.author().email()),
+            Field::AuthorDate => commit.author().to_string_date(),
+            Field::CommitterName => commit.committer().name().clone(),
+            Field::CommitterEmail => email(commit.committer().email()),
+            Field::CommitterDate => commit.committer().to_string_date(),
+            Field::Subject => commit.subject(),
+            Field::Body => commit.body(),
+            Field::RawBody => commit.full_message(),
+            Field::Decorations => self.decorate(commit.sha(), auto_color),
+        }
+    }
+
+    /// The refs pointing at the commit, like ` (HEAD -> main, tag: v1.0)`
+    fn decorate(&self, sha: &Sha, auto_color: bool) -> String {
+        let decorations = match self.decorations.get(sha) {
+            Some(decorations) => decorations,
+            None => return String::new(),
+        };
+        let names: Vec<String> = decorations
+            .iter()
+            .map(|pieces| {
+                pieces
+                    .iter()
+                    .map(|(color, text)| paint(auto_color, color, text))
+                    .collect()
+            })
+            .collect();
+        format!(
+            "{}{}{}",
+            paint(auto_color, DECORATION_COLOR, " ("),
+            names.join(&paint(auto_color, DECORATION_COLOR, ", ")),
+            paint(auto_color, DECORATION_COLOR, ")")
+        )
+    }
+}
+
+impl Printer for FormatPrinter {
+    fn format_commit(&self, commit: &Commit) -> String {
+        self.format(commit)
+    }
+
+    fn terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+fn paint(enabled: bool, color: &str, text: &str) -> String {
+    match enabled {
+        true => format!("{}{}{}", color, text, RESET),
+        false => text.to_string(),
+    }
+}
+
+/// Whether colours are shown: as set by color.ui in the config, which also takes a boolean
+/// to turn them off, otherwise only when the output goes straight to a terminal
+fn color_enabled() -> bool {
+    let setting = get_config_value("color.ui").map(|value| value.to_lowercase());
+    match setting.as_deref() {
+        Some("always") => true,
+        Some("never") => false,
+        Some(value) if parse_bool(Some(value)) == Some(false) => false,
+        _ => io::stdout().is_terminal(),
+    }
+}
+
+/// The refs pointing at each commit, in the order git shows them: HEAD first, followed by
+/// the others in reverse order of their names
+fn load_decorations() -> Result<HashMap<Sha, Vec<Decoration>>, io::Error> {
+    let current = get_current_branch()?;
+    let mut refs = vec![];
+    for dir in ["refs/heads", "refs/remotes", "refs/tags"].iter() {
+        refs.extend(RefFile::list(dir)?);
+    }
+    refs.sort_by(|a, b| b.name().cmp(a.name()));
+
+    let mut decorations: HashMap<Sha, Vec<Decoration>> = HashMap::new();
+    let mut head = vec![(HEAD_COLOR, String::from("HEAD"))];
+    for reff in refs.iter() {
+        let name = reff.name();
+        let piece = match name.strip_prefix("refs/remotes/") {
+            Some(remote) => (REMOTE_COLOR, remote.to_string()),
+            None if name.starts_with("refs/tags/") => {
+                (TAG_COLOR, format!("tag: {}", reff.short_name()))
+            }
+            None => (BRANCH_COLOR, reff.short_name().to_string()),
+        };
+        // the branch HEAD is on is shown along with it
+        if name == &current {
+            head = vec![(HEAD_COLOR, String::from("HEAD -> ")), piece];
+            continue;
+        }
+        let sha = peel_tags(reff.sha())?;
+        decorations.entry(sha).or_default().push(vec![piece]);
+    }
+    if let Some(sha) = get_head_sha()? {
+        decorations.entry(sha).or_default().insert(0, head);
+    }
+    Ok(decorations)
+}
+
+fn parse_template(template: &str) -> Vec<Part> {
+    let mut parts = vec![];
+    let mut literal = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('%') {
+        literal.push_str(&rest[..start]);
+        let spec = &rest[start + 1..];
+        match parse_placeholder(spec) {
+            Some((Part::Literal(text), used)) => {
+                literal.push_str(&text);
+                rest = &spec[used..];
+            }
+            Some((part, used)) => {
+                if !literal.is_empty() {
+                    parts.push(Part::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(part);
+                rest = &spec[used..];
+            }
+            // anything that isn't a placeholder is shown as it is
+            None => {
+                literal.push('%');
+                rest = spec;
+            }
+        }
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        parts.push(Part::Literal(literal));
+    }
+    parts
+}
+
+/// The placeholder at the start of the text following a `%`, and how much of it was used
+fn parse_placeholder(spec: &str) -> Option<(Part, usize)> {
+    let field = match spec.get(..2) {
+        Some("an") => Some(Field::AuthorName),
+        Some("ae") => Some(Field::AuthorEmail),
+        Some("ad") => Some(Field::AuthorDate),
+        Some("cn") => Some(Field::CommitterName),
+        Some("ce") => Some(Field::CommitterEmail),
+        Some("cd") => Some(Field::CommitterDate),
+        _ => None,
+    };
+    if let Some(field) = field {
+        return Some((Part::Field(field), 2));
+    }
+    let field = match spec.chars().next()? {
+        'H' => Field::Hash,
+        'h' => Field::AbbrevHash,
+        'T' => Field::Tree,
+        't' => Field::AbbrevTree,
+        'P' => Field::Parents,
+        'p' => Field::AbbrevParents,
+        's' => Field::Subject,
+        'b' => Field::Body,
+        'B' => Field::RawBody,
+        'd' => Field::Decorations,
+        'n' => return Some((Part::Literal(String::from("\n")), 1)),
+        '%' => return Some((Part::Literal(String::from("%")), 1)),
+        'C' => return parse_color(&spec[1..]).map(|(part, used)| (part, used + 1)),
+        '<' | '>' => return parse_alignment(spec),
+        _ => return None,
+    };
+    Some((Part::Field(field), 1))
+}
+
+/// `%Cred`, `%Cgreen`, `%Cblue`, `%Creset`, or a full spec like `%C(bold yellow)`
+fn parse_color(spec: &str) -> Option<(Part, usize)> {
+    let named = [
+        ("red", "\x1b[31m"),
+        ("green", "\x1b[32m"),
+        ("blue", "\x1b[34m"),
+        ("reset", RESET),
+    ];
+    for (name, code) in named.iter() {
+        if spec.starts_with(name) {
+            let part = Part::Color {
+                code: code.to_string(),
+                always: false,
+            };
+            return Some((part, name.len()));
+        }
+    }
+    let inner = spec.strip_prefix('(')?;
+    let end = inner.find(')')?;
+    let inner = &inner[..end];
+    if inner == "auto" {
+        return Some((Part::AutoColor, end + 2));
+    }
+    let (always, inner) = match inner.strip_prefix("always,") {
+        Some(inner) => (true, inner),
+        None => (false, inner.strip_prefix("auto,").unwrap_or(inner)),
+    };
+    let code = parse_color_spec(inner)?;
+    Some((Part::Color { code, always }, end + 2))
+}
+
+/// Turns a spec of attributes and up to two colours, foreground then background, into an
+/// escape sequence
+fn parse_color_spec(spec: &str) -> Option<String> {
+    let mut attributes = vec![];
+    let mut colors: Vec<Option<String>> = vec![];
+    for word in spec.split_whitespace() {
+        if word == "reset" {
+            return Some(RESET.to_string());
+        }
+        if let Some(attribute) = attribute_code(word) {
+            attributes.push(attribute.to_string());
+            continue;
+        }
+        if colors.len() == 2 {
+            return None;
+        }
+        colors.push(color_code(word, colors.len() == 1)?);
+    }
+    let codes: Vec<String> = attributes
+        .into_iter()
+        .chain(colors.into_iter().flatten())
+        .collect();
+    match codes.is_empty() {
+        true => Some(String::new()),
+        false => Some(format!("\x1b[{}m", codes.join(";"))),
+    }
+}
+
+fn attribute_code(word: &str) -> Option<&'static str> {
+    let code = match word.replace("no-", "no").as_str() {
+        "bold" => "1",
+        "dim" => "2",
+        "italic" => "3",
+        "ul" => "4",
+        "blink" => "5",
+        "reverse" => "7",
+        "strike" => "9",
+        "nobold" | "nodim" => "22",
+        "noitalic" => "23",
+        "noul" => "24",
+        "noblink" => "25",
+        "noreverse" => "27",
+        "nostrike" => "29",
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// The code for a colour, None inside for `normal` which leaves the colour as it is
+fn color_code(word: &str, background: bool) -> Option<Option<String>> {
+    let names = [
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    ];
+    let base = match background {
+        true => 40,
+        false => 30,
+    };
+    if word == "normal" {
+        return Some(None);
+    }
+    if word == "default" {
+        return Some(Some((base + 9).to_string()));
+    }
+    if let Some(index) = names.iter().position(|name| *name == word) {
+        return Some(Some((base + index).to_string()));
+    }
+    if let Some(bright) = word.strip_prefix("bright") {
+        let index = names.iter().position(|name| *name == bright)?;
+        return Some(Some((base + 60 + index).to_string()));
+    }
+    let extended = base + 8;
+    if let Some(hex) = word.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let rgb = hex::decode(hex).ok()?;
+        return Some(Some(format!(
+            "{};2;{};{};{}",
+            extended, rgb[0], rgb[1], rgb[2]
+        )));
+    }
+    let number: u8 = word.parse().ok()?;
+    Some(Some(format!("{};5;{}", extended, number)))
+}
+
+/// `%<(N)`, `%>(N)` and `%><(N)` with an optional `|` to pad up to a column instead, and an
+/// optional `,trunc`, `,ltrunc` or `,mtrunc` to cut fields that don't fit
+fn parse_alignment(spec: &str) -> Option<(Part, usize)> {
+    let (side, rest) = if let Some(rest) = spec.strip_prefix("><") {
+        (Side::Center, rest)
+    } else if let Some(rest) = spec.strip_prefix(">>") {
+        (Side::Right, rest)
+    } else if let Some(rest) = spec.strip_prefix('>') {
+        (Side::Right, rest)
+    } else {
+        (Side::Left, spec.strip_prefix('<')?)
+    };
+    let (to_column, rest) = match rest.strip_prefix('|') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let args = rest.strip_prefix('(')?;
+    let end = args.find(')')?;
+    let (width, truncate) = match args[..end].split_once(',') {
+        Some((width, "trunc")) => (width, Truncate::End),
+        Some((width, "ltrunc")) => (width, Truncate::Start),
+        Some((width, "mtrunc")) => (width, Truncate::Middle),
+        Some(_) => return None,
+        None => (&args[..end], Truncate::Nothing),
+    };
+    let alignment = Alignment {
+        side,
+        width: width.trim().parse().ok()?,
+        to_column,
+        truncate,
+    };
+    let used = spec.len() - args.len() + end + 1;
+    Some((Part::Align(alignment), used))
+}
+
+/// How many columns the text takes up, not counting colour escape sequences
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for ch in text.chars() {
+        match (in_escape, ch) {
+            (false, '\x1b') => in_escape = true,
+            (true, 'm') => in_escape = false,
+            (true, _) => {}
+            (false, _) => width += 1,
+        }
+    }
+    width
+}
+
+fn truncate(text: &str, width: usize, truncate: Truncate) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let kept = width.saturating_sub(2);
+    let start: String = chars[..kept.min(chars.len())].iter().collect();
+    let end = |count: usize| -> String { chars[chars.len() - count..].iter().collect() };
+    match truncate {
+        Truncate::Nothing => text.to_string(),
+        Truncate::End => format!("{}..", start),
+        Truncate::Start => format!("..{}", end(kept)),
+        Truncate::Middle => {
+            let left: String = chars[..kept / 2].iter().collect();
+            format!("{}..{}", left, end(kept - kept / 2))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_placeholders() {
+        let parts = parse_template("%h %an%n%%%x %C(bold red)%s");
+        assert_eq!(
+            parts,
+            vec![
+                Part::Field(Field::AbbrevHash),
+                Part::Literal(String::from(" ")),
+                Part::Field(Field::AuthorName),
+                Part::Literal(String::from("\n%%x ")),
+                Part::Color {
+                    code: String::from("\x1b[1;31m"),
+                    always: false
+                },
+                Part::Field(Field::Subject),
+            ]
+        );
+    }
+
+    #[test]
+    fn color_specs() {
+        assert_eq!(
+            parse_color_spec("yellow bold"),
+            Some(String::from("\x1b[1;33m"))
+        );
+        assert_eq!(
+            parse_color_spec("normal brightblue"),
+            Some(String::from("\x1b[104m"))
+        );
+        assert_eq!(
+            parse_color_spec("208 #ff0080"),
+            Some(String::from("\x1b[38;5;208;48;2;255;0;128m"))
+        );
+        assert_eq!(parse_color_spec("red green blue"), None);
+        assert_eq!(
+            parse_color("(always,ul)x"),
+            Some((
+                Part::Color {
+                    code: String::from("\x1b[4m"),
+                    always: true
+                },
+                11
+            ))
+        );
+    }
+
+    #[test]
+    fn alignment() {
+        let align = |spec: &str, text: &str| match parse_alignment(spec) {
+            Some((Part::Align(alignment), _)) => alignment.apply(text, 0),
+            _ => panic!("not an alignment: {}", spec),
+        };
+        assert_eq!(align("<(6)", "abc"), "abc   ");
+        assert_eq!(align(">(6)", "abc"), "   abc");
+        assert_eq!(align("><(6)", "abc"), " abc  ");
+        assert_eq!(align("<(6)", "abcdefgh"), "abcdefgh");
+        assert_eq!(align("<(6,trunc)", "abcdefgh"), "abcd..");
+        assert_eq!(align("<(6,ltrunc)", "abcdefgh"), "..efgh");
+        assert_eq!(align("<(7,mtrunc)", "abcdefgh"), "ab..fgh");
+        assert_eq!(parse_alignment("<|(12)").map(|(_, used)| used), Some(6));
+    }
+}
@@ -1,15 +1,15 @@
 use std::{
-    fs::File,
-    io::{self, BufRead, BufReader, Write},
+    fs::{self, File},
+    io::{self, Write},
     path::Path,
 };
 
 use derive_getters::Getters;
 use log::*;
 
-use crate::exit_with_message;
+use crate::{exit_with_message, utils::read_ref};
 
-use super::{check_file_is_of_kind, load_commit_from_sha, Printer, Sha};
+use super::{check_file_is_of_kind, Sha};
 
 #[derive(Clone, Debug, Getters)]
 pub struct RefFile {
@@ -20,7 +20,8 @@ pub struct RefFile {
 impl RefFile {
     pub fn new(reff: &String, new_value: &Sha) -> Self {
         info!("New ref file from sha: {}", new_value);
-        if !check_file_is_of_kind(new_value, "commit") {
+        // only branches have to point at commits, tags can name any object
+        if reff.starts_with("refs/heads/") && !check_file_is_of_kind(new_value, "commit") {
             let message = format!("fatal: update_ref failed for ref '{}': cannot update ref '{}': trying to write non-commit object {} to branch '{}'", reff, reff, new_value, reff);
             exit_with_message(&message);
         }
@@ -30,31 +31,130 @@ impl RefFile {
         }
     }
 
+    /// Reads a ref like `refs/heads/main`, following it when it is symbolic. Fails when it
+    /// doesn't resolve to a commit's sha
     pub fn new_from_branch(branch: &String) -> Result<Self, io::Error> {
         let branch = branch.trim();
         info!("New ref file from branch: {}", branch);
-        let path = Path::join(Path::new(".re_flogged"), Path::new(branch));
-        let file = File::open(&path)?;
-        let mut reader = BufReader::new(file);
-        let mut buf = String::new();
-        reader.read_line(&mut buf)?;
-        let sha = buf.trim().parse().unwrap();
-        Ok(Self {
-            name: branch.to_owned(),
-            sha,
-        })
+        match read_ref(branch)? {
+            Some(sha) => Ok(Self {
+                name: branch.to_owned(),
+                sha,
+            }),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("broken ref {}", branch),
+            )),
+        }
+    }
+
+    /// Every ref stored under a directory of .re_flogged like `refs/heads`, sorted by name
+    pub fn list(dir: &str) -> Result<Vec<Self>, io::Error> {
+        let mut refs = vec![];
+        let root = Path::new(".re_flogged").join(dir);
+        if root.is_dir() {
+            RefFile::collect_refs(&root, dir, &mut refs)?;
+        }
+        refs.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(refs)
+    }
+
+    fn collect_refs(path: &Path, name: &str, refs: &mut Vec<Self>) -> Result<(), io::Error> {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let child_name = format!("{}/{}", name, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                RefFile::collect_refs(&entry.path(), &child_name, refs)?;
+            } else {
+                // like git, a ref that doesn't lead to an object is passed over
+                match RefFile::new_from_branch(&child_name) {
+                    Ok(reff) => refs.push(reff),
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                        eprintln!("warning: ignoring broken ref {}", child_name)
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The ref's name with its `refs/heads/` style prefix taken off
+    pub fn short_name(&self) -> &str {
+        let prefixes = ["refs/heads/", "refs/tags/", "refs/"];
+        prefixes
+            .iter()
+            .find_map(|prefix| self.name.strip_prefix(prefix))
+            .unwrap_or(&self.name)
+    }
+
+    /// Removes the ref, along with any directories of a nested name it leaves empty
+    pub fn delete(&self) -> Result<(), io::Error> {
+        let refs_root = Path::new(".re_flogged/refs");
+        let path = Path::new(".re_flogged").join(self.name.trim());
+        debug!("Deleting ref: {}", path.display());
+        fs::remove_file(&path)?;
+        for ancestor in path.ancestors().skip(1) {
+            // refs/heads and refs/tags themselves always stay
+            if ancestor.parent() == Some(refs_root) || fs::remove_dir(ancestor).is_err() {
+                break;
+            }
+        }
+        Ok(())
     }
 
     pub fn write(&self) -> Result<(), io::Error> {
         let path = Path::join(Path::new(".re_flogged"), Path::new(&self.name.trim()));
         debug!("Writing new ref to: {}", path.display());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
         let mut file = File::create(path)?;
         file.write(self.sha.to_string().as_bytes())?;
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{revision::try_resolve_revision, test_repo::TestRepo};
+
+    #[test]
+    fn symbolic_and_broken_refs_are_listed_safely() {
+        let repo = TestRepo::new("ref_file_symbolic");
+        let first = repo.commit("first", &[], &[]);
+        repo.set_head(&first);
+        fs::create_dir_all(".re_flogged/refs/remotes/origin").unwrap();
+        fs::write(
+            ".re_flogged/refs/remotes/origin/master",
+            format!("{}\n", first),
+        )
+        .unwrap();
+        fs::write(
+            ".re_flogged/refs/remotes/origin/HEAD",
+            "ref: refs/remotes/origin/master\n",
+        )
+        .unwrap();
+        fs::write(".re_flogged/refs/heads/broken", "not a sha\n").unwrap();
+        fs::write(".re_flogged/refs/heads/loop", "ref: refs/heads/loop\n").unwrap();
 
-    pub fn pretty_print(&self, printer: &dyn Printer) {
-        let head = load_commit_from_sha(&self.sha).unwrap();
-        head.print_recursive(printer);
+        let refs = RefFile::list("refs").unwrap();
+        let names: Vec<&str> = refs.iter().map(|reff| reff.name().as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "refs/heads/main",
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/master"
+            ]
+        );
+        assert!(refs.iter().all(|reff| reff.sha() == &first));
+        let error = RefFile::new_from_branch(&String::from("refs/heads/broken")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        // searching every ref goes through them all
+        assert_eq!(try_resolve_revision(":/first").unwrap(), Some(first));
     }
 }
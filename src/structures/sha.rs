@@ -1,16 +1,12 @@
 use std::{
-    fmt,
-    fs::read_dir,
+    fmt, io,
     path::{Path, PathBuf},
     str::FromStr,
-    string::ParseError,
 };
 
 use sha1::{Digest, Sha1};
 
-use crate::exit_with_message;
-
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Sha {
     bytes: [u8; 20],
 }
@@ -80,37 +76,33 @@ impl fmt::Debug for Sha {
 }
 
 impl FromStr for Sha {
-    type Err = ParseError;
+    type Err = io::Error;
 
+    /// Reads a full 40 character hex sha. Abbreviated ones have to be looked up among the
+    /// objects, which `try_resolve_revision` does
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() < 4 {
-            let msg = format!(
-                "fatal: ambiguous argument '{}': unknown revision or path not in the working tree.",
-                s
-            );
-            exit_with_message(&msg);
-        }
-        let mut full_str = s.to_string();
-        if s.len() < 20 {
-            let mut string = s.to_string();
-            let first_two_chars: String = string.drain(..2).collect();
-            let path_string = format!(".re_flogged/objects/{}", first_two_chars);
-            let path = Path::new(&path_string);
-            if path.exists() {
-                for dir_entry in read_dir(path) {
-                    for entry in dir_entry {
-                        let entry = entry.unwrap();
-                        let file_name = entry.file_name().into_string().unwrap();
-                        if file_name.contains(&string) {
-                            let full = format!("{}{}", first_two_chars, file_name);
-                            full_str = full;
-                        }
-                    }
-                }
-            }
+        let mut bytes = [0; 20];
+        match s.len() == 40 && hex::decode_to_slice(s, &mut bytes).is_ok() {
+            true => Ok(Self { bytes }),
+            false => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not a full sha: {}", s),
+            )),
         }
-        let mut out = [0; 20];
-        hex::decode_to_slice(full_str, &mut out).expect("Failed to decode hex");
-        Ok(Self { bytes: out })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_full_hex_shas_parse() {
+        let full = "6312c14f195ad8be7dfe2fd682c9b6b6bc71c9a3";
+        assert_eq!(full.parse::<Sha>().unwrap().to_string(), full);
+        assert!("6312c14f".parse::<Sha>().is_err());
+        assert!("6312c14f195ad8be7dfe2fd682c9b6b6bc71c9zz"
+            .parse::<Sha>()
+            .is_err());
     }
 }
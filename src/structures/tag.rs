@@ -0,0 +1,180 @@
+use std::{
+    fs::{create_dir_all, File},
+    io::{self, BufRead, Write},
+};
+
+use derive_getters::Getters;
+use log::{debug, info};
+
+use crate::structures::compress;
+
+use super::{Author, GitObject, Sha};
+
+#[derive(Clone, Debug, Getters)]
+pub struct Tag {
+    object: Sha,
+    object_type: String,
+    tag: String,
+    tagger: Author,
+    /// The message exactly as stored, usually UTF-8 but not necessarily
+    message: Vec<u8>,
+    sha: Sha,
+}
+
+impl Tag {
+    pub fn new_from_disk<R: BufRead>(
+        reader: &mut R,
+        count: usize,
+        sha: Sha,
+    ) -> Result<Self, io::Error> {
+        // object 406b00943149ced320d43c489e6bca6ef423f8b4
+        // type commit
+        // tag v1.0
+        // tagger Mark Chaitin <markchaitin@gmail.com> 1647742703 -0700
+        //
+        // the first release
+        info!("Reading tag from disk. Total size: {} bytes", count);
+        let mut buf = vec![0; count];
+        reader.read_exact(&mut buf)?;
+        let (headers, message) = match buf.windows(2).position(|pair| pair == b"\n\n") {
+            Some(end) => (&buf[..end], &buf[end + 2..]),
+            None => (&buf[..], &[][..]),
+        };
+        let corrupt = || {
+            let msg = format!("corrupt tag object {}", sha);
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        };
+        let mut tag = Self {
+            object: Sha::empty(),
+            object_type: String::new(),
+            tag: String::new(),
+            tagger: Author::empty(),
+            message: message.to_vec(),
+            sha: sha.clone(),
+        };
+        for line in headers.split(|&b| b == b'\n') {
+            let space = line.iter().position(|&b| b == b' ').unwrap_or(line.len());
+            let data = line.get(space + 1..).unwrap_or_default();
+            let text = String::from_utf8_lossy(data).to_string();
+            match &line[..space] {
+                b"object" => tag.object = text.parse().map_err(|_| corrupt())?,
+                b"type" => tag.object_type = text,
+                b"tag" => tag.tag = text,
+                b"tagger" => tag.tagger = Author::new_from_bytes(data).map_err(|_| corrupt())?,
+                name => debug!(
+                    "Skipping unknown tag header {}",
+                    String::from_utf8_lossy(name)
+                ),
+            }
+        }
+        if tag.object.is_empty() {
+            return Err(corrupt());
+        }
+        Ok(tag)
+    }
+
+    /// New annotated tag pointing at an object of the given type
+    pub fn new(object: &Sha, object_type: &str, name: &str, message: &str) -> Self {
+        let mut message = message.to_string();
+        if !message.ends_with('\n') {
+            message.push('\n');
+        }
+        let mut tag = Self {
+            object: object.clone(),
+            object_type: object_type.to_string(),
+            tag: name.to_string(),
+            tagger: Author::current_committer(),
+            message: message.into_bytes(),
+            sha: Sha::empty(),
+        };
+        tag.sha = Sha::new_hash(tag.to_bytes());
+        tag
+    }
+
+    /// The tag as git stores it, without the header
+    fn body(&self) -> Vec<u8> {
+        let mut body = format!(
+            "object {}\ntype {}\ntag {}\ntagger ",
+            self.object, self.object_type, self.tag
+        )
+        .into_bytes();
+        body.extend(self.tagger.to_bytes());
+        body.extend_from_slice(b"\n\n");
+        body.extend_from_slice(&self.message);
+        body
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let body = self.body();
+        let mut bytes = Tag::create_heading(body.len());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    fn create_heading(size: usize) -> Vec<u8> {
+        let heading = format!("tag {}\0", size);
+        heading.chars().map(|ch| ch as u8).collect()
+    }
+}
+
+impl GitObject for Tag {
+    fn write_to_disk(&mut self) -> Result<(), io::Error> {
+        debug!("Write to disk called");
+        let compressed = compress(&*self.to_bytes());
+        let full_path = self.sha.to_path();
+        let dir_path = full_path.parent().unwrap();
+        debug!("write directory: {:?}", dir_path);
+        if !dir_path.exists() {
+            info!("Path doesn't exist, creating");
+            create_dir_all(dir_path)?;
+        }
+        debug!("Full write directory: {:?}", full_path);
+        let mut file = File::create(&full_path)?;
+        file.write_all(&compressed)?;
+        Ok(())
+    }
+
+    fn pretty_print(&self) {
+        // the message may not be UTF-8, so it is written out as it is
+        let _ = io::stdout().write_all(&self.body());
+    }
+
+    fn print_type(&self) {
+        println!("tag");
+    }
+
+    fn get_sha(&self) -> &Sha {
+        &self.sha
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn read(data: &[u8]) -> Result<Tag, io::Error> {
+        let sha = Sha::new_hash([format!("tag {}\0", data.len()).as_bytes(), data].concat());
+        Tag::new_from_disk(&mut Cursor::new(data), data.len(), sha)
+    }
+
+    #[test]
+    fn utf8_message_round_trips() {
+        let data = "object c49897f29f9819a0ab6850d7e22443508a1a29d5\ntype commit\ntag v1\n\
+tagger Jos\u{e9} <j@example.com> 1700000000 +0100\n\nh\u{e9}llo w\u{f6}rld\n";
+        let tag = read(data.as_bytes()).unwrap();
+        assert_eq!(tag.message(), "h\u{e9}llo w\u{f6}rld\n".as_bytes());
+        assert_eq!(tag.tagger().name(), "Jos\u{e9}");
+        assert_eq!(tag.body(), data.as_bytes());
+        assert_eq!(Sha::new_hash(tag.to_bytes()), *tag.sha());
+    }
+
+    #[test]
+    fn bad_object_header_is_an_error() {
+        let data = b"object not-a-sha\ntype commit\ntag v1\n\
+tagger T <t@example.com> 1700000000 +0000\n\nmsg\n";
+        assert!(read(data).is_err());
+        assert!(read(b"type commit\ntag v1\n\nmsg\n").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
+    collections::BTreeMap,
     fmt,
-    fs::{create_dir_all, metadata, File},
+    fs::{create_dir_all, File},
     io::{self, BufRead, Read, Seek, Write},
     path::{Ancestors, Path},
 };
@@ -14,7 +15,21 @@ use crate::{
     utils::iterable_to_string,
 };
 
-use super::{GitObject, IndexEntry, Sha};
+use super::{load_tree_from_sha, object_exists, GitObject, IndexEntry, Sha};
+
+/// A blob reachable from a tree, named by its full path from the root of that tree
+#[derive(Clone, Debug, Getters, PartialEq)]
+pub struct TreeEntry {
+    path: String,
+    mode: u32,
+    sha: Sha,
+}
+
+impl TreeEntry {
+    pub fn new(path: String, mode: u32, sha: Sha) -> Self {
+        Self { path, mode, sha }
+    }
+}
 
 #[derive(Clone, Debug, Getters, PartialEq)]
 pub struct TreeNode {
@@ -81,6 +96,40 @@ impl TreeNode {
         }
     }
 
+    /// The node's name without the null byte nodes built from the index carry
+    pub fn clean_name(&self) -> String {
+        self.name.trim_end_matches('\0').to_string()
+    }
+
+    /// The node's mode as a number, the stored string keeps the trailing space from the tree format
+    pub fn mode_bits(&self) -> u32 {
+        u32::from_str_radix(self.mode.trim(), 8).unwrap_or(0)
+    }
+
+    fn flatten_into(&self, prefix: &str, out: &mut Vec<TreeEntry>) -> Result<(), io::Error> {
+        for node in self.nodes.iter() {
+            let path = match prefix.is_empty() {
+                true => node.clean_name(),
+                false => format!("{}/{}", prefix, node.clean_name()),
+            };
+            match node.node_type() {
+                TreeNodeType::Blob => {
+                    out.push(TreeEntry::new(path, node.mode_bits(), node.sha.clone()))
+                }
+                TreeNodeType::Tree => {
+                    if node.nodes.is_empty() {
+                        // subtrees read from disk only know their sha
+                        let subtree = load_tree_from_sha(&node.sha)?;
+                        subtree.root.flatten_into(&path, out)?;
+                    } else {
+                        node.flatten_into(&path, out)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn read_mode<R: BufRead>(reader: &mut R) -> (String, TreeNodeType) {
         let mut buf = vec![];
         reader.read_until(' ' as u8, &mut buf).unwrap();
@@ -159,29 +208,26 @@ impl TreeNode {
             if ancestor.is_file() {
                 let path = ancestor.canonicalize().unwrap();
                 let blob = Blob::new_from_raw_file(&path);
-                let obj_path = blob.sha().to_path();
-                if let Err(e) = metadata(obj_path) {
-                    if let io::ErrorKind::NotFound = e.kind() {
-                        if !missing_ok {
-                            error!(
-                                "Failed to create tree with invalid object reference: {} {}",
-                                blob.sha(),
-                                ancestor.display()
-                            );
-                            let msg = format!(
-                                "error: invalid object {} {} for '{}'\nfatal: flog-write-tree: error building trees",
-                                blob.mode(),
-                                blob.sha(),
-                                ancestor.display()
-                            );
-                            exit_with_message(&msg);
-                        }
-                        warn!(
-                            "Creating tree with invalid object reference: {} {}",
+                if !object_exists(blob.sha()) {
+                    if !missing_ok {
+                        error!(
+                            "Failed to create tree with invalid object reference: {} {}",
                             blob.sha(),
                             ancestor.display()
                         );
+                        let msg = format!(
+                            "error: invalid object {} {} for '{}'\nfatal: flog-write-tree: error building trees",
+                            blob.mode(),
+                            blob.sha(),
+                            ancestor.display()
+                        );
+                        exit_with_message(&msg);
                     }
+                    warn!(
+                        "Creating tree with invalid object reference: {} {}",
+                        blob.sha(),
+                        ancestor.display()
+                    );
                 }
                 let mode = format!("{} ", blob.mode());
                 return Some(Self::new_from_data(
@@ -315,6 +361,81 @@ impl Tree {
         self.new_from_path_string(&name, missing_ok);
     }
 
+    /// Walks the whole tree, reading subtrees from the database as needed, and lists every blob in path order
+    pub fn flatten(&self) -> Result<Vec<TreeEntry>, io::Error> {
+        let mut entries = vec![];
+        self.root.flatten_into("", &mut entries)?;
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    /// The node at the path, reading subtrees from the database on the way down. An empty
+    /// path is the root of the tree
+    pub fn node_at(&self, path: &str) -> Result<Option<TreeNode>, io::Error> {
+        let mut node = self.root.clone();
+        for name in path
+            .split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+        {
+            if node.node_type == TreeNodeType::Tree && node.nodes.is_empty() {
+                // subtrees read from disk only know their sha
+                node = load_tree_from_sha(&node.sha)?.root;
+            }
+            match node.nodes.iter().find(|child| child.clean_name() == name) {
+                Some(child) => node = child.clone(),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(node))
+    }
+
+    /// Writes a tree object for every directory the blobs are in and returns the root tree's
+    /// sha. Nodes are sorted the way git sorts them, with directory names compared as if they
+    /// ended in a slash
+    pub fn write_from_entries(entries: &[TreeEntry]) -> Result<Sha, io::Error> {
+        let mut subtrees: BTreeMap<String, Vec<TreeEntry>> = BTreeMap::new();
+        let mut nodes: Vec<(String, u32, String, Sha)> = vec![];
+        for entry in entries.iter() {
+            match entry.path.split_once('/') {
+                Some((dir, rest)) => {
+                    subtrees
+                        .entry(dir.to_string())
+                        .or_default()
+                        .push(TreeEntry::new(
+                            rest.to_string(),
+                            entry.mode,
+                            entry.sha.clone(),
+                        ))
+                }
+                None => nodes.push((
+                    entry.path.clone(),
+                    entry.mode,
+                    entry.path.clone(),
+                    entry.sha.clone(),
+                )),
+            }
+        }
+        for (dir, dir_entries) in subtrees.iter() {
+            let sha = Tree::write_from_entries(dir_entries)?;
+            nodes.push((format!("{}/", dir), 0o40000, dir.clone(), sha));
+        }
+        nodes.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut data = vec![];
+        for (_, mode, name, sha) in nodes.iter() {
+            data.write_all(format!("{:o} {}\0", mode, name).as_bytes())?;
+            data.write_all(sha.buf())?;
+        }
+        let mut output = Tree::create_heading(data.len());
+        output.append(&mut data);
+        let sha = Sha::new_hash(&output);
+        let full_path = sha.to_path();
+        if !object_exists(&sha) {
+            create_dir_all(full_path.parent().unwrap())?;
+            File::create(&full_path)?.write_all(&compress(&*output))?;
+        }
+        Ok(sha)
+    }
+
     fn create_heading(size: usize) -> Vec<u8> {
         let heading = format!("tree {}\0", size);
         heading.chars().map(|ch| ch as u8).collect()
@@ -0,0 +1,186 @@
+use std::{
+    cell::{Cell, RefCell},
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::structures::{compress, kind_number, PackIndex, PackWriter, Sha, PACK_DIRECTORY};
+
+/// Repositories are found through the working directory, so tests using one take turns
+static CWD_LOCK: Mutex<()> = Mutex::new(());
+
+/// Keeps the working directory where it is for as long as the guard lives. Every test that
+/// reads or writes paths relative to it has to hold this, as a `TestRepo` moves it
+pub fn lock_cwd() -> MutexGuard<'static, ()> {
+    // a test that failed while holding the lock leaves nothing behind that matters
+    CWD_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// An empty repository in a temporary directory, made the working directory until it is
+/// dropped, for tests to build small histories in
+pub struct TestRepo {
+    dir: PathBuf,
+    previous: PathBuf,
+    /// Commits are dated a minute apart in the order they are made, unless given a date
+    clock: Cell<i64>,
+    /// Environment variables the test changed, with what they were before
+    saved_env: RefCell<Vec<(String, Option<OsString>)>>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl TestRepo {
+    pub fn new(name: &str) -> Self {
+        let lock = lock_cwd();
+        let dir = env::temp_dir().join(format!("re_flog_{}_{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for sub in ["objects", "refs/heads", "refs/tags"] {
+            fs::create_dir_all(dir.join(".re_flogged").join(sub)).unwrap();
+        }
+        fs::write(dir.join(".re_flogged/HEAD"), "ref: refs/heads/main\n").unwrap();
+        let previous = env::current_dir().unwrap();
+        env::set_current_dir(&dir).unwrap();
+        // packs found for another repository are cached per thread
+        PackIndex::reload();
+        let repo = Self {
+            dir,
+            previous,
+            clock: Cell::new(1_700_000_000),
+            saved_env: RefCell::new(vec![]),
+            _lock: lock,
+        };
+        // settings outside the repository would leak into the tests
+        repo.set_env("GIT_CONFIG_NOSYSTEM", Some("1"));
+        repo.set_env("GIT_CONFIG_GLOBAL", Some(".re_flogged/global_config"));
+        repo
+    }
+
+    /// Sets or, given None, removes an environment variable until the repository is dropped
+    pub fn set_env(&self, name: &str, value: Option<&str>) {
+        self.saved_env
+            .borrow_mut()
+            .push((name.to_string(), env::var_os(name)));
+        match value {
+            Some(value) => env::set_var(name, value),
+            None => env::remove_var(name),
+        }
+    }
+
+    /// Writes a file in the working tree, making the directories it is in
+    pub fn write_file(&self, path: &str, contents: &str) {
+        if let Some(parent) = Path::new(path).parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Runs flog in the repository, giving back its exit code and what it printed. Anything
+    /// it commits is by `A U Thor` and dated a minute after the last commit. The binary is the
+    /// one `cargo test` builds for the integration tests, next to the test executable
+    pub fn flog(&self, args: &[&str]) -> (i32, String) {
+        let date = format!("{} +0000", self.clock.get() + 60);
+        self.clock.set(self.clock.get() + 60);
+        let binary = env::current_exe()
+            .unwrap()
+            .parent()
+            .and_then(Path::parent)
+            .unwrap()
+            .join("re_flog");
+        let output = Command::new(binary)
+            .args(args)
+            .current_dir(&self.dir)
+            .env("GIT_AUTHOR_NAME", "A U Thor")
+            .env("GIT_AUTHOR_EMAIL", "author@example.com")
+            .env("GIT_AUTHOR_DATE", &date)
+            .env("GIT_COMMITTER_NAME", "A U Thor")
+            .env("GIT_COMMITTER_EMAIL", "author@example.com")
+            .env("GIT_COMMITTER_DATE", &date)
+            .output()
+            .unwrap();
+        let printed = String::from_utf8_lossy(&output.stdout).to_string();
+        (output.status.code().unwrap_or(-1), printed)
+    }
+
+    /// Points `main`, the branch HEAD is on, at the commit
+    pub fn set_head(&self, sha: &Sha) {
+        fs::write(".re_flogged/refs/heads/main", format!("{}\n", sha)).unwrap();
+    }
+
+    /// Stores an object of the kind, like `blob`, and gives back its sha
+    pub fn write_object(&self, kind: &str, data: &[u8]) -> Sha {
+        let mut object = format!("{} {}\0", kind, data.len()).into_bytes();
+        object.extend_from_slice(data);
+        let sha = Sha::new_hash(&object);
+        let path = sha.to_path();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, compress(&object[..])).unwrap();
+        sha
+    }
+
+    /// Stores an object in a pack of its own rather than loose, and gives back its sha
+    pub fn write_packed_object(&self, kind: &str, data: &[u8]) -> Sha {
+        let sha = Sha::new_hash([format!("{} {}\0", kind, data.len()).as_bytes(), data].concat());
+        let mut writer = PackWriter::new(Path::new(PACK_DIRECTORY), 1).unwrap();
+        writer
+            .write_object(&sha, kind_number(kind).unwrap(), data)
+            .unwrap();
+        writer.finish().unwrap();
+        PackIndex::reload();
+        sha
+    }
+
+    /// A commit of the files, each a name and its contents at the top of the tree, with the
+    /// message as its subject. Dated a minute after the commit made before it
+    pub fn commit(&self, message: &str, parents: &[&Sha], files: &[(&str, &str)]) -> Sha {
+        let date = self.clock.get() + 60;
+        self.commit_at(message, parents, files, date)
+    }
+
+    /// Like `commit`, with the author and committer dates given as a unix timestamp
+    pub fn commit_at(
+        &self,
+        message: &str,
+        parents: &[&Sha],
+        files: &[(&str, &str)],
+        date: i64,
+    ) -> Sha {
+        self.clock.set(self.clock.get().max(date));
+        let mut files = files.to_vec();
+        files.sort();
+        let mut tree = vec![];
+        for (name, contents) in files {
+            let blob = self.write_object("blob", contents.as_bytes());
+            tree.extend(format!("100644 {}\0", name).into_bytes());
+            tree.extend_from_slice(blob.buf());
+        }
+        let tree = self.write_object("tree", &tree);
+        let mut commit = format!("tree {}\n", tree);
+        for parent in parents {
+            commit.push_str(&format!("parent {}\n", parent));
+        }
+        let identity = format!("A U Thor <author@example.com> {} +0000", date);
+        commit.push_str(&format!(
+            "author {}\ncommitter {}\n\n{}\n",
+            identity, identity, message
+        ));
+        self.write_object("commit", commit.as_bytes())
+    }
+}
+
+impl Drop for TestRepo {
+    fn drop(&mut self) {
+        for (name, value) in self.saved_env.borrow_mut().drain(..).rev() {
+            match value {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+        let _ = env::set_current_dir(&self.previous);
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
@@ -1,11 +1,133 @@
 use std::{
-    fs::File,
-    io::{self, BufRead, BufReader},
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
 };
 
-use crate::exit_with_message;
+use crate::{
+    config::get_config_bool,
+    exit_with_message,
+    structures::{load_commit_from_sha, load_tree_from_sha, Author, Sha, TreeEntry},
+};
 
+/// The ref HEAD points at, like `refs/heads/main`. A detached HEAD holds the sha itself,
+/// so the ref is `HEAD`
 pub fn get_current_branch() -> Result<String, io::Error> {
+    let buf = read_head()?;
+    match buf.strip_prefix("ref:") {
+        Some(reff) => Ok(reff.trim().to_string()),
+        None => Ok(String::from("HEAD")),
+    }
+}
+
+/// The commit HEAD resolves to, None on an unborn branch with no commits yet
+pub fn get_head_sha() -> Result<Option<Sha>, io::Error> {
+    read_ref("HEAD")
+}
+
+/// Every blob in the commit's tree, keyed by path
+pub fn get_commit_tree_entries(sha: &Sha) -> Result<BTreeMap<String, TreeEntry>, io::Error> {
+    let commit = load_commit_from_sha(sha)?;
+    let entries = load_tree_from_sha(commit.tree_sha())?.flatten()?;
+    Ok(entries
+        .into_iter()
+        .map(|entry| (entry.path().clone(), entry))
+        .collect())
+}
+
+/// Every blob in the HEAD commit's tree, keyed by path. Empty on an unborn branch
+pub fn get_head_tree_entries() -> Result<BTreeMap<String, TreeEntry>, io::Error> {
+    match get_head_sha()? {
+        Some(head) => get_commit_tree_entries(&head),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// Points HEAD at a branch, given as its full ref like `refs/heads/main`
+pub fn set_head_to_branch(reff: &str) -> Result<(), io::Error> {
+    write_head(&format!("ref: {}\n", reff))
+}
+
+/// Detaches HEAD at a commit
+pub fn set_head_detached(sha: &Sha) -> Result<(), io::Error> {
+    write_head(&format!("{}\n", sha))
+}
+
+/// The sha a ref like `HEAD`, `MERGE_HEAD` or `refs/tags/v1` holds, following symbolic refs.
+/// None when there is no such ref, it is a branch with no commits yet, or it doesn't hold a
+/// sha. Like git, symbolic refs are only followed a few deep, so a loop of them ends
+pub fn read_ref(name: &str) -> Result<Option<Sha>, io::Error> {
+    let mut name = name.to_string();
+    for _ in 0..=MAX_SYMREF_DEPTH {
+        let path = Path::new(".re_flogged").join(&name);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(path)?;
+        if let Some(target) = contents.strip_prefix("ref:") {
+            name = target.trim().to_string();
+            continue;
+        }
+        // refs like FETCH_HEAD carry more after the sha
+        let sha = contents.split_whitespace().next().unwrap_or_default();
+        return Ok(sha.parse().ok());
+    }
+    Ok(None)
+}
+
+/// How many symbolic refs are followed from one to the next before giving up
+const MAX_SYMREF_DEPTH: usize = 5;
+
+const HEAD_LOG_PATH: &str = ".re_flogged/logs/HEAD";
+
+/// Records a move of HEAD in its log, the way git's reflog does. Turning off
+/// `core.logAllRefUpdates` stops the log being started
+pub fn append_head_log(old: &Sha, new: &Sha, message: &str) -> Result<(), io::Error> {
+    // a log that is already there is kept up to date whatever the setting says
+    if get_config_bool("core.logAllRefUpdates") == Some(false) && !Path::new(HEAD_LOG_PATH).exists()
+    {
+        return Ok(());
+    }
+    fs::create_dir_all(Path::new(HEAD_LOG_PATH).parent().unwrap())?;
+    let line = format!(
+        "{} {} {}\t{}\n",
+        old,
+        new,
+        // the log only records the move, so an unknown committer doesn't stop it
+        Author::from_environment("COMMITTER")
+            .unwrap_or_else(Author::anonymous)
+            .to_string_with_date(),
+        message
+    );
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(HEAD_LOG_PATH)?
+        .write_all(line.as_bytes())
+}
+
+/// The branch, or the sha of a detached HEAD, that the nth most recent checkout moved away
+/// from, as `@{-n}` names it
+pub fn previous_checkout(n: usize) -> Result<Option<String>, io::Error> {
+    let log = match (fs::read_to_string(HEAD_LOG_PATH), n) {
+        (Ok(log), 1..) => log,
+        _ => return Ok(None),
+    };
+    let previous = log
+        .lines()
+        .rev()
+        .filter_map(|line| {
+            line.split_once('\t')?
+                .1
+                .strip_prefix("checkout: moving from ")
+        })
+        .filter_map(|moved| Some(moved.split_once(" to ")?.0.to_string()))
+        .nth(n - 1);
+    Ok(previous)
+}
+
+fn read_head() -> Result<String, io::Error> {
     let file = match File::open(".re_flogged/HEAD") {
         Ok(f) => f,
         Err(_err) => {
@@ -17,16 +139,71 @@ pub fn get_current_branch() -> Result<String, io::Error> {
     let mut reader = BufReader::new(file);
     let mut buf = String::new();
     reader.read_line(&mut buf)?;
-    let last_bit = {
-        let this = buf.rsplit(" ").next();
-        match this {
-            Some(val) => val,
-            None => {
-                exit_with_message(
-                    "fatal: not a flog repository (or any of the parent directories): .re_flog",
-                );
-            }
-        }
+    Ok(buf.trim().to_string())
+}
+
+fn write_head(content: &str) -> Result<(), io::Error> {
+    let mut file = File::create(".re_flogged/HEAD")?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+const MERGE_HEAD_PATH: &str = ".re_flogged/MERGE_HEAD";
+const MERGE_MSG_PATH: &str = ".re_flogged/MERGE_MSG";
+
+/// The commit being merged in while a merge with conflicts waits to be committed
+pub fn get_merge_head() -> Result<Option<Sha>, io::Error> {
+    let file = match File::open(MERGE_HEAD_PATH) {
+        Ok(f) => f,
+        Err(_) => return Ok(None),
     };
-    Ok(last_bit.to_string())
+    let mut buf = String::new();
+    BufReader::new(file).read_line(&mut buf)?;
+    Ok(buf.trim().parse().ok())
+}
+
+/// The message prepared for the merge commit, without the comment lines
+pub fn get_merge_message() -> Option<String> {
+    let message = fs::read_to_string(MERGE_MSG_PATH).ok()?;
+    let lines: Vec<&str> = message
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect();
+    Some(lines.join("\n").trim().to_string())
+}
+
+/// Records an unfinished merge, so the next commit gets the merged commit as a second parent
+pub fn set_merge_state(sha: &Sha, message: &str) -> Result<(), io::Error> {
+    File::create(MERGE_HEAD_PATH)?.write_all(format!("{}\n", sha).as_bytes())?;
+    File::create(MERGE_MSG_PATH)?.write_all(message.as_bytes())?;
+    Ok(())
+}
+
+/// Forgets an unfinished merge once it has been committed
+pub fn clear_merge_state() -> Result<(), io::Error> {
+    for path in [MERGE_HEAD_PATH, MERGE_MSG_PATH].iter() {
+        if Path::new(path).exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_repo::TestRepo;
+
+    #[test]
+    fn head_that_is_not_a_sha_reads_as_unborn() {
+        let repo = TestRepo::new("database_head");
+        assert_eq!(get_head_sha().unwrap(), None);
+        let first = repo.commit("first", &[], &[]);
+        repo.set_head(&first);
+        assert_eq!(get_head_sha().unwrap(), Some(first.clone()));
+        fs::write(".re_flogged/refs/heads/main", &first.to_string()[..20]).unwrap();
+        assert_eq!(get_head_sha().unwrap(), None);
+        set_head_detached(&first).unwrap();
+        assert_eq!(get_head_sha().unwrap(), Some(first));
+    }
 }
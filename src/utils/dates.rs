@@ -0,0 +1,128 @@
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone,
+};
+
+/// Reads a date the way `--since` and `--until` take them: a unix timestamp like `@1650000000`,
+/// `now`, `yesterday`, an amount of time ago like `2 weeks ago`, RFC 2822, or an ISO 8601 date
+/// with an optional time and offset. Dates without an offset are local time
+pub fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    parse_date_from(date.trim(), Local::now())
+}
+
+/// Reads a date the way `GIT_AUTHOR_DATE` and `GIT_COMMITTER_DATE` take them: git's own
+/// `1650000000 +0200`, optionally with an `@` before the timestamp, or any date `parse_date`
+/// understands
+pub fn parse_identity_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    let date = date.trim();
+    if let Some((seconds, offset)) = date.trim_start_matches('@').split_once(' ') {
+        if let Ok(date) = DateTime::parse_from_str(&format!("{} {}", seconds, offset), "%s %z") {
+            return Some(date);
+        }
+    }
+    parse_date(date)
+}
+
+fn parse_date_from(date: &str, now: DateTime<Local>) -> Option<DateTime<FixedOffset>> {
+    let local = |date: DateTime<Local>| date.with_timezone(date.offset());
+    match date {
+        "now" => return Some(local(now)),
+        "yesterday" => return Some(local(now - Duration::days(1))),
+        _ => (),
+    }
+    if let Some(seconds) = date.strip_prefix('@') {
+        let date = Local.timestamp_opt(seconds.parse().ok()?, 0).single()?;
+        return Some(local(date));
+    }
+    if let Some(ago) = date.strip_suffix("ago") {
+        return parse_ago(ago, now).map(local);
+    }
+    if let Ok(date) = DateTime::parse_from_rfc2822(date) {
+        return Some(date);
+    }
+    let with_offset = [
+        "%Y-%m-%dT%H:%M:%S%z",
+        "%Y-%m-%d %H:%M:%S %z",
+        "%Y-%m-%d %H:%M:%S%:z",
+    ];
+    if let Some(date) = with_offset
+        .iter()
+        .find_map(|format| DateTime::parse_from_str(date, format).ok())
+    {
+        return Some(date);
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date);
+    }
+    let without_offset = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"];
+    let naive = without_offset
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+        .or_else(|| {
+            let day = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+            Some(day.and_hms(0, 0, 0))
+        })?;
+    Local.from_local_datetime(&naive).earliest().map(local)
+}
+
+/// Goes back from now by an amount like `3 days` or `1.month`
+fn parse_ago(ago: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let words: Vec<&str> = ago
+        .split(|c: char| c.is_whitespace() || c == '.')
+        .filter(|word| !word.is_empty())
+        .collect();
+    let (count, unit) = match words[..] {
+        [count, unit] => (count.parse::<i64>().ok()?, unit),
+        [unit] => (1, unit),
+        _ => return None,
+    };
+    let duration = match unit.trim_end_matches('s') {
+        "second" | "sec" => Duration::seconds(count),
+        "minute" | "min" => Duration::minutes(count),
+        "hour" => Duration::hours(count),
+        "day" => Duration::days(count),
+        "week" => Duration::weeks(count),
+        "month" => return months_before(now, count),
+        "year" => return months_before(now, count * 12),
+        _ => return None,
+    };
+    Some(now - duration)
+}
+
+/// The same day of the month some months earlier, or the last day of that month if it is
+/// shorter
+fn months_before(now: DateTime<Local>, months: i64) -> Option<DateTime<Local>> {
+    let total = now.year() as i64 * 12 + now.month0() as i64 - months;
+    let (year, month) = (total.div_euclid(12) as i32, total.rem_euclid(12) as u32 + 1);
+    let day = (1..=now.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))?;
+    let naive = day.and_time(now.time());
+    Local.from_local_datetime(&naive).earliest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_and_absolute_dates() {
+        let now = Local.ymd(2022, 3, 31).and_hms(12, 0, 0);
+        let parse = |date| parse_date_from(date, now).unwrap().timestamp();
+        assert_eq!(parse("now"), now.timestamp());
+        assert_eq!(parse("2 days ago"), now.timestamp() - 2 * 86400);
+        assert_eq!(parse("1.week.ago"), now.timestamp() - 7 * 86400);
+        assert_eq!(
+            parse("1 month ago"),
+            Local.ymd(2022, 2, 28).and_hms(12, 0, 0).timestamp()
+        );
+        assert_eq!(parse("@1650000000"), 1650000000);
+        assert_eq!(parse("2022-04-15T05:20:00+0000"), 1650000000);
+        assert_eq!(
+            parse("2022-01-02"),
+            Local.ymd(2022, 1, 2).and_hms(0, 0, 0).timestamp()
+        );
+        assert!(parse_date_from("whenever", now).is_none());
+        let identity = parse_identity_date("@1650000000 +0530").unwrap();
+        assert_eq!(identity.to_rfc3339(), "2022-04-15T10:50:00+05:30");
+    }
+}
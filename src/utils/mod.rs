@@ -1,5 +1,7 @@
 mod database;
+mod dates;
 mod strings;
 
 pub use database::*;
+pub use dates::*;
 pub use strings::*;